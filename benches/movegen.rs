@@ -0,0 +1,84 @@
+//! 着法生成的速度测试, 不依赖额外的测试框架.
+//! 只测当前的实现; 优化前后的对比是在改动前后的提交上分别运行得到的, 见OPTIMIZATION_SUMMARY.md
+//!
+//! ```bash
+//! cargo bench --bench movegen
+//! ```
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use aichess::cchess::CChess;
+use aichess::pos::fen::Fen;
+use aichess::pos::movelist::MoveList;
+use aichess::pos::position::Position;
+use aichess::synthesis::game::Game;
+
+/// 开局、中局、残局各取几个局面
+const FENS: [&str; 5] = [
+    "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1",
+    "r1bakab1r/9/1cn4cn/p1p1p1p1p/9/2P6/P3P1P1P/1CN1C1N2/9/R1BAKAB1R w - - 4 4",
+    "2bak4/4a4/4n4/9/9/4pp3/4pp3/4p4/9/5K3 b - - 0 1",
+    "3k5/9/9/4c4/9/9/4C4/9/9/3AKA3 w - - 0 1",
+    "4k4/9/3n5/2P1N4/4p4/3N5/2n6/9/4A4/3AK4 w - - 0 1",
+];
+
+/// 被将军的局面: 车、马、炮将军, 以及炮隔着本方棋子将军
+const CHECK_FENS: [&str; 4] = [
+    "rnbakabnr/9/1c5c1/p1p3p1p/9/9/P1P3P1P/1C5C1/4R4/RNBAKABN1 b - - 0 1",
+    "r1bakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C1n3C1/9/RNBAKABNR w - - 0 1",
+    "rnbakabnr/9/7c1/p1p1p1p1p/9/4c4/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1",
+    "3k5/9/9/9/R3c4/9/9/4N4/9/3AK4 w - - 0 1",
+];
+
+/// 反复执行`f`直到超过`min_time`, 返回每秒处理的着法(或节点)数
+fn per_second(min_time: Duration, mut f: impl FnMut() -> usize) -> f64 {
+    let start = Instant::now();
+    let mut count = 0;
+    while start.elapsed() < min_time {
+        count += f();
+    }
+    count as f64 / start.elapsed().as_secs_f64()
+}
+
+fn report(name: &str, rate: f64) {
+    println!("{:<32} {:>8.2} M/s", name, rate / 1e6);
+}
+
+fn positions(fens: &[&str]) -> Vec<Position> {
+    fens.iter().map(|fen| Position::from_fen(&fen.parse::<Fen>().unwrap())).collect()
+}
+
+fn main() {
+    let min_time = Duration::from_secs(2);
+    let positions = positions(&FENS);
+    report(
+        "legal_moves",
+        per_second(min_time, || positions.iter().map(|p| black_box(p.legal_moves()).len()).sum()),
+    );
+
+    let games: Vec<CChess> = FENS.iter().map(|fen| CChess::from_fen(fen.parse().unwrap()).unwrap()).collect();
+    report(
+        "CChess::iter_actions",
+        per_second(min_time, || games.iter().map(|g| black_box(g.iter_actions()).count()).sum()),
+    );
+
+    let start = positions[0].clone();
+    report("perft(4) 初始局面 (节点)", per_second(min_time, || start.perft(4) as usize));
+
+    let checked = self::positions(&CHECK_FENS);
+    assert!(checked.iter().all(|p| p.is_in_check(p.side_to_move())));
+    report(
+        "gen_evasions (被将军的局面)",
+        per_second(min_time, || {
+            checked
+                .iter()
+                .map(|p| {
+                    let mut moves = MoveList::new();
+                    p.gen_evasions(&mut moves);
+                    black_box(moves).len()
+                })
+                .sum()
+        }),
+    );
+}
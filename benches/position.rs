@@ -0,0 +1,67 @@
+//! 建局面、复制对局和随机走子的速度测试, 不依赖额外的测试框架.
+//! 优化前后的对比是在改动前后的提交上分别运行得到的, 见OPTIMIZATION_SUMMARY.md
+//!
+//! ```bash
+//! cargo bench --bench position
+//! ```
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::prelude::*;
+
+use aichess::cchess::CChess;
+use aichess::pos::fen::Fen;
+use aichess::pos::position::Position;
+use aichess::synthesis::game::Game;
+
+/// 反复执行`f`直到超过`min_time`, 返回每次操作的平均耗时(µs); `f`返回这一轮做了几次操作
+fn micros_per_op(min_time: Duration, mut f: impl FnMut() -> usize) -> f64 {
+    let start = Instant::now();
+    let mut count = 0;
+    while start.elapsed() < min_time {
+        count += f();
+    }
+    start.elapsed().as_secs_f64() * 1e6 / count as f64
+}
+
+fn report(name: &str, micros: f64) {
+    println!("{:<40} {:>8.2} µs", name, micros);
+}
+
+fn main() {
+    let min_time = Duration::from_secs(2);
+    let fen = Fen::init();
+
+    report(
+        "Position::from_fen + legal_moves",
+        micros_per_op(min_time, || black_box(Position::from_fen(&fen)).legal_moves().len().min(1)),
+    );
+    let position = Position::from_fen(&fen);
+    report("legal_moves", micros_per_op(min_time, || black_box(position.legal_moves()).len().min(1)));
+
+    // MCTS扩展节点时复制对局再列出着法
+    let game = CChess::new();
+    report(
+        "复制CChess + iter_actions",
+        micros_per_op(min_time, || black_box(game.clone()).iter_actions().count().min(1)),
+    );
+
+    // 从初始局面随机走到对局结束, 按走的步数平均
+    let mut rng = StdRng::seed_from_u64(42);
+    report(
+        "随机走子自对弈 (每步)",
+        micros_per_op(min_time, || {
+            let mut game = CChess::new();
+            let mut steps = 0;
+            loop {
+                let actions: Vec<_> = game.iter_actions().collect();
+                let action = actions[rng.random_range(0..actions.len())];
+                steps += 1;
+                if game.step(&action) {
+                    return steps;
+                }
+            }
+        }),
+    );
+}
@@ -1,17 +1,24 @@
 use std::path::PathBuf;
 use std::io::{self, Write};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 
 use aichess::{
-    alpha_zero, BurnTrainer, CChess, PlayerId, LearningConfig, 
+    alpha_zero, BurnTrainer, CChess, GameResult, PlayerId, LearningConfig, 
     MCTSConfig, RolloutConfig, ActionSelection, Exploration, Fpu, PolicyNoise, 
     ValueTarget, Game, MCTS, PolicyWithCache, NetConfig, MAX_NUM_ACTIONS,
-    AlphaZeroTrainer, HasTurnOrder,
+    AlphaZeroTrainer, FeatureEncoder, checkpoint_encoder,
 };
-use aichess::pos::{moves::Move, position::Position};
-use aichess::synthesis::pgn::{PgnGame, load_pgn, append_game_to_pgn};
+use aichess::pos::fen::Fen;
+use aichess::pos::moves::{Move, Notation};
+use aichess::pos::position::Position;
+use aichess::pos::search::{search, SearchLimits};
+use aichess::pos::tablebase::{Material, Tablebase};
+use aichess::pos::tt::TranspositionTable;
+use aichess::synthesis::policies::PolicyWithTable;
+use aichess::synthesis::book::OpeningBook;
+use aichess::synthesis::pgn::{PgnGame, load_pgn, append_game_to_pgn, move_to_pgn};
 
 #[derive(Parser, Debug)]
 #[command(name = "aichess-cli")]
@@ -109,10 +116,31 @@ enum Commands {
         #[arg(long, default_value_t = 8)]
         eval_batch_size: usize,
 
+        /// 网络输入包括当前局面在内的局面数（1 表示只看当前局面）
+        #[arg(long, default_value_t = 1)]
+        history: usize,
+
+        /// 每个局面加两个重复次数平面
+        #[arg(long)]
+        repetition_planes: bool,
+
+        /// 加一个对局步数平面
+        #[arg(long)]
+        move_count_plane: bool,
+
+        /// 加一个无吃子步数平面
+        #[arg(long)]
+        halfmove_clock_plane: bool,
+
         /// 从已有模型 checkpoint 继续训练（指定 .ot 文件路径）。
-        /// 网络结构（--hidden-size / --num-blocks）必须与该 checkpoint 完全一致。
+        /// 网络结构（--hidden-size / --num-blocks）必须与该 checkpoint 完全一致，
+        /// 特征编码沿用 checkpoint 记录的设置。
         #[arg(long)]
         resume_from: Option<PathBuf>,
+
+        /// 残局库目录, 自对弈中能查到的残局直接按库判定
+        #[arg(long)]
+        tablebase: Option<PathBuf>,
     },
 
     /// 两个模型对弈
@@ -160,6 +188,18 @@ enum Commands {
         /// 是否打印棋盘
         #[arg(short, long)]
         verbose: bool,
+
+        /// 开局库文件, 开局阶段直接按库里的权重随机走
+        #[arg(long)]
+        book: Option<PathBuf>,
+
+        /// 使用开局库的最大步数(半回合), 默认沿用建库时的设置
+        #[arg(long)]
+        book_depth: Option<usize>,
+
+        /// 残局库目录, MCTS搜到库里的残局时直接用库的结果
+        #[arg(long)]
+        tablebase: Option<PathBuf>,
     },
 
     /// 人机对弈
@@ -200,6 +240,10 @@ enum Commands {
         #[arg(long, default_value_t = 8)]
         eval_batch_size: usize,
 
+        /// 网络评估缓存的大小(MB), 整局共用
+        #[arg(long, default_value_t = 256)]
+        cache_mb: usize,
+
         /// 是否打印棋盘
         #[arg(short, long)]
         verbose: bool,
@@ -207,6 +251,22 @@ enum Commands {
         /// 保存游戏到 PGN 文件
         #[arg(long)]
         save_pgn_file: Option<PathBuf>,
+
+        /// 走法的输入和显示格式 (iccs/wxf/chinese)
+        #[arg(long, default_value = "iccs")]
+        notation: Notation,
+
+        /// 开局库文件, 开局阶段直接按库里的权重随机走
+        #[arg(long)]
+        book: Option<PathBuf>,
+
+        /// 使用开局库的最大步数(半回合), 默认沿用建库时的设置
+        #[arg(long)]
+        book_depth: Option<usize>,
+
+        /// 残局库目录, MCTS搜到库里的残局时直接用库的结果
+        #[arg(long)]
+        tablebase: Option<PathBuf>,
     },
 
     /// 查看或转换 PGN 文件
@@ -218,6 +278,40 @@ enum Commands {
         /// 操作类型 (show/convert)
         #[arg(short, long, default_value = "show")]
         action: String,
+
+        /// 显示或转换成的走法格式 (iccs/wxf/chinese)
+        #[arg(long)]
+        notation: Option<Notation>,
+    },
+
+    /// 从 PGN 棋谱生成开局库
+    Book {
+        /// PGN 文件路径（逗号分隔）
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        pgn: Vec<PathBuf>,
+
+        /// 开局库输出路径
+        #[arg(short, long, default_value = "./book.bin")]
+        out: PathBuf,
+
+        /// 只收录前这么多步(半回合)
+        #[arg(long, default_value_t = 20)]
+        max_depth: usize,
+
+        /// 出现次数少于这个值的着法不收录
+        #[arg(long, default_value_t = 2)]
+        min_games: u32,
+    },
+
+    /// 用逆向分析生成残局库
+    Tablebase {
+        /// 子力组合（逗号分隔）, 红方在前, 如 KRKAABB,KCPKAA
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        materials: Vec<Material>,
+
+        /// 残局库输出目录, 吃子后的子力组合也一起生成
+        #[arg(short, long, default_value = "./tablebase")]
+        out: PathBuf,
     },
 
     /// 批量评测搜索参数（同一对模型）
@@ -266,6 +360,40 @@ enum Commands {
         #[arg(long, default_value = "./eval_results.csv")]
         out_csv: PathBuf,
     },
+
+    /// 统计走法生成的叶子节点数(perft), 用于校验走法生成器
+    Perft {
+        /// 起始局面的 FEN
+        #[arg(short, long, default_value = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1")]
+        fen: String,
+
+        /// 搜索深度
+        #[arg(short, long, default_value_t = 3)]
+        depth: u32,
+
+        /// 按第一步着法分别列出节点数
+        #[arg(long)]
+        divide: bool,
+    },
+
+    /// 用Alpha-Beta搜索分析局面, 不需要模型
+    Search {
+        /// 要分析的局面的 FEN
+        #[arg(short, long, default_value = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1")]
+        fen: String,
+
+        /// 最大搜索深度
+        #[arg(short, long, default_value_t = 6)]
+        depth: u32,
+
+        /// 最长搜索时间(毫秒)
+        #[arg(short, long)]
+        time: Option<u64>,
+
+        /// 最多搜索的节点数
+        #[arg(short, long)]
+        nodes: Option<u64>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -296,8 +424,20 @@ fn main() -> Result<()> {
             progressive_weight,
             progressive_visits,
             eval_batch_size,
+            history,
+            repetition_planes,
+            move_count_plane,
+            halfmove_clock_plane,
             resume_from,
+            tablebase,
         } => {
+            install_tablebase(tablebase)?;
+            let encoder = FeatureEncoder {
+                history,
+                repetitions: repetition_planes,
+                move_count: move_count_plane,
+                halfmove_clock: halfmove_clock_plane,
+            };
             train_model(
                 log_dir,
                 iterations,
@@ -320,6 +460,7 @@ fn main() -> Result<()> {
                 progressive_weight,
                 progressive_visits,
                 eval_batch_size,
+                encoder,
                 resume_from,
             )?;
         }
@@ -335,7 +476,11 @@ fn main() -> Result<()> {
             progressive_visits,
             eval_batch_size,
             verbose,
+            book,
+            book_depth,
+            tablebase,
         } => {
+            install_tablebase(tablebase)?;
             play_models(
                 model1,
                 model2,
@@ -348,6 +493,7 @@ fn main() -> Result<()> {
                 progressive_visits,
                 eval_batch_size,
                 verbose,
+                load_book(book, book_depth)?,
             )?;
         }
         Commands::Human {
@@ -360,9 +506,15 @@ fn main() -> Result<()> {
             progressive_weight,
             progressive_visits,
             eval_batch_size,
+            cache_mb,
             verbose,
             save_pgn_file,
+            notation,
+            book,
+            book_depth,
+            tablebase,
         } => {
+            install_tablebase(tablebase)?;
             play_human(
                 model,
                 color,
@@ -373,12 +525,21 @@ fn main() -> Result<()> {
                 progressive_weight,
                 progressive_visits,
                 eval_batch_size,
+                cache_mb,
                 verbose,
                 save_pgn_file,
+                notation,
+                load_book(book, book_depth)?,
             )?;
         }
-        Commands::Pgn { file, action } => {
-            handle_pgn(file, &action)?;
+        Commands::Pgn { file, action, notation } => {
+            handle_pgn(file, &action, notation)?;
+        }
+        Commands::Book { pgn, out, max_depth, min_games } => {
+            build_book(&pgn, &out, max_depth, min_games)?;
+        }
+        Commands::Tablebase { materials, out } => {
+            build_tablebase(&materials, &out)?;
         }
         Commands::Eval {
             model1,
@@ -407,6 +568,12 @@ fn main() -> Result<()> {
                 &out_csv,
             )?;
         }
+        Commands::Perft { fen, depth, divide } => {
+            run_perft(&fen, depth, divide)?;
+        }
+        Commands::Search { fen, depth, time, nodes } => {
+            run_search(&fen, depth, time, nodes)?;
+        }
     }
 
     Ok(())
@@ -502,8 +669,14 @@ fn train_model(
     progressive_weight: f32,
     progressive_visits: usize,
     eval_batch_size: usize,
+    encoder: FeatureEncoder,
     resume_from: Option<PathBuf>,
 ) -> Result<()> {
+    // 断点续训时特征编码要和 checkpoint 一致
+    let encoder = match resume_from {
+        Some(ref ckpt_path) => checkpoint_encoder(ckpt_path)?,
+        None => encoder,
+    };
     println!("🚀 开始训练模型...");
     println!("📁 日志目录: {:?}", log_dir);
     println!("🔄 迭代次数: {}", iterations);
@@ -518,6 +691,7 @@ fn train_model(
     println!("🎯 Progressive weight: {}", progressive_weight);
     println!("📏 Progressive visits: {}", progressive_visits);
     println!("🧩 Eval batch size: {}", eval_batch_size);
+    println!("🔢 特征编码: {:?} ({} 个输入平面)", encoder, encoder.planes());
     println!("⚠️  注意: 使用单线程模式 (workers=0)");
 
     // 强制使用单线程以避免 Sync 问题
@@ -545,7 +719,7 @@ fn train_model(
 
     let device = Default::default();
     let model_config = NetConfig::new(hidden_size, num_blocks);
-    let mut trainer = BurnTrainer::new(model_config, device);
+    let mut trainer = BurnTrainer::with_encoder(model_config, encoder, device);
 
     // 若指定了 --resume-from，则从已有 checkpoint 加载权重
     if let Some(ref ckpt_path) = resume_from {
@@ -608,6 +782,7 @@ fn play_models(
     progressive_visits: usize,
     eval_batch_size: usize,
     _verbose: bool,
+    book: Option<OpeningBook>,
 ) -> Result<()> {
     println!("🎮 开始模型对弈...");
     println!("🤖 模型1: {:?}", model1_path);
@@ -633,7 +808,8 @@ fn play_models(
         progressive_visits,
         eval_batch_size,
     };
-    let stats = run_match_series(&mut policy1, &mut policy2, games, cfg, _verbose)?;
+    let encoders = (policy1.encoder(), policy2.encoder());
+    let stats = run_match_series(&mut policy1, &mut policy2, encoders, games, cfg, book.as_ref(), _verbose)?;
 
     println!("\n📊 对弈结果统计:");
     println!(
@@ -655,12 +831,6 @@ fn play_models(
     Ok(())
 }
 
-#[derive(Debug)]
-enum GameResult {
-    Win(PlayerId),
-    Draw,
-}
-
 #[derive(Clone, Copy, Debug)]
 struct SearchConfig {
     num_explores: usize,
@@ -679,9 +849,11 @@ struct MatchStats {
     draws: usize,
 }
 
+/// `encoders`是两个策略各自评估时用的特征编码, 搜索的对局按它编码, 缓存才会区分编码用到的历史
 fn play_single_game<P1, P2>(
     policy1: &mut P1,
     policy2: &mut P2,
+    encoders: (FeatureEncoder, FeatureEncoder),
     num_explores: usize,
     gumbel_scale: f32,
     contempt: f32,
@@ -689,6 +861,7 @@ fn play_single_game<P1, P2>(
     progressive_weight: f32,
     progressive_visits: usize,
     eval_batch_size: usize,
+    book: Option<&OpeningBook>,
     verbose: bool,
     p1_is_red: bool,
 ) -> Result<GameResult>
@@ -719,6 +892,7 @@ where
         ActionSelection::NumVisits
     };
 
+    let mut rng = rand::rng();
     let mut turn = 0;
     while !game.is_over() && turn < CChess::MAX_TURNS {
         if verbose {
@@ -734,14 +908,21 @@ where
             current_player == PlayerId::Black
         };
 
-        let action = if use_policy1 {
+        let book_move = book.and_then(|book| book.pick(&game, &mut rng));
+        let action = if let Some(action) = book_move {
+            action
+        } else if use_policy1 {
+            let mut root = game.clone();
+            root.set_encoder(encoders.0);
             let mut cached = PolicyWithCache::with_capacity(100, policy1);
-            let mut mcts = MCTS::with_capacity(num_explores + 1, mcts_cfg, &mut cached, game.clone());
+            let mut mcts = MCTS::with_capacity(num_explores + 1, mcts_cfg, &mut cached, root);
             mcts.explore_n(num_explores);
             mcts.best_action(action_selection)
         } else {
+            let mut root = game.clone();
+            root.set_encoder(encoders.1);
             let mut cached = PolicyWithCache::with_capacity(100, policy2);
-            let mut mcts = MCTS::with_capacity(num_explores + 1, mcts_cfg, &mut cached, game.clone());
+            let mut mcts = MCTS::with_capacity(num_explores + 1, mcts_cfg, &mut cached, root);
             mcts.explore_n(num_explores);
             mcts.best_action(action_selection)
         };
@@ -760,18 +941,17 @@ where
     }
 
     // 判断结果
-    let winner = get_winner(&game);
-    Ok(match winner {
-        Some(w) => GameResult::Win(w),
-        None => GameResult::Draw,
-    })
+    // 达到回合上限时按和棋处理
+    Ok(game.result().unwrap_or(GameResult::Draw))
 }
 
 fn run_match_series<P1, P2>(
     policy1: &mut P1,
     policy2: &mut P2,
+    encoders: (FeatureEncoder, FeatureEncoder),
     games: usize,
     cfg: SearchConfig,
+    book: Option<&OpeningBook>,
     verbose: bool,
 ) -> Result<MatchStats>
 where
@@ -786,6 +966,7 @@ where
         let result = play_single_game(
             policy1,
             policy2,
+            encoders,
             cfg.num_explores,
             cfg.gumbel_scale,
             cfg.contempt,
@@ -793,6 +974,7 @@ where
             cfg.progressive_weight,
             cfg.progressive_visits,
             cfg.eval_batch_size,
+            book,
             verbose,
             game_idx % 2 == 0,
         )?;
@@ -841,6 +1023,7 @@ fn eval_search_configs(
     let trainer = BurnTrainer::new(model_config, device);
     let mut policy1 = trainer.load_policy(&model1_path)?;
     let mut policy2 = trainer.load_policy(&model2_path)?;
+    let encoders = (policy1.encoder(), policy2.encoder());
 
     println!("🔬 开始批量评测，共 {} 组参数", explores.len()
         * gumbel_scales.len()
@@ -881,7 +1064,7 @@ fn eval_search_configs(
                                 eval_batch_size,
                             };
                             let stats =
-                                run_match_series(&mut policy1, &mut policy2, games, cfg, false)?;
+                                run_match_series(&mut policy1, &mut policy2, encoders, games, cfg, None, false)?;
                             let p1 = stats.wins_p1 as f64 / games as f64 * 100.0;
                             let p2 = stats.wins_p2 as f64 / games as f64 * 100.0;
                             let draw = stats.draws as f64 / games as f64 * 100.0;
@@ -929,36 +1112,6 @@ fn eval_search_configs(
     Ok(())
 }
 
-fn get_winner(game: &CChess) -> Option<PlayerId> {
-    // 检查是否将帅被吃
-    let mut has_red_king = false;
-    let mut has_black_king = false;
-    
-    let fen_str = game.state().fen_str();
-    for (piece, _) in aichess::fen::fen2_coords(fen_str) {
-        match piece {
-            'K' => has_red_king = true,
-            'k' => has_black_king = true,
-            _ => {}
-        }
-    }
-
-    match (has_red_king, has_black_king) {
-        (true, false) => Some(PlayerId::Red),
-        (false, true) => Some(PlayerId::Black),
-        (false, false) => None,
-        (true, true) => {
-            // 检查是否无合法走法
-            let position = Position::from_fen(&game.state());
-            if position.gen_legal_moves().is_empty() {
-                Some(game.player().prev())
-            } else {
-                None
-            }
-        }
-    }
-}
-
 fn play_human(
     model_path: PathBuf,
     color: String,
@@ -969,8 +1122,11 @@ fn play_human(
     progressive_weight: f32,
     progressive_visits: usize,
     eval_batch_size: usize,
+    cache_mb: usize,
     _verbose: bool,
     save_pgn_file: Option<PathBuf>,
+    notation: Notation,
+    book: Option<OpeningBook>,
 ) -> Result<()> {
     println!("🎮 开始人机对弈...");
     println!("🤖 AI模型: {:?}", model_path);
@@ -985,6 +1141,9 @@ fn play_human(
     let model_config = NetConfig::new(256, 7);
     let trainer = BurnTrainer::new(model_config, device);
     let mut ai_policy = trainer.load_policy(&model_path)?;
+    // 对方走一步后, 上一步搜索过的局面大多还会用到
+    let table = TranspositionTable::new(cache_mb);
+    let mut rng = rand::rng();
 
     let player_color = if color.to_lowercase() == "red" || color.to_lowercase() == "r" {
         PlayerId::Red
@@ -994,16 +1153,22 @@ fn play_human(
 
     println!("\n提示:");
     println!("  - 输入走法编号 (如 0, 1, 2)");
-    println!("  - 或输入 ICCS 坐标 (如 h2e2)");
+    match notation {
+        Notation::Iccs => println!("  - 或输入 ICCS 坐标 (如 h2e2)"),
+        Notation::Wxf => println!("  - 或输入 WXF 纵线格式 (如 C2=5)"),
+        Notation::Chinese => println!("  - 或输入中文纵线格式 (如 炮二平五)"),
+    }
     println!("  - 输入 'quit' 或 'q' 退出游戏\n");
 
-    let mut game = CChess::new();
+    // 按网络的编码走棋, 置换表才会区分同一局面的不同历史
+    let mut game = CChess::with_encoder(ai_policy.encoder());
     let mut pgn_game = PgnGame::new();
     
     // 设置 PGN 头部信息
     pgn_game.set_header("Event", "人机对弈");
     pgn_game.set_header("White", if player_color == PlayerId::Red { "Human" } else { "AI" });
     pgn_game.set_header("Black", if player_color == PlayerId::Black { "Human" } else { "AI" });
+    pgn_game.set_header("Format", &notation.to_string());
     
     let mcts_cfg = MCTSConfig {
         exploration: Exploration::PolynomialUct { c: 1.25 },
@@ -1049,22 +1214,33 @@ fn play_human(
                 return Ok(());
             }
 
-            // 解析人类输入
-            let action = parse_human_move(&game, input)?;
-            let move_str = format!("{:?}", action);
-            pgn_game.add_move(&move_str);
+            // 解析人类输入, 输入有误时重新输入
+            let action = match parse_human_move(&game, input, notation) {
+                Ok(action) => action,
+                Err(e) => {
+                    println!("❌ {}", e);
+                    continue;
+                }
+            };
+            pgn_game.add_move(&move_to_pgn(game.position(), &action, notation));
             game.step(&action);
         } else {
             // AI走棋
-            println!("🤔 AI思考中...");
-            let mut cached = PolicyWithCache::with_capacity(100, &mut ai_policy);
-            let mut mcts = MCTS::with_capacity(num_explores + 1, mcts_cfg, &mut cached, game.clone());
-            mcts.explore_n(num_explores);
-            let action = mcts.best_action(action_selection);
-            
-            let move_str = format!("{:?}", action);
-            pgn_game.add_move(&move_str);
-            println!("AI走法: {}", move_str);
+            let book_move = book.as_ref().and_then(|book| book.pick(&game, &mut rng));
+            let action = if let Some(action) = book_move {
+                println!("📖 开局库着法");
+                action
+            } else {
+                println!("🤔 AI思考中...");
+                table.new_generation();
+                let mut cached = PolicyWithTable::new(&mut ai_policy, &table);
+                let mut mcts = MCTS::with_capacity(num_explores + 1, mcts_cfg, &mut cached, game.clone());
+                mcts.explore_n(num_explores);
+                mcts.best_action(action_selection)
+            };
+
+            pgn_game.add_move(&move_to_pgn(game.position(), &action, notation));
+            println!("AI走法: {} ({})", action.to_chinese(game.position()), notation.format(game.position(), &action));
             game.step(&action);
         }
 
@@ -1075,10 +1251,11 @@ fn play_human(
     println!("\n=== 游戏结束 ===");
     game.print();
 
-    let winner = get_winner(&game);
+    let winner = game.winner();
     match winner {
         Some(w) => {
-            pgn_game.result = Some(if w == player_color { "1-0" } else { "0-1" }.to_string());
+            // White 是红方
+            pgn_game.result = Some(if w == PlayerId::Red { "1-0" } else { "0-1" }.to_string());
             if w == player_color {
                 println!("🎉 恭喜你获胜!");
             } else {
@@ -1102,12 +1279,9 @@ fn play_human(
     Ok(())
 }
 
-fn parse_human_move(game: &CChess, input: &str) -> Result<Move> {
+fn parse_human_move(game: &CChess, input: &str, notation: Notation) -> Result<Move> {
     // 获取所有合法走法
-    let legal_moves: Vec<Move> = {
-        let position = Position::from_fen(&game.state());
-        position.gen_legal_moves()
-    };
+    let legal_moves: Vec<Move> = game.position().gen_legal_moves();
 
     if legal_moves.is_empty() {
         anyhow::bail!("没有合法走法");
@@ -1120,37 +1294,21 @@ fn parse_human_move(game: &CChess, input: &str) -> Result<Move> {
         }
     }
 
-    // 尝试解析为 ICCS 格式 (如 "h2e2")
-    if input.len() == 4 {
-        let chars: Vec<char> = input.chars().collect();
-        if let Some(move_found) = find_move_by_iccs(&legal_moves, &chars) {
-            return Ok(*move_found);
+    // 按选定的格式解析 (如 "h2e2" 或 "C2=5")
+    match notation.parse(game.position(), input) {
+        Ok(mv) => Ok(mv),
+        Err(e) => {
+            // 显示所有合法走法
+            println!("\n合法走法列表:");
+            for (i, m) in legal_moves.iter().enumerate() {
+                println!("  {}: {} ({})", i, m.to_chinese(game.position()), notation.format(game.position(), m));
+            }
+            anyhow::bail!("无法解析走法: {}", e);
         }
     }
-
-    // 显示所有合法走法
-    println!("\n合法走法列表:");
-    for (i, m) in legal_moves.iter().enumerate() {
-        println!("  {}: {}", i, m);
-    }
-    
-    anyhow::bail!("无法解析走法 '{}', 请输入走法编号或ICCS坐标(如h2e2)", input);
 }
 
-/// 根据 ICCS 坐标查找走法
-fn find_move_by_iccs<'a>(moves: &'a [Move], coords: &[char]) -> Option<&'a Move> {
-    // 简化实现：比较起始和目标位置的字符表示
-    moves.iter().find(|m| {
-        let from_str = format!("{:x}", m.from);
-        let to_str = format!("{:x}", m.to);
-        let input_str: String = coords.iter()
-            .map(|c| c.to_lowercase().next().unwrap())
-            .collect();
-        format!("{}{}", from_str, to_str) == input_str
-    })
-}
-
-fn handle_pgn(file: PathBuf, action: &str) -> Result<()> {
+fn handle_pgn(file: PathBuf, action: &str, notation: Option<Notation>) -> Result<()> {
     match action {
         "show" => {
             println!("📄 加载 PGN 文件: {:?}", file);
@@ -1163,8 +1321,22 @@ fn handle_pgn(file: PathBuf, action: &str) -> Result<()> {
                     println!("{}: {}", key, value);
                 }
                 println!("\n走法:");
+                // 按局面复盘, 附上中文纵线格式或指定的格式; 复盘失败时原样显示
+                let show_notation = notation.unwrap_or(Notation::Chinese);
+                let moves: Vec<String> = match game.convert_moves(show_notation) {
+                    Ok(converted) if game.notation().ok() != Some(show_notation) => game.moves
+                        .iter()
+                        .zip(converted)
+                        .map(|(pgn_move, mv)| format!("{} ({})", pgn_move, mv))
+                        .collect(),
+                    Ok(_) => game.moves.clone(),
+                    Err(e) => {
+                        println!("⚠️  无法复盘: {:#}", e);
+                        game.moves.clone()
+                    }
+                };
                 let mut move_text = String::new();
-                for (j, mv) in game.moves.iter().enumerate() {
+                for (j, mv) in moves.iter().enumerate() {
                     if j % 2 == 0 {
                         if !move_text.is_empty() {
                             move_text.push('\n');
@@ -1182,8 +1354,12 @@ fn handle_pgn(file: PathBuf, action: &str) -> Result<()> {
             }
         }
         "convert" => {
-            println!("⚙️  转换功能开发中...");
-            // TODO: 实现格式转换功能
+            let notation = notation.context("转换需要用 --notation 指定目标格式")?;
+            for mut game in load_pgn(&file)? {
+                game.moves = game.convert_moves(notation)?;
+                game.set_header("Format", &notation.to_string());
+                println!("{}\n", game.to_string());
+            }
         }
         _ => {
             anyhow::bail!("未知操作: {}。支持的操作: show, convert", action);
@@ -1193,6 +1369,119 @@ fn handle_pgn(file: PathBuf, action: &str) -> Result<()> {
     Ok(())
 }
 
+/// 读取开局库, 指定了步数时覆盖建库时的设置
+fn load_book(path: Option<PathBuf>, depth: Option<usize>) -> Result<Option<OpeningBook>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let mut book = OpeningBook::load(&path)?;
+    if let Some(depth) = depth {
+        book.max_depth = depth;
+    }
+    println!("📖 开局库: {:?} ({} 个局面, 前 {} 步)", path, book.len(), book.max_depth);
+    Ok(Some(book))
+}
+
+/// 从 PGN 棋谱生成开局库, 无法复盘的棋谱跳过
+fn build_book(files: &[PathBuf], out: &PathBuf, max_depth: usize, min_games: u32) -> Result<()> {
+    let mut book = OpeningBook::new(max_depth);
+    let (mut added, mut skipped) = (0, 0);
+    for file in files {
+        println!("📄 加载 PGN 文件: {:?}", file);
+        for game in load_pgn(file)? {
+            match book.add_pgn(&game) {
+                Ok(()) => added += 1,
+                Err(e) => {
+                    skipped += 1;
+                    println!("⚠️  跳过无法复盘的棋谱: {:#}", e);
+                }
+            }
+        }
+    }
+    book.prune(min_games);
+    book.save(out)?;
+    println!("✅ 收录 {} 局, 跳过 {} 局; 开局库共 {} 个局面", added, skipped, book.len());
+    println!("💾 开局库已写入: {:?}", out);
+    Ok(())
+}
+
+/// 读取残局库目录, 设为进程共用的残局库
+fn install_tablebase(dir: Option<PathBuf>) -> Result<()> {
+    let Some(dir) = dir else {
+        return Ok(());
+    };
+    let tablebase = Tablebase::load_dir(&dir)?;
+    let mut materials: Vec<String> = tablebase.materials().map(|m| m.to_string()).collect();
+    materials.sort();
+    println!("📚 残局库: {:?} ({} 种子力组合: {})", dir, materials.len(), materials.join(", "));
+    tablebase.install();
+    Ok(())
+}
+
+/// 生成残局库并保存, 每种子力组合一个文件
+fn build_tablebase(materials: &[Material], out: &PathBuf) -> Result<()> {
+    let mut tablebase = Tablebase::new();
+    for material in materials {
+        let start = std::time::Instant::now();
+        println!("⚙️  生成残局库: {}", material);
+        tablebase.generate(material);
+        println!("   用时 {:.1}s", start.elapsed().as_secs_f64());
+    }
+    tablebase.save_dir(out)?;
+    println!("💾 残局库已写入: {:?} ({} 种子力组合)", out, tablebase.materials().count());
+    Ok(())
+}
+
+/// 打印perft结果, divide为true时先按第一步着法逐个列出
+fn run_perft(fen: &str, depth: u32, divide: bool) -> Result<()> {
+    let fen: Fen = fen.parse()?;
+    let position = Position::from_fen(&fen);
+    println!("局面: {}", fen);
+
+    let start = std::time::Instant::now();
+    let nodes = if divide {
+        let moves = position.divide(depth);
+        for (mv, count) in &moves {
+            println!("  {}: {}", mv, count);
+        }
+        moves.iter().map(|(_, count)| count).sum()
+    } else {
+        position.perft(depth)
+    };
+    let elapsed = start.elapsed();
+
+    println!("perft({}) = {}", depth, nodes);
+    println!("用时: {:.3}s ({:.0} 节点/秒)", elapsed.as_secs_f64(), nodes as f64 / elapsed.as_secs_f64().max(1e-9));
+    Ok(())
+}
+
+/// 打印Alpha-Beta搜索的结果: 最好着法、分数、主要变例和节点数
+fn run_search(fen: &str, depth: u32, time: Option<u64>, nodes: Option<u64>) -> Result<()> {
+    let fen: Fen = fen.parse()?;
+    let position = Position::from_fen(&fen);
+    println!("局面: {}", fen);
+
+    let limits = SearchLimits { depth, nodes, time: time.map(std::time::Duration::from_millis) };
+    let start = std::time::Instant::now();
+    let result = search(&position, limits);
+    let elapsed = start.elapsed();
+
+    match result.best_move {
+        Some(mv) => println!("最好着法: {}", mv),
+        None => println!("走棋方已无棋可走"),
+    }
+    match result.mate_in() {
+        Some(plies) if plies > 0 => println!("分数: {} 步(半回合)后将死对方", plies),
+        Some(plies) => println!("分数: {} 步(半回合)后被将死", -plies),
+        None => println!("分数: {}", result.score),
+    }
+    let pv: Vec<String> = result.pv.iter().map(|mv| mv.to_string()).collect();
+    println!("主要变例: {}", pv.join(" "));
+    println!("深度: {}, 节点: {}", result.depth, result.nodes);
+    println!("用时: {:.3}s ({:.0} 节点/秒)", elapsed.as_secs_f64(), result.nodes as f64 / elapsed.as_secs_f64().max(1e-9));
+    Ok(())
+}
+
 /// 绘制训练曲线图
 fn plot_training_curves(
     report: &aichess::AlphaZeroReport,
@@ -5,8 +5,8 @@ use anyhow::bail;
 use clap::Parser;
 use resvg::{tiny_skia, usvg};
 
-use aichess::{FILE_LEFT, file_x, RANK_TOP, rank_y};
 use aichess::fen::fen2_coords;
+use aichess::square::Square;
 
 const PIECE_SIZE: usize = 50;
 const HALF_PIECE_SIZE: usize = 25;
@@ -43,10 +43,10 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn build_char_loc_str(piece: char, loc: u8) -> String {
+fn build_char_loc_str(piece: char, loc: Square) -> String {
     // <use href="#r" transform="translate(25,25)" />
-    let x = (file_x(loc as usize) - FILE_LEFT) * PIECE_SIZE + HALF_PIECE_SIZE;
-    let y = (rank_y(loc as usize) - RANK_TOP) * PIECE_SIZE + HALF_PIECE_SIZE;
+    let x = loc.file() * PIECE_SIZE + HALF_PIECE_SIZE;
+    let y = (9 - loc.rank()) * PIECE_SIZE + HALF_PIECE_SIZE;
     format!("\n        <use href=\"#{piece}\" transform=\"translate({x},{y})\" />")
 }
 
@@ -58,10 +58,10 @@ fn insert_substring(original_string: &mut String, substring1: &str, substring2:
 
 fn fen2svg(fen: &str) -> anyhow::Result<String> {
     // let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
-    let piece_locs = fen2_coords(fen);
+    let piece_locs = fen2_coords(fen)?;
     let mut template = include_str!("board.svg").to_string();
     for (piece, loc) in piece_locs {
-        let s = build_char_loc_str(piece, loc);
+        let s = build_char_loc_str(piece, Square::new(loc as usize).unwrap());
         insert_substring(&mut template, LOC_STRING, &s);
     }
 
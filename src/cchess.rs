@@ -1,77 +1,443 @@
-use crate::fen::{fen2_coords, Fen};
+use crate::fen::{Fen, FenError};
 use crate::position::Position;
+use crate::synthesis::game::Outcome;
 use crate::{Game, HasTurnOrder};
-use crate::pos::moves::Move;
+use crate::pos::movelist;
+use crate::pos::moves::{Move, NUM_ACTION_INDICES};
+use crate::pos::piece::{Color, PieceKind};
+use crate::pos::tablebase::{Tablebase, Wdl};
+use crate::pos::{REP_DRAW, REP_LOSS, REP_NONE, REP_WIN};
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
 
 pub const BOARD_RANKS: usize = 10;
 pub const BOARD_FILES: usize = 9;
-pub const INPUT_PLANES: usize = 14;
-pub const MAX_NUM_ACTIONS: usize = BOARD_RANKS * BOARD_FILES * BOARD_RANKS * BOARD_FILES;
+/// 每个局面的棋子平面数: 走棋方和对方的帅仕相马车炮兵各一个平面
+pub const PIECE_PLANES: usize = 14;
+/// 默认的特征编码(只看当前局面)的输入平面数, 其他编码见`FeatureEncoder::planes`
+pub const INPUT_PLANES: usize = PIECE_PLANES;
+/// 动作空间的大小: 只包括几何上可能的着法, 见`Move::action_index`
+pub const MAX_NUM_ACTIONS: usize = NUM_ACTION_INDICES;
 const MAX_GAME_TURNS: usize = 200;
+// 同一局面第三次出现(之前已出现两次)时按重复局面判定
+const REP_RECUR: usize = 2;
+// 将军时全部位都置位, 捉子时只置被捉棋子序号对应的位, 一将一捉按长捉处理
+const PERP_CHECK: u32 = 0x1ffff;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum PlayerId {
-    Red,
-    Black,
-}
+/// 对局中的玩家就是红黑双方
+pub type PlayerId = Color;
 
-impl HasTurnOrder for PlayerId {
+impl HasTurnOrder for Color {
     fn prev(&self) -> Self {
-        self.next()
+        self.opponent()
     }
 
     fn next(&self) -> Self {
-        match self {
-            PlayerId::Black => PlayerId::Red,
-            PlayerId::Red => PlayerId::Black,
+        self.opponent()
+    }
+}
+
+/// 对局结果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    Win(PlayerId),
+    Draw,
+}
+
+/// 和棋规则
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawRules {
+    /// 自然限着: 双方连续这么多回合没有吃子时判和, 0表示不限制
+    pub move_limit: u32,
+    /// 对局最多走这么多步(半回合), 达到后判和, 0表示不限制
+    pub max_plies: usize,
+}
+
+impl Default for DrawRules {
+    fn default() -> Self {
+        DrawRules {
+            move_limit: 60,
+            max_plies: MAX_GAME_TURNS,
+        }
+    }
+}
+
+/// 一个输入平面, 行从走棋方看过去的对方底线数起
+pub type Plane = [[f32; BOARD_FILES]; BOARD_RANKS];
+
+/// 神经网络输入特征的编码方式. 平面依次是当前局面和之前`history - 1`个局面的棋子平面
+/// (打开`repetitions`时每个局面后面跟着两个重复次数平面), 最后是步数平面和无吃子步数平面.
+/// 所有局面都从当前走棋方看过去, 对局开始之前的局面全为0. 训练时随checkpoint一起保存
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeatureEncoder {
+    /// 包括当前局面在内编码的局面数, 至少为1
+    pub history: usize,
+    /// 局面之前出现过至少一次、至少两次时, 对应的平面全为1
+    pub repetitions: bool,
+    /// 加一个平面: 已走的步数占对局步数上限的比例
+    pub move_count: bool,
+    /// 加一个平面: 无吃子步数占自然限着的比例
+    pub halfmove_clock: bool,
+}
+
+impl Default for FeatureEncoder {
+    fn default() -> Self {
+        FeatureEncoder {
+            history: 1,
+            repetitions: false,
+            move_count: false,
+            halfmove_clock: false,
+        }
+    }
+}
+
+impl FeatureEncoder {
+    /// 输入平面数, 网络的输入层要与之一致
+    pub fn planes(&self) -> usize {
+        self.history.max(1) * self.position_planes() + self.move_count as usize + self.halfmove_clock as usize
+    }
+
+    fn position_planes(&self) -> usize {
+        PIECE_PLANES + if self.repetitions { 2 } else { 0 }
+    }
+
+    /// 编码对局的当前局面
+    pub fn encode(&self, game: &CChess) -> Vec<Plane> {
+        let mut planes = vec![[[0.0; BOARD_FILES]; BOARD_RANKS]; self.planes()];
+        let player = game.player();
+        let locks = game_locks(game);
+        let mut position = game.position.clone();
+        for step in 0..self.history.max(1) {
+            if step > 0 {
+                let Some(record) = game.history.len().checked_sub(step).map(|i| game.history[i]) else {
+                    break;
+                };
+                position.unmake_move(record.mv, record.halfmove_clock);
+            }
+            let base = step * self.position_planes();
+            encode_pieces(&position, player, &mut planes[base..base + PIECE_PLANES]);
+            if self.repetitions {
+                let count = repetitions(&locks, locks.len() - 1 - step);
+                for plane in planes[base + PIECE_PLANES..base + PIECE_PLANES + count].iter_mut() {
+                    *plane = [[1.0; BOARD_FILES]; BOARD_RANKS];
+                }
+            }
+        }
+
+        let mut index = self.history.max(1) * self.position_planes();
+        if self.move_count {
+            let max_plies = match game.rules.max_plies {
+                0 => MAX_GAME_TURNS,
+                n => n,
+            };
+            let value = (game.history.len() as f32 / max_plies as f32).min(1.0);
+            planes[index] = [[value; BOARD_FILES]; BOARD_RANKS];
+            index += 1;
         }
+        if self.halfmove_clock {
+            let move_limit = match game.rules.move_limit {
+                0 => DrawRules::default().move_limit,
+                n => n,
+            };
+            let value = (game.halfmove_clock() as f32 / (move_limit * 2) as f32).min(1.0);
+            planes[index] = [[value; BOARD_FILES]; BOARD_RANKS];
+        }
+        planes
     }
+
+    /// 除当前局面外编码还用到的内容: 之前局面的校验锁、各局面的重复次数、步数和无吃子步数(连同对应的限制).
+    /// 当前局面相同、这些也相同的两个对局编码相同; 默认编码只看当前局面, 返回空
+    fn context(&self, game: &CChess) -> Vec<u64> {
+        let mut res = Vec::new();
+        let steps = self.history.max(1).min(game.history.len() + 1);
+        if steps > 1 || self.repetitions {
+            let locks = game_locks(game);
+            for step in 0..steps {
+                let t = locks.len() - 1 - step;
+                if step > 0 {
+                    res.push(locks[t]);
+                }
+                if self.repetitions {
+                    res.push(repetitions(&locks, t) as u64);
+                }
+            }
+        }
+        if self.move_count {
+            res.extend([game.history.len() as u64, game.rules.max_plies as u64]);
+        }
+        if self.halfmove_clock {
+            res.extend([game.halfmove_clock() as u64, game.rules.move_limit as u64]);
+        }
+        res
+    }
+}
+
+/// 对局中依次出现的局面的校验锁, 最后一个是当前局面
+fn game_locks(game: &CChess) -> Vec<u64> {
+    game.history.iter().map(|record| record.lock).chain([game.position.lock()]).collect()
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// 第t个局面之前出现过的次数, 最多算2次
+fn repetitions(locks: &[u64], t: usize) -> usize {
+    locks[..t].iter().filter(|&&lock| lock == locks[t]).count().min(2)
+}
+
+/// 局面的14个棋子平面: 前7个是`player`的帅仕相马车炮兵, 后7个是对方的
+fn encode_pieces(position: &Position, player: Color, planes: &mut [Plane]) {
+    for (offset, color) in [(0, player), (7, player.opponent())] {
+        for kind in PieceKind::ALL {
+            for square in position.pieces(color, kind) {
+                let (rank, file) = match player {
+                    Color::Red => (BOARD_RANKS - 1 - square.rank(), square.file()),
+                    Color::Black => (square.rank(), BOARD_FILES - 1 - square.file()),
+                };
+                planes[offset + kind as usize][rank][file] = 1.0;
+            }
+        }
+    }
+}
+
+/// 历史着法记录, 用于判断重复局面和悔棋
+#[derive(Debug, Clone, Copy)]
+struct MoveRecord {
+    // 走这步之前局面的Zobrist键值和校验锁
+    hash: u32,
+    lock: u64,
+    // 走过的着法, 带有被吃的棋子; 吃子后之前的局面不可能再出现
+    mv: Move,
+    // 走这步之前的无吃子步数
+    halfmove_clock: u32,
+    // 将军记为PERP_CHECK, 捉子记为被捉棋子的位, 否则为0
+    perp: u32,
+}
+
+/// 局面比较和哈希看当前局面的Zobrist键值(包括棋盘和走棋方), 以及特征编码除当前局面外还用到的内容
+/// (默认编码没有), 以便缓存和去重: 编码相同的对局才算相同
+#[derive(Debug, Clone)]
 pub struct CChess {
-    state: Fen,
-    player: PlayerId,
+    position: Position,
+    history: Vec<MoveRecord>,
+    rules: DrawRules,
+    encoder: FeatureEncoder,
+}
+
+impl PartialEq for CChess {
+    fn eq(&self, other: &Self) -> bool {
+        self.position == other.position && self.encoder.context(self) == other.encoder.context(other)
+    }
+}
+
+impl Eq for CChess {}
+
+impl Hash for CChess {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&self.position, state);
+        self.encoder.context(self).hash(state);
+    }
 }
 
 impl CChess {
-    fn winner(&self) -> Option<PlayerId> {
-        let mut has_red_king = false;
-        let mut has_black_king = false;
-        for (piece, _) in fen2_coords(self.state.fen_str()) {
-            match piece {
-                'K' => has_red_king = true,
-                'k' => has_black_king = true,
-                _ => {}
-            }
+    /// 从FEN局面开始一局, FEN不合法或局面不可能出现时返回错误
+    pub fn from_fen(fen: Fen) -> Result<Self, FenError> {
+        // Fen::new不做校验, 这里重新解析一遍
+        let fen: Fen = fen.fen_str().parse()?;
+        Ok(CChess {
+            position: Position::from_fen(&fen),
+            history: Vec::new(),
+            rules: DrawRules::default(),
+            encoder: FeatureEncoder::default(),
+        })
+    }
+
+    /// 按指定的和棋规则新开一局
+    pub fn with_rules(rules: DrawRules) -> Self {
+        CChess {
+            rules,
+            ..<Self as Game<MAX_NUM_ACTIONS>>::new()
         }
+    }
 
-        match (has_red_king, has_black_king) {
-            (true, false) => Some(PlayerId::Red),
-            (false, true) => Some(PlayerId::Black),
-            (false, false) => None,
-            (true, true) => {
-                let position = Position::from_fen(&self.state);
-                if position.gen_legal_moves().is_empty() {
-                    Some(self.player.prev())
-                } else {
-                    None
+    /// 当前使用的和棋规则
+    pub fn rules(&self) -> DrawRules {
+        self.rules
+    }
+
+    /// 按指定的特征编码新开一局, `features()`按它编码
+    pub fn with_encoder(encoder: FeatureEncoder) -> Self {
+        CChess {
+            encoder,
+            ..<Self as Game<MAX_NUM_ACTIONS>>::new()
+        }
+    }
+
+    /// 当前使用的特征编码
+    pub fn encoder(&self) -> FeatureEncoder {
+        self.encoder
+    }
+
+    /// 改用另一种特征编码, 对局历史不变. 评估网络按自己的编码训练时,
+    /// 搜索的对局要用同样的编码, 缓存才会区分编码用到的历史
+    pub fn set_encoder(&mut self, encoder: FeatureEncoder) {
+        self.encoder = encoder;
+    }
+
+    /// 缓存评估结果用的键值和校验锁: 默认编码就是局面的Zobrist键值和校验锁,
+    /// 否则混入编码用到的历史局面、重复次数和步数, 与`==`的区分一致
+    pub fn feature_key(&self) -> (u64, u64) {
+        let (key, lock) = (self.position.hash() as u64, self.position.lock());
+        let context = self.encoder.context(self);
+        if context.is_empty() {
+            return (key, lock);
+        }
+        let mut hasher = DefaultHasher::new();
+        context.hash(&mut hasher);
+        let h = hasher.finish();
+        (key ^ h, lock ^ h.rotate_left(32))
+    }
+
+    /// 自上次吃子以来的步数(半回合)
+    pub fn halfmove_clock(&self) -> u32 {
+        self.position.halfmove_clock()
+    }
+
+    /// 当前回合数
+    pub fn fullmove_number(&self) -> u32 {
+        self.position.fullmove_number()
+    }
+
+    /// 对局的胜方, 对局未结束或和棋时返回None
+    pub fn winner(&self) -> Option<PlayerId> {
+        match self.result() {
+            Some(GameResult::Win(winner)) => Some(winner),
+            _ => None,
+        }
+    }
+
+    /// 对局结果: 走棋方被将死或困毙时对方获胜; 重复局面时,
+    /// 单方长将或长捉判负, 否则判和; 达到自然限着或对局步数上限时判和.
+    /// 对局未结束返回None
+    pub fn result(&self) -> Option<GameResult> {
+        let position = &self.position;
+        if let Some(winner) = position.winner() {
+            return Some(GameResult::Win(winner));
+        }
+        match self.rep_status(REP_RECUR) {
+            REP_NONE => {}
+            REP_LOSS => return Some(GameResult::Win(self.player().next())),
+            REP_WIN => return Some(GameResult::Win(self.player())),
+            _ => return Some(GameResult::Draw),
+        }
+        let move_limit = self.rules.move_limit;
+        if move_limit > 0 && position.halfmove_clock() >= move_limit * 2 {
+            return Some(GameResult::Draw);
+        }
+        let max_plies = self.rules.max_plies;
+        if max_plies > 0 && self.history.len() >= max_plies {
+            return Some(GameResult::Draw);
+        }
+        None
+    }
+
+    /// 检查当前局面是否重复出现了n_recur次, 返回REP_NONE/REP_DRAW/REP_LOSS/REP_WIN,
+    /// 输赢是对走棋方而言. 参考ElephantEye的RepStatus
+    fn rep_status(&self, n_recur: usize) -> usize {
+        let mut n_recur = n_recur;
+        let mut self_side = false;
+        let mut perp = PERP_CHECK;
+        let mut opp_perp = PERP_CHECK;
+        for record in self.history.iter().rev() {
+            if record.mv.captured().is_some() {
+                break;
+            }
+            if self_side {
+                perp &= record.perp;
+                if record.hash == self.position.hash() && record.lock == self.position.lock() {
+                    n_recur -= 1;
+                    if n_recur == 0 {
+                        return match (perp != 0, opp_perp != 0) {
+                            (true, false) => REP_LOSS,
+                            (false, true) => REP_WIN,
+                            _ => REP_DRAW,
+                        };
+                    }
                 }
+            } else {
+                opp_perp &= record.perp;
+            }
+            self_side = !self_side;
+        }
+        REP_NONE
+    }
+
+    /// 当前局面的FEN
+    pub fn state(&self) -> Fen {
+        self.position.to_fen()
+    }
+
+    /// 当前局面
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    /// 在残局库里查当前局面, 胜负带上到将死的步数. 库里不考虑和棋规则, 所以:
+    /// 将死之前对局步数上限就用完的按和棋算; 将死之前会到自然限着的, 途中吃子又会重新计数, 结果不确定;
+    /// 局面已经出现过的, 长将长捉可能改变结果; 后两种返回None, 交给搜索和对局规则
+    pub fn tablebase_outcome(&self, tablebase: &Tablebase) -> Option<Outcome> {
+        let probe = tablebase.probe(&self.position)?;
+        if self.rep_status(1) != REP_NONE {
+            return None;
+        }
+        let dtm = probe.dtm as usize;
+        if probe.wdl != Wdl::Draw {
+            let (plies, max_plies) = (self.history.len(), self.rules.max_plies);
+            if max_plies > 0 && plies + dtm > max_plies {
+                return Some(Outcome::Draw(max_plies.saturating_sub(plies)));
+            }
+            let move_limit = self.rules.move_limit as usize * 2;
+            if move_limit > 0 && self.halfmove_clock() as usize + dtm > move_limit {
+                return None;
             }
         }
+        Some(match probe.wdl {
+            Wdl::Win => Outcome::Win(dtm),
+            Wdl::Loss => Outcome::Lose(dtm),
+            Wdl::Draw => Outcome::Draw(0),
+        })
     }
 
-    /// 获取当前状态的引用
-    pub fn state(&self) -> &Fen {
-        &self.state
+    /// 悔一步棋, 返回被撤销的着法, 没有可悔的棋时返回None.
+    /// 撤销记录在对局里, 复制出来的对局也能悔到开局
+    pub fn undo(&mut self) -> Option<Move> {
+        let record = self.history.pop()?;
+        self.position.unmake_move(record.mv, record.halfmove_clock);
+        Some(record.mv)
+    }
+}
+
+/// 左右镜像的输入特征: 每个平面的纵线左右翻转, 与镜像局面(`Position::mirror`)的`features()`相同
+pub fn mirror_features(features: &[Plane]) -> Vec<Plane> {
+    let mut res = features.to_vec();
+    for row in res.iter_mut().flatten() {
+        row.reverse();
     }
+    res
+}
+
+/// 左右镜像的策略向量: 每个动作的值搬到镜像着法(`Move::mirror`)的动作序号上
+pub fn mirror_policy(policy: &[f32; MAX_NUM_ACTIONS]) -> [f32; MAX_NUM_ACTIONS] {
+    let mut res = [0.0; MAX_NUM_ACTIONS];
+    for (index, &p) in policy.iter().enumerate() {
+        let mv = Move::try_from(index).expect("action index out of range");
+        res[mv.mirror().action_index()] = p;
+    }
+    res
 }
 
 impl Game<MAX_NUM_ACTIONS> for CChess {
     type PlayerId = PlayerId;
     type Action = Move;
-    type ActionIterator = MoveIterator;
-    type Features = [[[f32; BOARD_FILES]; BOARD_RANKS]; INPUT_PLANES];
+    type ActionIterator = movelist::IntoIter;
+    type Features = Vec<Plane>;
 
     const MAX_TURNS: usize = MAX_GAME_TURNS;
     const NAME: &'static str = "Chinese chess";
@@ -80,113 +446,91 @@ impl Game<MAX_NUM_ACTIONS> for CChess {
 
     fn new() -> Self {
         CChess {
-            state: Fen::init(),
-            player: PlayerId::Red,
+            position: Position::from_fen(&Fen::init()),
+            history: Vec::new(),
+            rules: DrawRules::default(),
+            encoder: FeatureEncoder::default(),
         }
     }
 
-    fn player(&self) -> Self::PlayerId { self.player }
+    fn player(&self) -> Self::PlayerId { self.position.side_to_move() }
 
     fn is_over(&self) -> bool {
-        self.winner().is_some()
+        self.result().is_some()
     }
 
     fn reward(&self, player_id: Self::PlayerId) -> f32
     {
-        match self.winner() {
-            Some(winner) => {
+        match self.result() {
+            Some(GameResult::Win(winner)) => {
                 if winner == player_id {
                     1.0
                 } else {
                     -1.0
                 }
             }
-            None => 0.0,
+            _ => 0.0,
         }
     }
 
     fn iter_actions(&self) -> Self::ActionIterator {
-        let position = Position::from_fen(&self.state);
-        MoveIterator {
-            moves: position.gen_legal_moves(),
-            index: 0,
-        }
+        self.position.legal_moves().into_iter()
     }
 
     fn step(&mut self, action: &Self::Action) -> bool {
-        let mut position = Position::from_fen(&self.state);
-        position.make_move(*action);
-        self.state = position.to_fen();
-        self.player = self.player.next();
+        // 棋子以棋盘为准, 动作可以是只从动作序号还原的着法
+        let (from, to) = (action.from().index(), action.to().index());
+        let mv = Move::new(self.position.pc_on(from), from, to, self.position.pc_on(to));
+        let (hash, lock, halfmove_clock) = (self.position.hash(), self.position.lock(), self.position.halfmove_clock());
+        self.position.make_move(*action);
+        let perp = if self.position.is_in_check(self.position.side_to_move()) {
+            PERP_CHECK
+        } else {
+            match self.position.chased_by(action) {
+                0 => 0,
+                pc => 1 << (pc & 15),
+            }
+        };
+        self.history.push(MoveRecord { hash, lock, mv, halfmove_clock, perp });
         self.is_over()
     }
 
-    fn features(&self) -> Self::Features {
-        // 使用 Copy trait，直接赋值而非 clone
-        let mut features = [[[0.0; BOARD_FILES]; BOARD_RANKS]; INPUT_PLANES];
-
-        for (piece, square) in fen2_coords(self.state.fen_str()) {
-            let file = (square as usize & 0x0f).saturating_sub(3);
-            let rank = ((square as usize) >> 4).saturating_sub(3);
-            if rank >= BOARD_RANKS || file >= BOARD_FILES {
-                continue;
-            }
-
-            let is_red_piece = piece.is_ascii_uppercase();
-            let plane = match piece.to_ascii_uppercase() {
-                'K' => 0,
-                'A' => 1,
-                'B' => 2,
-                'N' => 3,
-                'R' => 4,
-                'C' => 5,
-                'P' => 6,
-                _ => continue,
-            };
-
-            let is_current_player_piece = match self.player {
-                PlayerId::Red => is_red_piece,
-                PlayerId::Black => !is_red_piece,
-            };
-
-            let plane = if is_current_player_piece { plane } else { plane + 7 };
-            let (rank, file) = match self.player {
-                PlayerId::Red => (rank, file),
-                PlayerId::Black => (BOARD_RANKS - 1 - rank, BOARD_FILES - 1 - file),
-            };
-            features[plane][rank][file] = 1.0;
-        }
+    /// 查进程共用的残局库(见`Tablebase::install`), 按本局的和棋规则修正, 见`tablebase_outcome`
+    fn known_outcome(&self) -> Option<Outcome> {
+        self.tablebase_outcome(Tablebase::shared()?)
+    }
 
-        features
+    /// 按对局的特征编码(见`with_encoder`)编码, 默认只有当前局面的14个棋子平面
+    fn features(&self) -> Self::Features {
+        self.encoder.encode(self)
     }
 
     fn print(&self) {
-        println!("{}", self.state);
+        println!("{}", self.position.to_fen());
     }
 }
 
-pub struct MoveIterator {
-    moves: Vec<Move>,
-    index: usize,
-}
-
-impl Iterator for MoveIterator {
-    type Item = Move;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.moves.len() {
-            let item = self.moves[self.index];
-            self.index += 1;
-            Some(item)
-        } else {
-            None
-        }
+/// 测试用: 从初始局面开始双方来回跳马一圈, 返回初始局面和回到初始局面后的对局.
+/// `repetitions`为真时按重复次数平面编码, 两者应该不同, 否则相同
+#[cfg(test)]
+pub(crate) fn repeated_start(repetitions: bool) -> (CChess, CChess) {
+    let start = CChess::with_encoder(FeatureEncoder { repetitions, ..FeatureEncoder::default() });
+    let mut game = start.clone();
+    for iccs in ["h0g2", "h9g7", "g2h0", "g7h9"] {
+        let mv = game.iter_actions().find(|mv| mv.to_iccs() == iccs).unwrap();
+        game.step(&mv);
     }
+    assert_eq!(game.position(), start.position());
+    (start, game)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{CChess, Game, INPUT_PLANES, BOARD_FILES, BOARD_RANKS, MAX_NUM_ACTIONS};
+    use super::{mirror_features, mirror_policy, repeated_start, CChess, DrawRules, FeatureEncoder, Game, GameResult, PlayerId, INPUT_PLANES, BOARD_FILES, BOARD_RANKS, MAX_NUM_ACTIONS};
+    use crate::fen::{Fen, FenError};
+    use crate::pos::tablebase::Tablebase;
+    use crate::synthesis::game::Outcome;
+    use crate::position::PositionError;
 
     #[test]
     fn cchess_initial_features_match_expected_tensor_shape() {
@@ -198,4 +542,231 @@ mod tests {
         assert_eq!(features[0][0].len(), BOARD_FILES);
         assert_eq!(CChess::MAX_NUM_ACTIONS, MAX_NUM_ACTIONS);
     }
+
+    #[test]
+    fn cchess_mirror_features_and_policy() {
+        let game = CChess::from_fen(Fen::new("r1bakab1r/9/1cn4cn/p1p1p1p1p/9/2P6/P3P1P1P/1CN1C1N2/9/R1BAKAB1R b")).unwrap();
+        let mirrored = CChess::from_fen(game.position().mirror().to_fen()).unwrap();
+        assert_eq!(mirror_features(&game.features()), mirrored.features());
+        assert_ne!(mirrored.features(), game.features());
+
+        let mut policy = [0.0; MAX_NUM_ACTIONS];
+        for (i, mv) in game.position().legal_moves().iter().enumerate() {
+            policy[mv.action_index()] = i as f32 + 1.0;
+        }
+        let mirrored_policy = mirror_policy(&policy);
+        for mv in game.position().legal_moves() {
+            assert_eq!(mirrored_policy[mv.mirror().action_index()], policy[mv.action_index()]);
+        }
+        assert_eq!(mirrored_policy.iter().sum::<f32>(), policy.iter().sum::<f32>());
+        assert_eq!(mirror_policy(&mirrored_policy), policy);
+    }
+
+    #[test]
+    fn cchess_history_and_clock_planes() {
+        let encoder = FeatureEncoder { history: 3, repetitions: true, move_count: true, halfmove_clock: true };
+        assert_eq!(FeatureEncoder::default().planes(), INPUT_PLANES);
+        assert_eq!(encoder.planes(), 50);
+        assert_eq!(serde_json::from_str::<FeatureEncoder>(&serde_json::to_string(&encoder).unwrap()).unwrap(), encoder);
+
+        let mut game = CChess::with_encoder(encoder);
+        let features = game.features();
+        assert_eq!(features.len(), 50);
+        assert!(features[14..].iter().flatten().flatten().all(|&x| x == 0.0));
+
+        // 红马b0跳到c2, 轮到黑方, 从黑方看过去: 当前局面马在(2, 6), 上一个局面马在(0, 7)
+        let cycle = [(0xc4, 0xa5), (0x34, 0x55), (0xa5, 0xc4), (0x55, 0x34)];
+        play(&mut game, &cycle[..1]);
+        let features = game.features();
+        assert_eq!((features[10][2][6], features[10][0][7]), (1.0, 0.0));
+        assert_eq!((features[26][2][6], features[26][0][7]), (0.0, 1.0));
+        assert!(features[32..48].iter().flatten().flatten().all(|&x| x == 0.0));
+
+        // 回到初始局面, 之前出现过一次
+        play(&mut game, &cycle[1..]);
+        let features = game.features();
+        assert_eq!(features[0], CChess::new().features()[0]);
+        assert!(features[14].iter().flatten().all(|&x| x == 1.0));
+        assert!(features[15].iter().flatten().all(|&x| x == 0.0));
+        assert!(features[30].iter().flatten().all(|&x| x == 0.0));
+        assert_eq!(features[48][0][0], 4.0 / 200.0);
+        assert_eq!(features[49][0][0], 4.0 / 120.0);
+
+        // 再走一圈, 初始局面出现过两次, 两步之前的局面出现过一次
+        play(&mut game, &cycle);
+        let features = game.features();
+        assert!(features[14..16].iter().flatten().flatten().all(|&x| x == 1.0));
+        assert!(features[46].iter().flatten().all(|&x| x == 1.0));
+        assert!(features[47].iter().flatten().all(|&x| x == 0.0));
+        assert_eq!(mirror_features(&features).len(), 50);
+    }
+
+    #[test]
+    fn cchess_set_encoder_keeps_history() {
+        // 按默认编码走出来的对局, 换成网络的编码后要按它区分历史
+        let (mut start, mut repeated) = repeated_start(false);
+        assert_eq!(start, repeated);
+        let encoder = FeatureEncoder { repetitions: true, ..FeatureEncoder::default() };
+        start.set_encoder(encoder);
+        repeated.set_encoder(encoder);
+        assert_ne!(start, repeated);
+        assert_ne!(start.feature_key(), repeated.feature_key());
+        assert_eq!(repeated.features(), encoder.encode(&repeated));
+        assert!(repeated.features()[14].iter().flatten().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    fn cchess_rejects_illegal_start_position() {
+        // 帅将照面, 红方走棋可以直接吃将
+        assert_eq!(
+            CChess::from_fen(Fen::new("4k4/9/9/9/9/9/9/9/9/4K4 w")).err(),
+            Some(FenError::IllegalPosition(PositionError::OpponentInCheck(PlayerId::Black)))
+        );
+        // Fen::new不做校验, 多出来的车也要报错
+        assert_eq!(
+            CChess::from_fen(Fen::new("3k5/9/9/9/9/9/9/9/RRR6/4K4 w")).err(),
+            Some(FenError::TooManyPieces('R'))
+        );
+    }
+
+    #[test]
+    fn cchess_ends_at_checkmate() {
+        // 红车平六将军, 帅控制中路, 黑将无处可逃
+        let mut game = CChess::from_fen(Fen::new("3k5/9/R8/9/9/9/9/9/9/4K4 w")).unwrap();
+        assert!(!game.is_over());
+        let action = game
+            .iter_actions()
+            .find(|mv| mv.from().index() == 0x53 && mv.to().index() == 0x56)
+            .unwrap();
+        assert!(game.step(&action));
+        assert_eq!(game.winner(), Some(PlayerId::Red));
+        assert_eq!(game.reward(PlayerId::Red), 1.0);
+        assert_eq!(game.reward(PlayerId::Black), -1.0);
+    }
+
+    /// 按ICCS坐标走一串棋
+    fn play(game: &mut CChess, moves: &[(u8, u8)]) {
+        for &(from, to) in moves {
+            let action = game
+                .iter_actions()
+                .find(|mv| mv.from().index() == from as usize && mv.to().index() == to as usize)
+                .unwrap();
+            game.step(&action);
+        }
+    }
+
+    #[test]
+    fn cchess_repetition_is_draw() {
+        let mut game = CChess::new();
+        // 双方来回跳马, 初始局面第三次出现
+        let cycle = [(0xc4, 0xa5), (0x34, 0x55), (0xa5, 0xc4), (0x55, 0x34)];
+        play(&mut game, &cycle);
+        assert!(!game.is_over());
+        play(&mut game, &cycle);
+        assert_eq!(game.result(), Some(GameResult::Draw));
+        assert_eq!(game.reward(PlayerId::Red), 0.0);
+    }
+
+    #[test]
+    fn cchess_perpetual_check_loses() {
+        // 红车在底线来回将军, 黑将在九宫里来回躲
+        let mut game = CChess::from_fen(Fen::new("4k4/R8/9/9/9/9/9/9/9/3K5 w")).unwrap();
+        let cycle = [(0x43, 0x33), (0x37, 0x47), (0x33, 0x43), (0x47, 0x37)];
+        play(&mut game, &cycle);
+        assert!(!game.is_over());
+        play(&mut game, &cycle);
+        assert_eq!(game.result(), Some(GameResult::Win(PlayerId::Black)));
+        assert_eq!(game.reward(PlayerId::Red), -1.0);
+    }
+
+    #[test]
+    fn cchess_perpetual_chase_loses() {
+        // 红车跟着无根的黑炮左右捉, 黑炮来回躲
+        let mut game = CChess::from_fen(Fen::new("5k3/9/c8/9/9/9/9/1R7/9/3K5 w")).unwrap();
+        let cycle = [(0xa4, 0xa3), (0x53, 0x54), (0xa3, 0xa4), (0x54, 0x53)];
+        play(&mut game, &cycle);
+        assert!(!game.is_over());
+        play(&mut game, &cycle);
+        assert_eq!(game.result(), Some(GameResult::Win(PlayerId::Black)));
+        assert_eq!(game.reward(PlayerId::Black), 1.0);
+    }
+
+    #[test]
+    fn cchess_mutual_chase_is_draw() {
+        // 红车来回捉黑炮, 黑车来回捉红炮, 双方都是长捉
+        let mut game = CChess::from_fen(Fen::new("5k3/8r/9/c8/9/8C/9/R8/9/3K5 w")).unwrap();
+        let cycle = [(0xa3, 0x93), (0x4b, 0x5b), (0x93, 0xa3), (0x5b, 0x4b)];
+        play(&mut game, &cycle);
+        assert!(game.history.iter().all(|record| record.perp != 0));
+        assert!(!game.is_over());
+        play(&mut game, &cycle);
+        assert_eq!(game.result(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn cchess_move_limit_is_draw() {
+        let mut game = CChess::from_fen(Fen::new("3k5/9/9/9/9/9/9/9/9/R3K4 w - - 118 80")).unwrap();
+        play(&mut game, &[(0xc3, 0xb3)]);
+        assert_eq!(game.halfmove_clock(), 119);
+        assert!(!game.is_over());
+        play(&mut game, &[(0x36, 0x46)]);
+        assert_eq!(game.fullmove_number(), 81);
+        assert_eq!(game.result(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn cchess_max_plies_is_draw() {
+        let mut game = CChess::with_rules(DrawRules { move_limit: 0, max_plies: 4 });
+        let cycle = [(0xc4, 0xa5), (0x34, 0x55), (0xa5, 0xc4)];
+        play(&mut game, &cycle);
+        assert!(!game.is_over());
+        assert!(game.step(&game.iter_actions().next().unwrap()));
+        assert_eq!(game.result(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn cchess_tablebase_outcome_follows_draw_rules() {
+        let mut tablebase = Tablebase::new();
+        tablebase.generate(&"KRK".parse().unwrap());
+        let fen = |clock: u32| Fen::new(format!("5k3/9/9/9/9/9/9/9/9/3K4R w - - {} 80", clock));
+        let game = CChess::from_fen(fen(0)).unwrap();
+        let Some(Outcome::Win(dtm)) = game.tablebase_outcome(&tablebase) else {
+            panic!("KRK应该是红胜");
+        };
+        assert!(dtm > 1);
+
+        // 自然限着120步(半回合), 将死之前先到限着的不确定
+        let game = CChess::from_fen(fen(120 - dtm as u32)).unwrap();
+        assert_eq!(game.tablebase_outcome(&tablebase), Some(Outcome::Win(dtm)));
+        let game = CChess::from_fen(fen(121 - dtm as u32)).unwrap();
+        assert_eq!(game.tablebase_outcome(&tablebase), None);
+
+        // 对局步数上限在将死之前用完, 判和
+        let game = CChess { rules: DrawRules { move_limit: 60, max_plies: dtm - 1 }, ..CChess::from_fen(fen(0)).unwrap() };
+        assert_eq!(game.tablebase_outcome(&tablebase), Some(Outcome::Draw(dtm - 1)));
+        let game = CChess { rules: DrawRules { move_limit: 60, max_plies: dtm }, ..CChess::from_fen(fen(0)).unwrap() };
+        assert_eq!(game.tablebase_outcome(&tablebase), Some(Outcome::Win(dtm)));
+
+        // 双方来回走帅将, 局面重复后交给对局规则
+        let mut game = CChess::from_fen(fen(0)).unwrap();
+        play(&mut game, &[(0xc6, 0xc7), (0x38, 0x48), (0xc7, 0xc6), (0x48, 0x38)]);
+        assert_eq!(game.position(), CChess::from_fen(fen(0)).unwrap().position());
+        assert_eq!(game.tablebase_outcome(&tablebase), None);
+    }
+
+    #[test]
+    fn cchess_undo_restores_position() {
+        let start = CChess::new();
+        let mut game = start.clone();
+        play(&mut game, &[(0xc4, 0xa5), (0x34, 0x55), (0xa5, 0xc4)]);
+        assert_ne!(game, start);
+        // 复制的局面不带撤销记录, 对局自己的记录也能悔到开局
+        let mut copy = game.clone();
+        while copy.undo().is_some() {}
+        assert_eq!(copy.state(), start.state());
+        while game.undo().is_some() {}
+        assert_eq!(game, start);
+        assert_eq!(game.player(), PlayerId::Red);
+        assert_eq!(game.state(), start.state());
+    }
 }
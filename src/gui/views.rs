@@ -467,6 +467,8 @@ pub struct HumanView {
     pub game_status: String,
     pub chess_board: ChessBoardWidget,
     pub current_fen: Fen,
+    /// 摆局用的 FEN 输入
+    pub setup_fen: String,
     // 用时相关
     pub red_time: std::time::Duration,
     pub black_time: std::time::Duration,
@@ -484,6 +486,7 @@ impl HumanView {
             chess_board: ChessBoardWidget::new().with_size(600.0, 667.0),
             // current_fen: Fen::init(),  // 暂时注释掉
             current_fen: Fen::new("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1"),
+            setup_fen: String::new(),
             red_time: std::time::Duration::ZERO,
             black_time: std::time::Duration::ZERO,
             last_move_time: None,
@@ -546,6 +549,28 @@ impl HumanView {
                 // TODO: 实现提示
             }
         });
+
+        // 摆局：从 FEN 开始，不合法的局面不接受
+        ui.horizontal(|ui| {
+            ui.label("局面 FEN:");
+            ui.text_edit_singleline(&mut self.setup_fen);
+            if ui.button("📋 摆局").clicked() {
+                match self.setup_fen.parse::<Fen>() {
+                    Ok(fen) => {
+                        self.current_fen = fen;
+                        self.chess_board.clear_last_move();
+                        self.chess_board.clear_selection();
+                        self.game_status = "从指定局面开始".to_string();
+                        self.red_time = std::time::Duration::ZERO;
+                        self.black_time = std::time::Duration::ZERO;
+                        self.last_move_time = Some(std::time::Instant::now());
+                    }
+                    Err(e) => {
+                        self.game_status = format!("局面不合法: {}", e);
+                    }
+                }
+            }
+        });
         
         ui.add_space(5.0);
         ui.separator();
@@ -560,7 +585,7 @@ impl HumanView {
         
         // 根据当前走棋方计算总用时
         let position = crate::pos::position::Position::from_fen(&self.current_fen);
-        let is_red_turn = position.current_player() == crate::pos::ChessPlayer::Red;
+        let is_red_turn = position.side_to_move() == crate::pos::piece::Color::Red;
         
         let red_display = if is_red_turn {
             self.red_time + current_time
@@ -627,7 +652,7 @@ impl HumanView {
             if let Some(start) = self.last_move_time {
                 let elapsed = start.elapsed();
                 let position = crate::pos::position::Position::from_fen(&self.current_fen);
-                let is_red_turn = position.current_player() == crate::pos::ChessPlayer::Red;
+                let is_red_turn = position.side_to_move() == crate::pos::piece::Color::Red;
                 
                 if is_red_turn {
                     self.red_time += elapsed;
@@ -650,6 +675,10 @@ impl HumanView {
             // 为新局面重新生成合法走法
             let new_position = crate::pos::position::Position::from_fen(&self.current_fen);
             let new_legal_moves = new_position.gen_legal_moves();
+            if new_legal_moves.is_empty() {
+                let reason = if new_position.check_mate() { "绝杀" } else { "困毙" };
+                self.game_status = format!("走法: {}, {}!", mv, reason);
+            }
             self.chess_board.set_legal_moves(new_legal_moves);
         }
         
@@ -662,7 +691,7 @@ impl HumanView {
         let legal_moves = position.gen_legal_moves();
         ui.label(format!("合法走法数: {}", legal_moves.len()));
         if let Some(selected) = self.chess_board.get_selected_square() {
-            ui.label(format!("选中格子: 0x{:02X} ({})", selected, selected));
+            ui.label(format!("选中格子: {}", selected));
         }
     }
 }
@@ -9,7 +9,8 @@ use resvg::usvg;
 use crate::fen::Fen;
 use crate::pos::position::Position;
 use crate::pos::moves::Move;
-use crate::pos::{file_x, rank_y};
+use crate::pos::piece::{Color, Piece};
+use crate::pos::square::Square;
 
 /// 棋盘模板 SVG 内容（嵌入在代码中）
 const BOARD_SVG: &str = include_str!("../bin/board.svg");
@@ -30,7 +31,7 @@ pub struct ChessBoardWidget {
     /// 当前显示的 FEN
     current_fen: Option<Fen>,
     /// 缓存的位置信息
-    cached_pieces: Vec<(char, u8)>,
+    cached_pieces: Vec<(Piece, Square)>,
     /// 棋盘纹理（从 SVG 渲染）
     board_texture: Option<TextureHandle>,
     /// 棋盘尺寸（像素）
@@ -38,7 +39,7 @@ pub struct ChessBoardWidget {
     /// 格子尺寸
     cell_size: f32,
     /// 选中的格子（如果有）
-    selected_square: Option<usize>,
+    selected_square: Option<Square>,
     /// 上一步走子（起点和终点）
     last_move: Option<Move>,
     /// 合法走法列表
@@ -76,7 +77,7 @@ impl ChessBoardWidget {
     }
 
     /// 获取当前选中的格子
-    pub fn get_selected_square(&self) -> Option<usize> {
+    pub fn get_selected_square(&self) -> Option<Square> {
         self.selected_square
     }
 
@@ -105,7 +106,7 @@ impl ChessBoardWidget {
 
             // 更新棋子缓存
             let position = Position::from_fen(fen);
-            self.cached_pieces = position.piece_loc();
+            self.cached_pieces = board_pieces(&position);
             self.current_fen = Some(fen.clone());
 
             // 清除旧纹理,强制重新创建
@@ -190,12 +191,12 @@ impl ChessBoardWidget {
                 log::debug!("Grid position: col={}, row={}", col, row);
 
                 if col >= 0 && col < 9 && row >= 0 && row < 10 {
-                    // 转换为内部坐标
-                    let sq = ((row + 3) << 4) | (col + 3);
-                    log::debug!("Square: 0x{:02X} ({})", sq, sq);
+                    // 行从上往下数, ICCS横线从红方底线往上数
+                    let sq = Square::from_coords(col as usize, 9 - row as usize).unwrap();
+                    log::debug!("Square: {}", sq);
 
                     // 处理点击
-                    let result = self.handle_click(sq as usize);
+                    let result = self.handle_click(sq);
 
                     // 如果选中状态改变，需要重新渲染以显示绿色圆圈
                     self.board_texture = None;
@@ -262,7 +263,7 @@ impl ChessBoardWidget {
             self.cell_size = final_width / 9.0;
 
             let position = Position::from_fen(fen);
-            self.cached_pieces = position.piece_loc();
+            self.cached_pieces = board_pieces(&position);
             self.current_fen = Some(fen.clone());
 
             // 清除旧纹理,强制重新创建
@@ -346,10 +347,10 @@ impl ChessBoardWidget {
                 log::debug!("Grid position: col={}, row={}", col, row);
 
                 if col >= 0 && col < 9 && row >= 0 && row < 10 {
-                    // 转换为内部坐标
-                    let sq = ((row + 3) << 4) | (col + 3);
-                    log::debug!("Square: 0x{:02X} ({})", sq, sq);
-                    return self.handle_click(sq as usize);
+                    // 行从上往下数, ICCS横线从红方底线往上数
+                    let sq = Square::from_coords(col as usize, 9 - row as usize).unwrap();
+                    log::debug!("Square: {}", sq);
+                    return self.handle_click(sq);
                 } else {
                     log::debug!("Click outside board");
                 }
@@ -440,39 +441,21 @@ impl ChessBoardWidget {
 
             log::info!("Generating {} pieces", self.cached_pieces.len());
 
-            for (piece_char, sq) in &self.cached_pieces {
-                if *sq > 0 {
-                    let px = file_x(*sq as usize);
-                    let py = rank_y(*sq as usize);
-
-                    // SVG 坐标：x = 25 + (px-3)*50, y = 25 + (py-3)*50
-                    let x = 25.0 + ((px - 3) as f32) * 50.0;
-                    let y = 25.0 + ((py - 3) as f32) * 50.0;
-
-                    // 根据棋子类型选择对应的模板
-                    let template = match *piece_char {
-                        'r' => "r", 'n' => "n", 'b' => "b", 'a' => "a",
-                        'k' => "k", 'c' => "c", 'p' => "p",
-                        'R' => "R", 'N' => "N", 'B' => "B", 'A' => "A",
-                        'K' => "K", 'C' => "C", 'P' => "P",
-                        _ => continue,
-                    };
-
-                    pieces_svg.push_str(&format!(
-                        "        <use href=\"#{}\" transform=\"translate({:.1},{:.1})\" />\n",
-                        template, x, y
-                    ));
-
-                    log::debug!("Piece {} at ({}, {}) -> SVG ({:.1}, {:.1})", piece_char, px, py, x, y);
-                }
+            for (piece, sq) in &self.cached_pieces {
+                let (x, y) = svg_coords(*sq);
+
+                // 模板 id 就是棋子的 FEN 字母
+                pieces_svg.push_str(&format!(
+                    "        <use href=\"#{}\" transform=\"translate({:.1},{:.1})\" />\n",
+                    piece, x, y
+                ));
+
+                log::debug!("Piece {} at {} -> SVG ({:.1}, {:.1})", piece, sq, x, y);
             }
 
             // 添加选中状态高亮
             if let Some(selected_sq) = self.selected_square {
-                let px = file_x(selected_sq);
-                let py = rank_y(selected_sq);
-                let x = 25.0 + ((px - 3) as f32) * 50.0;
-                let y = 25.0 + ((py - 3) as f32) * 50.0;
+                let (x, y) = svg_coords(selected_sq);
 
                 // 绘制选中高亮圆圈（半透明绿色）
                 pieces_svg.push_str(&format!(
@@ -484,10 +467,7 @@ impl ChessBoardWidget {
             // 添加上一步走子标记
             if let Some(last_mv) = self.last_move {
                 // 只在起点标记（白色小圆点）
-                let from_px = file_x(last_mv.from);
-                let from_py = rank_y(last_mv.from);
-                let from_x = 25.0 + ((from_px - 3) as f32) * 50.0;
-                let from_y = 25.0 + ((from_py - 3) as f32) * 50.0;
+                let (from_x, from_y) = svg_coords(last_mv.from());
 
                 pieces_svg.push_str(&format!(
                     "        <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"5\" fill=\"#FFFFFF\" opacity=\"0.8\" />\n",
@@ -530,7 +510,7 @@ impl ChessBoardWidget {
     }
 
     /// 处理点击事件
-    fn handle_click(&mut self, sq: usize) -> Option<Move> {
+    fn handle_click(&mut self, sq: Square) -> Option<Move> {
         // 安全检查：确保 current_fen 存在
         let fen = match &self.current_fen {
             Some(f) => f,
@@ -542,16 +522,14 @@ impl ChessBoardWidget {
 
         // 获取当前位置的棋子信息
         let position = Position::from_fen(fen);
-        let piece_locs = position.piece_loc();
 
-        log::debug!("Click at square: {} (0x{:02X})", sq, sq);
-        log::debug!("Total pieces: {}", piece_locs.len());
+        log::debug!("Click at square: {}", sq);
 
         // 检查点击的位置是否有棋子
-        let clicked_piece = piece_locs.iter().find(|(_, s)| *s as usize == sq);
+        let clicked_piece = position.piece_at(sq);
 
-        if let Some((piece_char, _)) = clicked_piece {
-            log::debug!("Clicked on piece: {}", piece_char);
+        if let Some(piece) = clicked_piece {
+            log::debug!("Clicked on piece: {}", piece);
         } else {
             log::debug!("Clicked on empty square");
         }
@@ -568,7 +546,7 @@ impl ChessBoardWidget {
             }
 
             // 检查是否是合法走法
-            if let Some(mv) = self.legal_moves.iter().find(|m| m.from == selected && m.to as usize == sq) {
+            if let Some(mv) = self.legal_moves.iter().find(|m| m.from() == selected && m.to() == sq) {
                 log::debug!("Valid move found: {:?}", mv);
                 self.selected_square = None;
                 return Some(*mv);
@@ -577,15 +555,10 @@ impl ChessBoardWidget {
             log::debug!("Not a valid move, checking if it's own piece");
 
             // 如果点击的是己方棋子，则重新选择
-            if let Some((piece_char, _)) = clicked_piece {
-                // 通过 FEN 字符串判断当前走棋方
-                let fen_str = fen.fen_str();
-                let is_red_turn = fen_str.contains(" w "); // "w" 表示红方走棋
-                let is_red_piece = piece_char.is_ascii_uppercase();
-
-                log::debug!("Is red turn: {}, is red piece: {}", is_red_turn, is_red_piece);
+            if let Some(piece) = clicked_piece {
+                log::debug!("Side to move: {:?}, piece: {:?}", position.side_to_move(), piece.color);
 
-                if is_red_turn == is_red_piece {
+                if piece.color == position.side_to_move() {
                     log::debug!("Selecting new piece");
                     self.selected_square = Some(sq);
                     return None;
@@ -597,16 +570,11 @@ impl ChessBoardWidget {
             self.selected_square = None;
         } else {
             // 没有选中的棋子，如果点击的是己方棋子则选择
-            if let Some((piece_char, _)) = clicked_piece {
-                // 通过 FEN 字符串判断当前走棋方
-                let fen_str = fen.fen_str();
-                let is_red_turn = fen_str.contains(" w "); // "w" 表示红方走棋
-                let is_red_piece = piece_char.is_ascii_uppercase();
+            if let Some(piece) = clicked_piece {
+                log::debug!("First click - Side to move: {:?}, piece: {:?}", position.side_to_move(), piece.color);
 
-                log::debug!("First click - Is red turn: {}, is red piece: {}", is_red_turn, is_red_piece);
-
-                if is_red_turn == is_red_piece {
-                    log::debug!("Selecting piece: {}", piece_char);
+                if piece.color == position.side_to_move() {
+                    log::debug!("Selecting piece: {}", piece);
                     self.selected_square = Some(sq);
                 } else {
                     log::debug!("Not your piece to move");
@@ -624,7 +592,7 @@ impl ChessBoardWidget {
 
     fn generate_board_svg(&self, fen: &Fen) -> String {
         let position = Position::from_fen(fen);
-        let piece_locs = position.piece_loc();
+        let piece_locs = board_pieces(&position);
 
         let mut svg = String::new();
 
@@ -700,50 +668,46 @@ impl ChessBoardWidget {
         svg.push_str("</g>");
 
         // 绘制棋子
-        for (piece_char, sq) in &piece_locs {
-            if *sq > 0 {
-                let x = file_x(*sq as usize);
-                let y = rank_y(*sq as usize);
-                let pixel_x = 27.0 + (x - 3) as f32 * self.cell_size;
-                let pixel_y = 27.0 + (y - 3) as f32 * self.cell_size;
-
-                let is_red = piece_char.is_ascii_uppercase();
-                let (fill, stroke, text_color, piece_name) = if is_red {
-                    ("#fff", "#c00", "#c00", self.get_piece_name(*piece_char, true))
-                } else {
-                    ("#fff", "#000", "#000", self.get_piece_name(*piece_char, false))
-                };
-
-                // 检查是否是选中的棋子
-                let is_selected = self.selected_square.map_or(false, |s| s == *sq as usize);
-                let stroke_width = if is_selected { 4.0 } else { 2.0 };
-                let radius = if is_selected { 23.0 } else { 21.0 };
-
-                svg.push_str("<g filter=\"url(#shadow)\">\n");
-                svg.push_str("  <circle cx=\"");
-                svg.push_str(&format!("{:.1}", pixel_x));
-                svg.push_str("\" cy=\"");
-                svg.push_str(&format!("{:.1}", pixel_y));
-                svg.push_str("\" r=\"");
-                svg.push_str(&format!("{:.1}", radius));
-                svg.push_str("\" fill=\"");
-                svg.push_str(fill);
-                svg.push_str("\" stroke=\"");
-                svg.push_str(stroke);
-                svg.push_str("\" stroke-width=\"");
-                svg.push_str(&format!("{:.1}", stroke_width));
-                svg.push_str("\"/>\n");
-                svg.push_str("  <text x=\"");
-                svg.push_str(&format!("{:.1}", pixel_x));
-                svg.push_str("\" y=\"");
-                svg.push_str(&format!("{:.1}", pixel_y + 1.0));
-                svg.push_str("\" font-family=\"楷体, KaiTi, serif\" font-size=\"28\" font-weight=\"bold\" fill=\"");
-                svg.push_str(text_color);
-                svg.push_str("\" text-anchor=\"middle\" dominant-baseline=\"central\">");
-                svg.push_str(piece_name);
-                svg.push_str("</text>\n");
-                svg.push_str("</g>\n");
-            }
+        for (piece, sq) in &piece_locs {
+            let pixel_x = 27.0 + sq.file() as f32 * self.cell_size;
+            let pixel_y = 27.0 + (9 - sq.rank()) as f32 * self.cell_size;
+
+            let is_red = piece.color == Color::Red;
+            let (fill, stroke, text_color, piece_name) = if is_red {
+                ("#fff", "#c00", "#c00", self.get_piece_name(piece.to_char(), true))
+            } else {
+                ("#fff", "#000", "#000", self.get_piece_name(piece.to_char(), false))
+            };
+
+            // 检查是否是选中的棋子
+            let is_selected = self.selected_square == Some(*sq);
+            let stroke_width = if is_selected { 4.0 } else { 2.0 };
+            let radius = if is_selected { 23.0 } else { 21.0 };
+
+            svg.push_str("<g filter=\"url(#shadow)\">\n");
+            svg.push_str("  <circle cx=\"");
+            svg.push_str(&format!("{:.1}", pixel_x));
+            svg.push_str("\" cy=\"");
+            svg.push_str(&format!("{:.1}", pixel_y));
+            svg.push_str("\" r=\"");
+            svg.push_str(&format!("{:.1}", radius));
+            svg.push_str("\" fill=\"");
+            svg.push_str(fill);
+            svg.push_str("\" stroke=\"");
+            svg.push_str(stroke);
+            svg.push_str("\" stroke-width=\"");
+            svg.push_str(&format!("{:.1}", stroke_width));
+            svg.push_str("\"/>\n");
+            svg.push_str("  <text x=\"");
+            svg.push_str(&format!("{:.1}", pixel_x));
+            svg.push_str("\" y=\"");
+            svg.push_str(&format!("{:.1}", pixel_y + 1.0));
+            svg.push_str("\" font-family=\"楷体, KaiTi, serif\" font-size=\"28\" font-weight=\"bold\" fill=\"");
+            svg.push_str(text_color);
+            svg.push_str("\" text-anchor=\"middle\" dominant-baseline=\"central\">");
+            svg.push_str(piece_name);
+            svg.push_str("</text>\n");
+            svg.push_str("</g>\n");
         }
 
         svg.push_str("</svg>");
@@ -806,3 +770,15 @@ impl ChessBoardWidget {
         "?"
     }
 }
+
+/// 棋盘上的所有棋子及其所在的格子
+fn board_pieces(position: &Position) -> Vec<(Piece, Square)> {
+    Square::all()
+        .filter_map(|sq| position.piece_at(sq).map(|piece| (piece, sq)))
+        .collect()
+}
+
+/// 格子中心在 board.svg 中的坐标：左上角 (25,25)，每格 50px
+fn svg_coords(sq: Square) -> (f32, f32) {
+    (25.0 + sq.file() as f32 * 50.0, 25.0 + (9 - sq.rank()) as f32 * 50.0)
+}
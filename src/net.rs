@@ -76,6 +76,9 @@ pub struct Net<B: Backend> {
 pub struct NetConfig {
     num_classes: usize,
     num_res_blocks: usize,
+    /// 输入平面数, 与训练时使用的特征编码(`FeatureEncoder::planes`)一致
+    #[config(default = "INPUT_PLANES")]
+    input_planes: usize,
 }
 
 impl NetConfig {
@@ -86,7 +89,7 @@ impl NetConfig {
             res_blocks.push(ResBlockConfig::new(self.num_classes).init(device));
         }
         Net {
-            conv_block: Conv2dConfig::new([INPUT_PLANES, self.num_classes], [3, 3])
+            conv_block: Conv2dConfig::new([self.input_planes, self.num_classes], [3, 3])
                 .with_padding(PaddingConfig2d::Same)
                 .init(device),
             conv_block_bn: BatchNormConfig::new(self.num_classes).init(device),
@@ -0,0 +1,235 @@
+use crate::pos::piece::{Color, Piece, PieceKind};
+use crate::pos::position::Position;
+use crate::pos::square::Square;
+
+/// 子力价值, 按帅仕相马车炮兵排列, 与`PieceKind::value`成比例
+pub const MATERIAL: [i32; 7] = [0, 200, 200, 400, 900, 450, 100];
+
+// 每个伪合法着法的机动性分数, 只算马车炮
+const MOBILITY: [i32; 7] = [0, 0, 0, 6, 3, 2, 0];
+
+// 对方每有一个进攻子力(车马炮和过河兵), 缺一个仕(士)、相(象)的扣分
+const MISSING_ADVISOR: i32 = 12;
+const MISSING_BISHOP: i32 = 8;
+// 九宫里每个被对方攻击的格子的扣分
+const PALACE_ATTACK: i32 = 8;
+
+type Table = [[i32; 9]; 10];
+
+/// 红方的位置分, 第0行是黑方底线(与FEN的顺序一致), 左右对称; 黑方上下翻转后使用
+#[rustfmt::skip]
+const PST: [Table; 7] = [
+    // 帅(将): 待在底线中间
+    [
+        [0; 9], [0; 9], [0; 9], [0; 9], [0; 9], [0; 9], [0; 9],
+        [0, 0, 0, -16, -14, -16, 0, 0, 0],
+        [0, 0, 0,  -8,  -6,  -8, 0, 0, 0],
+        [0, 0, 0,   0,  10,   0, 0, 0, 0],
+    ],
+    // 仕(士): 上到中间
+    [
+        [0; 9], [0; 9], [0; 9], [0; 9], [0; 9], [0; 9], [0; 9],
+        [0, 0, 0, -4, 0, -4, 0, 0, 0],
+        [0, 0, 0,  0, 6,  0, 0, 0, 0],
+        [0, 0, 0,  0, 0,  0, 0, 0, 0],
+    ],
+    // 相(象): 中相最好, 边相略差
+    [
+        [0; 9], [0; 9], [0; 9], [0; 9], [0; 9],
+        [0, 0, -2, 0, 0, 0, -2, 0, 0],
+        [0; 9],
+        [-4, 0, 0, 0, 6, 0, 0, 0, -4],
+        [0; 9],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0],
+    ],
+    // 马: 往中间和前面跳, 别待在边上和底线的马位
+    [
+        [4,  8, 16, 12,  4, 12, 16,  8, 4],
+        [4, 10, 28, 16,  8, 16, 28, 10, 4],
+        [12, 14, 16, 20, 18, 20, 16, 14, 12],
+        [8, 24, 18, 24, 20, 24, 18, 24, 8],
+        [6, 16, 14, 18, 16, 18, 14, 16, 6],
+        [4, 12, 16, 14, 12, 14, 16, 12, 4],
+        [2,  6,  8,  6, 10,  6,  8,  6, 2],
+        [4,  2,  8,  8,  4,  8,  8,  2, 4],
+        [0,  2,  4,  4, -2,  4,  4,  2, 0],
+        [0, -4,  0,  0,  0,  0,  0, -4, 0],
+    ],
+    // 车: 占肋道和对方的卒林、下二路
+    [
+        [14, 14, 12, 18, 16, 18, 12, 14, 14],
+        [16, 20, 18, 24, 26, 24, 18, 20, 16],
+        [12, 12, 12, 18, 18, 18, 12, 12, 12],
+        [12, 18, 16, 22, 22, 22, 16, 18, 12],
+        [12, 14, 12, 18, 18, 18, 12, 14, 12],
+        [12, 16, 14, 20, 20, 20, 14, 16, 12],
+        [6, 10,  8, 14, 14, 14,  8, 10, 6],
+        [4,  8,  6, 14, 12, 14,  6,  8, 4],
+        [8,  4,  8, 16,  8, 16,  8,  4, 8],
+        [-2, 10, 6, 14, 12, 14,  6, 10, -2],
+    ],
+    // 炮: 中炮和巡河炮, 别冲到对方九宫里
+    [
+        [6, 4,  0, -10, -12, -10,  0, 4, 6],
+        [2, 2,  0,  -4, -14,  -4,  0, 2, 2],
+        [2, 2,  0, -10,  -8, -10,  0, 2, 2],
+        [0, 0, -2,   4,  10,   4, -2, 0, 0],
+        [0, 0,  0,   2,   8,   2,  0, 0, 0],
+        [-2, 0, 4,   2,   6,   2,  4, 0, -2],
+        [0, 0,  0,   2,   4,   2,  0, 0, 0],
+        [4, 0,  8,   6,  10,   6,  8, 0, 4],
+        [0, 2,  4,   6,   6,   6,  4, 2, 0],
+        [0, 0,  2,   6,   6,   6,  2, 0, 0],
+    ],
+    // 兵(卒): 过河后越靠近九宫越好, 到底线就没用了
+    [
+        [0,  3,  6,  9,  12,  9,  6,  3,  0],
+        [18, 36, 56, 80, 120, 80, 56, 36, 18],
+        [14, 26, 42, 60,  80, 60, 42, 26, 14],
+        [10, 20, 30, 34,  40, 34, 30, 20, 10],
+        [6, 12, 18, 18,  20, 18, 18, 12,  6],
+        [2,  0,  8,  0,   8,  0,  8,  0,  2],
+        [0,  0, -2,  0,   4,  0, -2,  0,  0],
+        [0; 9], [0; 9], [0; 9],
+    ],
+];
+
+/// 棋子在某个格子上的位置分
+pub fn piece_square(piece: Piece, sq: Square) -> i32 {
+    let (row, col) = match piece.color {
+        Color::Red => (9 - sq.rank(), sq.file()),
+        Color::Black => (sq.rank(), 8 - sq.file()),
+    };
+    PST[piece.kind as usize][row][col]
+}
+
+impl Position {
+    /// 手写的静态评估: 子力、位置分、机动性和帅(将)的安全, 以兵为100;
+    /// 从走棋方的角度看, 正数表示走棋方占优. 不判断将死和困毙
+    pub fn evaluate(&self) -> i32 {
+        let score = side_score(self, Color::Red) - side_score(self, Color::Black);
+        match self.side_to_move() {
+            Color::Red => score,
+            Color::Black => -score,
+        }
+    }
+}
+
+/// 一方的得分: 子力和位置分、机动性, 减去帅(将)的安全扣分
+fn side_score(position: &Position, color: Color) -> i32 {
+    let mut score = 0;
+    for sq in Square::all() {
+        match position.piece_at(sq) {
+            Some(piece) if piece.color == color => score += MATERIAL[piece.kind as usize] + piece_square(piece, sq),
+            _ => {}
+        }
+    }
+    for mv in position.pseudo_moves_of(color).iter() {
+        score += mv.piece().map_or(0, |piece| MOBILITY[piece.kind as usize]);
+    }
+    score - king_danger(position, color)
+}
+
+/// 帅(将)的危险程度: 对方进攻子力越多, 缺仕(士)相(象)越危险; 九宫里被对方攻击的格子也扣分
+fn king_danger(position: &Position, color: Color) -> i32 {
+    let Some(king) = position.king_square(color) else {
+        return 0;
+    };
+    let opponent = color.opponent();
+    let own_half = |sq: &Square| match color {
+        Color::Red => sq.rank() <= 4,
+        Color::Black => sq.rank() >= 5,
+    };
+    let attackers = [PieceKind::Rook, PieceKind::Knight, PieceKind::Cannon]
+        .into_iter()
+        .map(|kind| position.pieces(opponent, kind).len())
+        .sum::<usize>()
+        + position.pieces(opponent, PieceKind::Pawn).iter().filter(|sq| own_half(sq)).count();
+
+    let missing_advisors = 2 - position.pieces(color, PieceKind::Advisor).len();
+    let missing_bishops = 2 - position.pieces(color, PieceKind::Bishop).len();
+    let shortage = missing_advisors as i32 * MISSING_ADVISOR + missing_bishops as i32 * MISSING_BISHOP;
+
+    // 九宫的3x3个格子, 横线取帅(将)所在的一边
+    let bottom = if king.rank() <= 2 { 0 } else { 7 };
+    let attacked = (3..=5)
+        .flat_map(|file| (bottom..bottom + 3).map(move |rank| (file, rank)))
+        .filter_map(|(file, rank)| Square::from_coords(file, rank))
+        .filter(|&sq| position.is_attacked(sq, opponent))
+        .count();
+
+    shortage * attackers.min(4) as i32 / 4 + attacked as i32 * PALACE_ATTACK
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{piece_square, PST};
+    use crate::fen::Fen;
+    use crate::piece::{Color, Piece, PieceKind};
+    use crate::position::Position;
+    use crate::square::Square;
+
+    /// 把局面旋转180度并交换红黑, 评估应该不变
+    fn flip(fen: &str) -> String {
+        let mut fields = fen.split(' ');
+        let board: Vec<String> = fields
+            .next()
+            .unwrap()
+            .split('/')
+            .rev()
+            .map(|row| {
+                row.chars()
+                    .rev()
+                    .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() })
+                    .collect()
+            })
+            .collect();
+        let side = if fields.next() == Some("w") { "b" } else { "w" };
+        format!("{} {}", board.join("/"), side)
+    }
+
+    #[test]
+    fn test_tables_symmetric() {
+        for table in PST {
+            for row in table {
+                assert!((0..9).all(|x| row[x] == row[8 - x]));
+            }
+        }
+        let red = Piece::new(Color::Red, PieceKind::Pawn);
+        let black = Piece::new(Color::Black, PieceKind::Pawn);
+        let e6: Square = "e6".parse().unwrap();
+        let e3: Square = "e3".parse().unwrap();
+        assert_eq!(piece_square(red, e6), piece_square(black, e3));
+        assert!(piece_square(red, e6) > piece_square(red, e3));
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w")]
+    #[case("r1bakab1r/9/1cn4cn/p1p1p1p1p/9/2P6/P3P1P1P/1CN1C1N2/9/R1BAKAB1R w")]
+    #[case("4k4/9/3n5/2P1N4/4p4/3N5/2n6/9/4A4/3AK4 w")]
+    #[case("3k5/9/9/4c4/9/9/4C4/9/9/3AKA3 b")]
+    fn test_evaluate_symmetric(#[case] fen: &str) {
+        let position = Position::from_fen(&Fen::new(fen));
+        let flipped = Position::from_fen(&Fen::new(&flip(fen)));
+        assert_eq!(position.evaluate(), flipped.evaluate());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let position = Position::from_fen(&Fen::init());
+        assert_eq!(position.evaluate(), 0);
+
+        // 多一个车, 走棋方换了以后分数取反
+        let red = Position::from_fen(&Fen::new("rnbakabn1/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w"));
+        let black = Position::from_fen(&Fen::new("rnbakabn1/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b"));
+        assert!(red.evaluate() > 800);
+        assert_eq!(red.evaluate(), -black.evaluate());
+
+        // 过河兵比没过河的兵值钱
+        let advanced = Position::from_fen(&Fen::new("4k4/9/9/4P4/9/9/9/9/9/4K4 w"));
+        let home = Position::from_fen(&Fen::new("4k4/9/9/9/9/9/4P4/9/9/4K4 w"));
+        assert!(advanced.evaluate() > home.evaluate());
+    }
+}
@@ -1,24 +1,159 @@
 use std::fmt::Display;
-use crate::pos::{coord_xy, FILE_LEFT, FILE_RIGHT, RANK_BOTTOM, RANK_TOP};
+use std::str::FromStr;
+use crate::pos::{coord_xy, FILE_LEFT, RANK_TOP};
+use crate::pos::position::{Position, PositionError};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
-pub struct Fen(&'static  str);
+/// FEN局面串. 用`parse`(FromStr)构造时会做完整校验(包括局面是否合法), 并补齐省略的字段,
+/// 得到"棋盘 走棋方 - - 无吃子步数 回合数"的标准形式
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fen(String);
 
 const PIECE_TYPES: &str = "KABNRCPkabnrcp";
 const INITIAL_FEN: &str = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
+// 每方各种棋子的数量上限, 顺序同PIECE_TYPES
+const MAX_PIECES: [usize; 7] = [1, 2, 2, 2, 2, 2, 5];
+
+/// FEN解析错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    /// 空串
+    Empty,
+    /// 行数不是10
+    BadRankCount(usize),
+    /// 某一行(从上往下, 从0开始)的格子数不是9
+    BadRankLength { rank: usize, len: usize },
+    /// 不认识的棋子字母
+    BadPiece(char),
+    /// 某种棋子超过了数量上限
+    TooManyPieces(char),
+    /// 缺少帅(K)或将(k)
+    MissingKing(char),
+    /// 走棋方不是w或b
+    BadSide(String),
+    /// 无吃子步数或回合数不是数字
+    BadCounter(String),
+    /// 格式正确, 但局面不可能在对局中出现
+    IllegalPosition(PositionError),
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "FEN为空"),
+            FenError::BadRankCount(n) => write!(f, "FEN应有10行, 实际有{}行", n),
+            FenError::BadRankLength { rank, len } => {
+                write!(f, "FEN第{}行应有9格, 实际有{}格", rank + 1, len)
+            }
+            FenError::BadPiece(ch) => write!(f, "FEN中有不认识的棋子'{}'", ch),
+            FenError::TooManyPieces(ch) => write!(f, "FEN中棋子'{}'的数量超过上限", ch),
+            FenError::MissingKing(ch) => write!(f, "FEN中缺少'{}'", ch),
+            FenError::BadSide(side) => write!(f, "FEN中的走棋方'{}'应为w或b", side),
+            FenError::BadCounter(n) => write!(f, "FEN中的步数'{}'不是数字", n),
+            FenError::IllegalPosition(err) => write!(f, "FEN局面不合法: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
 
 impl Fen {
-    pub fn new(fen: &'static str) -> Self {
-        Self(fen)
+    /// 直接包装FEN串, 不做校验
+    pub fn new(fen: impl Into<String>) -> Self {
+        Self(fen.into())
     }
     pub fn init() -> Self {
-        Self(INITIAL_FEN)
+        Self(INITIAL_FEN.to_string())
     }
 
     pub fn fen_str(&self) -> &str {
         &self.0
     }
+
+    /// 棋盘上的所有棋子及其所在的格子
+    pub fn pieces(&self) -> Vec<(char, u8)> {
+        let mut piece_locs = vec![];
+        let board = self.0.split_whitespace().next().unwrap_or("");
+        for (i, rank) in board.split('/').enumerate() {
+            let mut j = 0;
+            for ch in rank.chars() {
+                if let Some(n) = ch.to_digit(10) {
+                    j += n as usize;
+                } else {
+                    piece_locs.push((ch, coord_xy(FILE_LEFT + j, RANK_TOP + i) as u8));
+                    j += 1;
+                }
+            }
+        }
+        piece_locs
+    }
 }
+
+/// 棋子字母对应PIECE_TYPES中的序号, 兼容ICCS的E(相)和H(马)
+fn piece_index(ch: char) -> Option<usize> {
+    let upper = match ch.to_ascii_uppercase() {
+        'E' => 'B',
+        'H' => 'N',
+        c => c,
+    };
+    let idx = PIECE_TYPES.find(upper)?;
+    Some(if ch.is_ascii_lowercase() { idx + 7 } else { idx })
+}
+
+impl FromStr for Fen {
+    type Err = FenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let board = fields.next().ok_or(FenError::Empty)?;
+
+        let ranks: Vec<&str> = board.split('/').collect();
+        if ranks.len() != 10 {
+            return Err(FenError::BadRankCount(ranks.len()));
+        }
+        let mut counts = [0usize; 14];
+        for (rank, line) in ranks.iter().enumerate() {
+            let mut len = 0;
+            for ch in line.chars() {
+                match ch.to_digit(10) {
+                    Some(n) if n > 0 => len += n as usize,
+                    _ => {
+                        let idx = piece_index(ch).ok_or(FenError::BadPiece(ch))?;
+                        counts[idx] += 1;
+                        if counts[idx] > MAX_PIECES[idx % 7] {
+                            return Err(FenError::TooManyPieces(ch));
+                        }
+                        len += 1;
+                    }
+                }
+            }
+            if len != 9 {
+                return Err(FenError::BadRankLength { rank, len });
+            }
+        }
+        for king in ['K', 'k'] {
+            if counts[piece_index(king).unwrap()] == 0 {
+                return Err(FenError::MissingKing(king));
+            }
+        }
+
+        let side = match fields.next() {
+            None | Some("w") => "w",
+            Some("b") => "b",
+            Some(side) => return Err(FenError::BadSide(side.to_string())),
+        };
+        // 第3、4个字段在象棋中不用, 固定为"-"
+        let mut counters = fields.skip(2).map(|n| {
+            n.parse::<u32>().map_err(|_| FenError::BadCounter(n.to_string()))
+        });
+        let halfmove = counters.next().transpose()?.unwrap_or(0);
+        let fullmove = counters.next().transpose()?.unwrap_or(1);
+
+        let fen = Fen(format!("{} {} - - {} {}", board, side, halfmove, fullmove));
+        Position::from_fen(&fen).validate().map_err(FenError::IllegalPosition)?;
+        Ok(fen)
+    }
+}
+
 impl Display for Fen {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -27,36 +162,73 @@ impl Display for Fen {
 
 impl Default for Fen {
     fn default() -> Self {
-        Self::init()    
+        Self::init()
     }
 }
 
-pub fn fen2_coords(fen: &str) -> Vec<(char, u8)> {
-    let mut piece_locs = vec![];
-    let mut i = RANK_TOP;
-    let mut j = FILE_LEFT;
+/// 校验FEN并返回所有棋子及其所在的格子
+pub fn fen2_coords(fen: &str) -> Result<Vec<(char, u8)>, FenError> {
+    Ok(fen.parse::<Fen>()?.pieces())
+}
 
-    let mut lp_fen: String = fen.split(" ").collect();
-    let first_space_index = fen.find(' ').unwrap_or(fen.len());
-    let mut first = &fen[..first_space_index]; //取fen的第一段
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
 
-    for ch in first.chars() {
-        if ch == '/' {
-            j = FILE_LEFT;
-            i += 1;
-            if i > RANK_BOTTOM {
-                break;
-            }
-        } else if ch.is_ascii_digit() {
-            j += ch.to_digit(10).unwrap() as usize; //如果是数字, 则向前滑动j值
-        } else if PIECE_TYPES.contains(ch) {
-            if j <= FILE_RIGHT {
-                piece_locs.push((ch, coord_xy(j, i) as u8));
-            }
-            j += 1;
-        } else { // 不认识的字符, 直接跳过, 这里是否合适?
-            j += 1;
-        }
+    use crate::fen::{Fen, FenError};
+    use crate::piece::{Color, Piece};
+    use crate::position::{Position, PositionError};
+    use crate::square::Square;
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1")]
+    #[case("3k5/9/9/9/9/9/9/9/3Rp4/3K5 b - - 7 30")]
+    fn test_round_trip(#[case] fen: &str) {
+        let parsed: Fen = fen.parse().unwrap();
+        assert_eq!(parsed.fen_str(), fen);
+        assert_eq!(Position::from_fen(&parsed).to_fen(), parsed);
+    }
+
+    #[test]
+    fn test_fill_missing_fields() {
+        let fen: Fen = "3k5/9/9/9/9/9/9/9/9/4K4 b".parse().unwrap();
+        assert_eq!(fen.fen_str(), "3k5/9/9/9/9/9/9/9/9/4K4 b - - 0 1");
     }
-    piece_locs
-}
\ No newline at end of file
+
+    #[rstest]
+    #[case("", FenError::Empty)]
+    #[case("4k4/9/9/9/9/9/9/9/4K4 w", FenError::BadRankCount(9))]
+    #[case("4k4/9/9/9/9/9/9/9/8/4K4 w", FenError::BadRankLength { rank: 8, len: 8 })]
+    #[case("4k4/9/9/9/9/9/9/9/4X4/4K4 w", FenError::BadPiece('X'))]
+    #[case("4k4/9/9/9/9/9/9/9/PPPPPP3/4K4 w", FenError::TooManyPieces('P'))]
+    #[case("9/9/9/9/9/9/9/9/9/4K4 w", FenError::MissingKing('k'))]
+    #[case("4k4/9/9/9/9/9/9/9/9/4K4 x", FenError::BadSide("x".to_string()))]
+    #[case("4k4/9/9/9/9/9/9/9/9/4K4 w - - a 1", FenError::BadCounter("a".to_string()))]
+    fn test_parse_errors(#[case] fen: &str, #[case] expected: FenError) {
+        assert_eq!(fen.parse::<Fen>(), Err(expected));
+    }
+
+    #[rstest]
+    // 仕在九宫外
+    #[case("4k4/9/9/9/9/9/9/9/9/A3K4 w", PositionError::BadPlacement(Piece::from_char('A').unwrap(), Square::from_coords(0, 0).unwrap()))]
+    // 仕在九宫内但不在斜线上
+    #[case("4k4/9/9/9/9/9/9/9/9/3KA4 w", PositionError::BadPlacement(Piece::from_char('A').unwrap(), Square::from_coords(4, 0).unwrap()))]
+    // 象不在相位上
+    #[case("4k4/9/3b5/9/9/9/9/9/9/4K4 w", PositionError::BadPlacement(Piece::from_char('b').unwrap(), Square::from_coords(3, 7).unwrap()))]
+    // 相过河
+    #[case("4k4/9/9/9/2B6/9/9/9/9/4K4 w", PositionError::BadPlacement(Piece::from_char('B').unwrap(), Square::from_coords(2, 5).unwrap()))]
+    // 兵在初始位置后面
+    #[case("4k4/9/9/9/9/9/9/P8/9/4K4 w", PositionError::BadPlacement(Piece::from_char('P').unwrap(), Square::from_coords(0, 2).unwrap()))]
+    // 没过河的卒不在兵线的纵线上
+    #[case("4k4/9/9/1p7/9/9/9/9/9/4K4 w", PositionError::BadPlacement(Piece::from_char('p').unwrap(), Square::from_coords(1, 6).unwrap()))]
+    // 帅出九宫
+    #[case("4k4/9/9/9/9/9/9/9/9/2K6 w", PositionError::BadPlacement(Piece::from_char('K').unwrap(), Square::from_coords(2, 0).unwrap()))]
+    // 帅将照面, 红方走棋可以直接吃将
+    #[case("4k4/9/9/9/9/9/9/9/9/4K4 w", PositionError::OpponentInCheck(Color::Black))]
+    // 黑方走棋, 红方却被车将军
+    #[case("3k5/9/9/9/9/9/9/9/9/r3K4 b", PositionError::OpponentInCheck(Color::Red))]
+    fn test_illegal_positions(#[case] fen: &str, #[case] expected: PositionError) {
+        assert_eq!(fen.parse::<Fen>(), Err(FenError::IllegalPosition(expected)));
+        assert_eq!(Position::from_fen(&Fen::new(fen)).validate(), Err(expected));
+    }
+}
@@ -2,9 +2,16 @@
 #![allow(unused, dead_code)]
 #![allow(warnings)]
 
+pub mod eval;
 pub mod fen;
+pub mod movelist;
 pub mod moves;
+pub mod piece;
 pub mod position;
+pub mod search;
+pub mod square;
+pub mod tablebase;
+pub mod tt;
 mod pregen;
 
 pub const RANK_TOP: usize = 3;
@@ -257,10 +264,11 @@ pub(crate) const CANNON_TO: usize = 10;
 pub(crate) const PAWN_FROM: usize = 11;
 pub(crate) const PAWN_TO: usize = 15;
 
-const REP_NONE: usize = 0;
-const REP_DRAW: usize = 1;
-const REP_LOSS: usize = 3;
-const REP_WIN: usize = 5;
+// 重复局面的判定结果, 输赢都是对走棋方而言
+pub(crate) const REP_NONE: usize = 0;
+pub(crate) const REP_DRAW: usize = 1;
+pub(crate) const REP_LOSS: usize = 3;
+pub(crate) const REP_WIN: usize = 5;
 
 const KING_BITPIECE: u32 = 1 << KING_FROM;
 const ADVISOR_BITPIECE: u32 = (1 << ADVISOR_FROM) | (1 << ADVISOR_TO);
@@ -294,21 +302,14 @@ fn both_bitpiece(n_bitpiece: u32) -> u32 {
 pub(crate) fn side_tag(sd: usize) -> usize {
     16 + (sd << 4)
 }
-use serde::{Deserialize, Serialize};
 use toto::Toi32;
 
-// By default, player is Red, and computer is Black.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
-#[derive(Clone, Copy, Eq, Hash)]
-pub(crate) enum ChessPlayer {
-    Red,
-    Black,
-}
+use crate::pos::piece::Color;
 
-fn side_tag2(sd: &ChessPlayer) -> u8 {
+fn side_tag2(sd: &Color) -> u8 {
     match sd {
-        ChessPlayer::Red => 16,
-        ChessPlayer::Black => 32,
+        Color::Red => 16,
+        Color::Black => 32,
     }
 }
 
@@ -316,10 +317,10 @@ fn opp_side_tag(sd: usize) -> usize {
     32 - (sd << 4)
 }
 
-fn opp_side_tag2(sd: &ChessPlayer) -> usize {
+fn opp_side_tag2(sd: &Color) -> usize {
     match sd {
-        ChessPlayer::Red => 32,
-        ChessPlayer::Black => 16,
+        Color::Red => 32,
+        Color::Black => 16,
     }
 }
 
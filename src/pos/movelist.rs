@@ -0,0 +1,168 @@
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+
+use crate::pos::moves::Move;
+
+/// 着法列表的容量. 中国象棋一方最多约120个伪合法着法(双车双炮各17个, 马8个, 兵15个, 仕相帅16个)
+pub const MAX_MOVES: usize = 128;
+
+/// 定长的着法列表, 放在栈上, 生成着法时不分配堆内存
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList { moves: [Move::default(); MAX_MOVES], len: 0 }
+    }
+
+    /// 追加一个着法, 超过容量时panic
+    pub fn push(&mut self, mv: Move) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// 只保留前len个着法
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    /// 只保留满足条件的着法, 保持原有顺序
+    pub fn retain(&mut self, mut f: impl FnMut(&Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            let mv = self.moves[i];
+            if f(&mv) {
+                self.moves[kept] = mv;
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl Debug for MoveList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for MoveList {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Extend<Move> for MoveList {
+    fn extend<T: IntoIterator<Item = Move>>(&mut self, iter: T) {
+        for mv in iter {
+            self.push(mv);
+        }
+    }
+}
+
+impl FromIterator<Move> for MoveList {
+    fn from_iter<T: IntoIterator<Item = Move>>(iter: T) -> Self {
+        let mut moves = MoveList::new();
+        moves.extend(iter);
+        moves
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter { list: self, index: 0 }
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// 按值遍历`MoveList`, 也是`CChess`的动作迭代器
+#[derive(Debug, Clone)]
+pub struct IntoIter {
+    list: MoveList,
+    index: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        let mv = self.list.get(self.index).copied();
+        self.index += mv.is_some() as usize;
+        mv
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.list.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+#[cfg(test)]
+mod tests {
+    use super::MoveList;
+    use crate::fen::Fen;
+    use crate::moves::Move;
+    use crate::position::Position;
+
+    #[test]
+    fn test_move_list() {
+        let position = Position::from_fen(&Fen::init());
+        let all: Vec<Move> =
+            ["h2e2", "b0c2", "a0a1", "e3e4"].iter().map(|s| Move::from_iccs(&position, s).unwrap()).collect();
+        let mut moves: MoveList = all.iter().copied().collect();
+        assert_eq!(moves.len(), 4);
+        assert_eq!(&moves[..], &all[..]);
+
+        moves.retain(|mv| mv.to_iccs() != "b0c2");
+        assert_eq!(moves.iter().map(Move::to_iccs).collect::<Vec<_>>(), ["h2e2", "a0a1", "e3e4"]);
+
+        let mut iter = moves.clone().into_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next().map(|mv| mv.to_iccs()), Some("h2e2".to_string()));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.count(), 2);
+
+        moves.clear();
+        assert!(moves.is_empty());
+    }
+}
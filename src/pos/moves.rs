@@ -1,5 +1,10 @@
-use std::fmt::{Display, Formatter};
-use crate::pos::{file_x, rank_y};
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use crate::pos::{ADVISOR_TYPE, BISHOP_TYPE, KING_TYPE, KNIGHT_TYPE, PAWN_TYPE};
+use crate::pos::piece::{Color, Piece};
+use crate::pos::position::Position;
+use crate::pos::square::Square;
 
 ///https://www.xqbase.com/protocol/cchess_move.htm
 /*
@@ -17,31 +22,174 @@ use crate::pos::{file_x, rank_y};
  [2] 世界象棋联合会推荐的字母代号为H(Horse)
 ```
 */
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Move {
-    pub piece: char,
-    pub from: usize,
-    pub to: u8,
-}
+/// 着法, 压缩在一个u32里: 0~7位是起点格, 8~15位是终点格, 16~23位是走动的棋子, 24~31位是被吃的棋子.
+/// 棋子用局面中的序号(16~47)表示, 0表示不知道走动的棋子(如只从动作序号还原的着法)或没有吃子.
+/// 着法的比较和哈希只看起点和终点
+#[derive(Clone, Copy, Default)]
+pub struct Move(u32);
 
 const BOARD_FILES: usize = 9;
 const BOARD_RANKS: usize = 10;
 const BOARD_SIZE: usize = BOARD_FILES * BOARD_RANKS;
-const FILE_LEFT: usize = 3;
-const FILE_RIGHT: usize = 11;
-const RANK_TOP: usize = 3;
-const RANK_BOTTOM: usize = 12;
+
+/// 动作序号的个数, 即象棋里几何上可能的着法数: 同一横线或纵线上的两格(车炮帅兵) 1530个,
+/// 马的日字 508个, 九宫里仕的斜线 16个, 己方半边象的田字 32个
+pub const NUM_ACTION_INDICES: usize = 2086;
+
+// 查表中表示不是可能的着法
+const NO_ACTION: u16 = u16::MAX;
+
+/// 动作序号和起点、终点(9x10棋盘上的序号)之间的两个方向的查表, 编译时生成.
+/// 动作按起点、再按终点的顺序编号
+struct ActionTables {
+    squares: [(u8, u8); NUM_ACTION_INDICES],
+    actions: [[u16; BOARD_SIZE]; BOARD_SIZE],
+}
+
+static ACTION_TABLES: ActionTables = ActionTables::new();
+
+impl ActionTables {
+    const fn new() -> Self {
+        let mut squares = [(0, 0); NUM_ACTION_INDICES];
+        let mut actions = [[NO_ACTION; BOARD_SIZE]; BOARD_SIZE];
+        let mut n = 0;
+        let mut from = 0;
+        while from < BOARD_SIZE {
+            let mut to = 0;
+            while to < BOARD_SIZE {
+                if is_possible_move(from, to) {
+                    squares[n] = (from as u8, to as u8);
+                    actions[from][to] = n as u16;
+                    n += 1;
+                }
+                to += 1;
+            }
+            from += 1;
+        }
+        assert!(n == NUM_ACTION_INDICES);
+        ActionTables { squares, actions }
+    }
+}
+
+/// 不看棋子和局面, 某种棋子能不能从起点走到终点(9x10棋盘上的序号)
+const fn is_possible_move(from: usize, to: usize) -> bool {
+    let (r1, c1) = (from / BOARD_FILES, from % BOARD_FILES);
+    let (r2, c2) = (to / BOARD_FILES, to % BOARD_FILES);
+    let (dr, dc) = (r1.abs_diff(r2), c1.abs_diff(c2));
+    match (dr, dc) {
+        (0, 0) => false,
+        (0, _) | (_, 0) => true,
+        (1, 2) | (2, 1) => true,
+        (1, 1) => is_advisor_point(r1, c1) && is_advisor_point(r2, c2),
+        (2, 2) => (r1 < BOARD_RANKS / 2) == (r2 < BOARD_RANKS / 2) && is_bishop_point(r1, c1) && is_bishop_point(r2, c2),
+        _ => false,
+    }
+}
+
+/// 仕能到的5个点: 九宫的四角和中心, 从各自底线数起的横线和纵线相加为奇数
+const fn is_advisor_point(rank: usize, file: usize) -> bool {
+    let rank = own_rank(rank);
+    rank <= 2 && file >= 3 && file <= 5 && (rank + file) % 2 == 1
+}
+
+/// 象能到的7个点: 从各自底线数起的横线和纵线都是偶数, 且两者的一半相加为奇数
+const fn is_bishop_point(rank: usize, file: usize) -> bool {
+    let rank = own_rank(rank);
+    rank.is_multiple_of(2) && file.is_multiple_of(2) && !(rank / 2 + file / 2).is_multiple_of(2)
+}
+
+/// 从所在半边的底线数起的横线
+const fn own_rank(rank: usize) -> usize {
+    if rank < BOARD_RANKS / 2 {
+        rank
+    } else {
+        BOARD_RANKS - 1 - rank
+    }
+}
 
 impl Move {
-    pub(crate) fn new(p: char, f: usize, t: u8) -> Move {
-        Move {
-            piece: p,
-            from: f,
-            to: t,
+    /// 调用方保证起点和终点在棋盘内
+    pub(crate) fn new(pc: u8, from: usize, to: usize, pc_captured: u8) -> Move {
+        debug_assert!(Square::new(from).is_some() && Square::new(to).is_some());
+        Move(from as u32 | (to as u32) << 8 | (pc as u32) << 16 | (pc_captured as u32) << 24)
+    }
+
+    /// 起点格
+    pub fn from(&self) -> Square {
+        Square::new_unchecked(self.0 as usize & 0xff)
+    }
+
+    /// 终点格
+    pub fn to(&self) -> Square {
+        Square::new_unchecked(self.0 as usize >> 8 & 0xff)
+    }
+
+    /// 走动的棋子, 不知道时为None
+    pub fn piece(&self) -> Option<Piece> {
+        Piece::from_pc(self.pc())
+    }
+
+    /// 被吃的棋子, 不吃子时为None
+    pub fn captured(&self) -> Option<Piece> {
+        Piece::from_pc(self.pc_captured())
+    }
+
+    pub(crate) fn pc(&self) -> usize {
+        self.0 as usize >> 16 & 0xff
+    }
+
+    pub(crate) fn pc_captured(&self) -> usize {
+        self.0 as usize >> 24
+    }
+
+    /// 动作序号: 几何上可能的着法(见`NUM_ACTION_INDICES`)中的序号, 0~2085.
+    /// 走法生成和着法解析得到的着法总是可能的, 不可能的着法(如只用`Move::new`拼出来的)会panic
+    pub fn action_index(&self) -> usize {
+        let index = ACTION_TABLES.actions[board_index_from_square(self.from())][board_index_from_square(self.to())];
+        assert!(index != NO_ACTION, "着法{}不是象棋里可能的走法", self.to_iccs());
+        index as usize
+    }
+
+    /// 沿中间的纵线左右镜像的着法, 棋子不变, 正好是`Position::mirror`后的局面里对应的着法
+    pub fn mirror(&self) -> Move {
+        Move::new(self.pc() as u8, self.from().mirror().index(), self.to().mirror().index(), self.pc_captured() as u8)
+    }
+
+    /// 从动作序号还原着法, 并从局面中填上走动的棋子和被吃的棋子
+    pub fn from_action(position: &Position, index: usize) -> Result<Move, MoveError> {
+        let mv = Move::try_from(index)?;
+        let (from, to) = (mv.from().index(), mv.to().index());
+        match position.pc_on(from) {
+            0 => Err(MoveError::EmptySquare(mv.from())),
+            pc => Ok(Move::new(pc, from, to, position.pc_on(to))),
         }
     }
 }
 
+impl PartialEq for Move {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 & 0xffff == other.0 & 0xffff
+    }
+}
+
+impl Eq for Move {}
+
+impl Hash for Move {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u16(self.0 as u16);
+    }
+}
+
+impl Debug for Move {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Move")
+            .field("iccs", &self.to_iccs())
+            .field("piece", &self.piece())
+            .field("captured", &self.captured())
+            .finish()
+    }
+}
+
 impl Display for Move {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         // 将Move转换为中文纵线格式，如"马二进三"
@@ -51,142 +199,65 @@ impl Display for Move {
 }
 
 impl Move {
-    /// 将着法转换为中文纵线格式 (如 "马二进三")
+    /// 将着法转换为中文纵线格式 (如 "马二进三"), 只看着法本身, 不区分同一纵线上的前后棋子;
+    /// 需要区分时用to_chinese
     pub fn to_chinese_notation(&self) -> String {
-        // 获取棋子的中文字符
-        let piece_char = self.piece_to_chinese(self.piece);
-        
-        // 计算起始和目标位置的棋盘坐标
-        let from_file = file_x(self.from);  // 横坐标 (FILE_LEFT=3 到 FILE_RIGHT=11)
-        let from_rank = rank_y(self.from);  // 纵坐标 (RANK_TOP=3 到 RANK_BOTTOM=12)
-        let to_file = file_x(self.to as usize);
-        let to_rank = rank_y(self.to as usize);
-        
-        // 判断是红方还是黑方
-        let is_red = self.piece.is_ascii_uppercase();
-        
-        // 计算纵线编号 (红方从右到左1-9, 黑方从左到右1-9)
-        let from_file_num = if is_red {
-            FILE_RIGHT - from_file + 1  // 红方: 右边是1, 左边是9
-        } else {
-            from_file - FILE_LEFT + 1   // 黑方: 左边是1, 右边是9
-        };
-        
-        // 将数字转换为中文数字
-        let from_file_cn = Self::number_to_chinese(from_file_num);
-        
-        // 判断移动方向: 进、退、平
-        let (direction, target_str) = if from_file == to_file {
-            // 纵向移动
-            let direction_char = if is_red {
-                if to_rank < from_rank { "进" } else { "退" }
-            } else {
-                if to_rank > from_rank { "进" } else { "退" }
-            };
-            
-            // 对于车、炮、兵(卒)、帅(将)，用目标位置的纵线编号
-            // 对于马、相(象)、仕(士)，用移动的步数
-            let target = match self.piece.to_ascii_lowercase() {
-                'k' | 'r' | 'c' | 'p' => {
-                    // 车、炮、兵、帅：使用目标位置的纵线编号
-                    let to_file_num = if is_red {
-                        FILE_RIGHT - to_file + 1
-                    } else {
-                        to_file - FILE_LEFT + 1
-                    };
-                    Self::number_to_chinese(to_file_num)
-                },
-                _ => {
-                    // 马、相、仕：使用移动的步数(行数差)
-                    let steps = if is_red {
-                        from_rank - to_rank
-                    } else {
-                        to_rank - from_rank
-                    };
-                    Self::number_to_chinese(steps)
-                }
-            };
-            
-            (direction_char, target)
-        } else {
-            // 横向移动 - 一定是"平"
-            let to_file_num = if is_red {
-                FILE_RIGHT - to_file + 1
-            } else {
-                to_file - FILE_LEFT + 1
-            };
-            ("平", Self::number_to_chinese(to_file_num))
-        };
-        
-        format!("{}{}{}{}", piece_char, from_file_cn, direction, target_str)
-    }
-    
-    /// 将棋子字符转换为中文
-    fn piece_to_chinese(&self, piece: char) -> &'static str {
-        match piece.to_ascii_lowercase() {
-            'k' => {
-                if piece.is_ascii_uppercase() { "帅" } else { "将" }
-            },
-            'a' => {
-                if piece.is_ascii_uppercase() { "仕" } else { "士" }
-            },
-            'b' => {
-                if piece.is_ascii_uppercase() { "相" } else { "象" }
-            },
-            'n' => "马",
-            'r' => "车",
-            'c' => "炮",
-            'p' => {
-                if piece.is_ascii_uppercase() { "兵" } else { "卒" }
-            },
-            _ => "?",
-        }
+        FileMove::from_move(self.piece(), self).to_chinese()
     }
-    
-    /// 将数字转换为中文数字 (1-10)
-    fn number_to_chinese(num: usize) -> &'static str {
-        const CHINESE_NUMBERS: [&str; 11] = [
-            "", "一", "二", "三", "四", "五", "六", "七", "八", "九", "十"
-        ];
-        if num >= 1 && num <= 10 {
-            CHINESE_NUMBERS[num]
-        } else {
-            "?"
+}
+
+/// 着法转换错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// 动作序号超出范围
+    BadActionIndex(usize),
+    /// 起点格上没有棋子
+    EmptySquare(Square),
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::BadActionIndex(index) => {
+                write!(f, "动作序号{}超出范围(0~{})", index, NUM_ACTION_INDICES - 1)
+            }
+            MoveError::EmptySquare(sq) => write!(f, "格子{}上没有棋子", sq),
         }
     }
 }
 
-fn board_index_from_square(square: usize) -> Option<usize> {
-    let file = square & 0x0f;
-    let rank = square >> 4;
-    if !(FILE_LEFT..=FILE_RIGHT).contains(&file) || !(RANK_TOP..=RANK_BOTTOM).contains(&rank) {
-        return None;
-    }
-    Some((rank - RANK_TOP) * BOARD_FILES + (file - FILE_LEFT))
+impl std::error::Error for MoveError {}
+
+/// 格子在9x10棋盘上的序号, 从左上角开始逐行往下
+fn board_index_from_square(sq: Square) -> usize {
+    (BOARD_RANKS - 1 - sq.rank()) * BOARD_FILES + sq.file()
 }
 
-fn square_from_board_index(index: usize) -> usize {
-    let rank = index / BOARD_FILES + RANK_TOP;
-    let file = index % BOARD_FILES + FILE_LEFT;
-    (rank << 4) | file
+fn square_from_board_index(index: usize) -> Square {
+    Square::from_coords(index % BOARD_FILES, BOARD_RANKS - 1 - index / BOARD_FILES).unwrap()
 }
 
-impl From<usize> for Move {
-    fn from(value: usize) -> Self {
-        let from = value / BOARD_SIZE;
-        let to = value % BOARD_SIZE;
-        Move::new('?', square_from_board_index(from), square_from_board_index(to) as u8)
+/// 只从动作序号还原起点和终点, 不知道走动的棋子; 需要棋子时用Move::from_action
+impl TryFrom<usize> for Move {
+    type Error = MoveError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        if value >= NUM_ACTION_INDICES {
+            return Err(MoveError::BadActionIndex(value));
+        }
+        let (from, to) = ACTION_TABLES.squares[value];
+        let from = square_from_board_index(from as usize);
+        let to = square_from_board_index(to as usize);
+        Ok(Move::new(0, from.index(), to.index(), 0))
     }
 }
-impl Into<usize> for Move {
-    fn into(self) -> usize {
-        let from = board_index_from_square(self.from)
-            .expect("move source square must be on the 9x10 board");
-        let to = board_index_from_square(self.to as usize)
-            .expect("move destination square must be on the 9x10 board");
-        from * BOARD_SIZE + to
+
+impl From<Move> for usize {
+    fn from(mv: Move) -> Self {
+        mv.action_index()
     }
 }
+
 ///  ICCS坐标格式
 ///  ICCS是中国象棋互联网服务器(Internet Chinese Chess Server)的缩写。
 /// 在网络对弈服务器处理着法时，把着法表示成起点和终点的坐标是最方便的
@@ -206,40 +277,65 @@ impl Display for Iccs {
     }
 }
 
-// impl From<Move> for Iccs {
-//     fn from(mv: Move) -> Self {
-//         // 0~9
-//         let row_src: usize = INDEX_ROW[mv.from];
-//         // 0~8
-//         let col_src: usize = INDEX_COLUMN[mv.from];
-//
-//         // 0~9
-//         let row_dst: usize = INDEX_ROW[mv.to];
-//         // 0~8
-//         let col_dst: usize = INDEX_COLUMN[mv.to];
-//         let iccs = format!("{}{}{}{}", column_to_char(col_src), row_src, column_to_char(col_dst), row_dst);
-//         Iccs(iccs)
-//     }
-// }
+impl From<Move> for Iccs {
+    fn from(mv: Move) -> Self {
+        Iccs(format!("{}{}", mv.from(), mv.to()))
+    }
+}
+
+/// 着法解析错误(ICCS、WXF等格式通用)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotationError {
+    /// 不是该格式的着法, 如ICCS应形如"h2e2"或"H2-E2"
+    BadFormat(String),
+    /// ICCS纵线不在a~i之间
+    BadFile(char),
+    /// ICCS横线不在0~9之间
+    BadRank(char),
+    /// 格式正确, 但在当前局面下不是合法着法
+    IllegalMove(String),
+}
 
-fn column_to_char(col: usize) -> char {
-    match col {
-        0 => 'a',
-        1 => 'b',
-        2 => 'c',
-        3 => 'd',
-        4 => 'e',
-        5 => 'f',
-        6 => 'g',
-        7 => 'h',
-        8 => 'i',
-        _ => panic!("error col number"),
+impl Display for NotationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotationError::BadFormat(s) => write!(f, "无法识别的着法'{}'", s),
+            NotationError::BadFile(ch) => write!(f, "ICCS纵线'{}'应在a~i之间", ch),
+            NotationError::BadRank(ch) => write!(f, "ICCS横线'{}'应在0~9之间", ch),
+            NotationError::IllegalMove(s) => write!(f, "'{}'在当前局面下不是合法着法", s),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+impl Move {
+    /// 着法的ICCS坐标(UCCI协议中的简化形式), 如"h2e2"
+    pub fn to_iccs(&self) -> String {
+        Iccs::from(*self).0
+    }
+
+    /// 解析ICCS坐标, 兼容"h2e2"和"H2-E2"两种写法, 并在给定局面的合法着法中查找
+    pub fn from_iccs(position: &Position, iccs: &str) -> Result<Move, NotationError> {
+        let coords: String = iccs.trim().chars().filter(|&ch| ch != '-').collect();
+        if !coords.is_ascii() || coords.len() != 4 {
+            return Err(NotationError::BadFormat(iccs.to_string()));
+        }
+        let (from, to) = coords.split_at(2);
+        let (from, to) = (from.parse::<Square>()?, to.parse::<Square>()?);
+        position
+            .gen_legal_moves()
+            .into_iter()
+            .find(|mv| mv.from() == from && mv.to() == to)
+            .ok_or_else(|| NotationError::IllegalMove(iccs.to_string()))
     }
 }
 
-const CC_DIRECT_2_BYTE: [char; 4] = ['+', '.', '-', ' '];
+const CC_DIRECT_2_BYTE: [char; 4] = ['+', '=', '-', ' '];
 
-const CC_POS_2_BYTE: [char; 12] = ['a', 'b', 'c', 'd', 'e', '+', '.', '-', ' ', ' ', ' ', ' '];
+const CC_POS_2_BYTE: [char; 12] = ['a', 'b', 'c', 'd', 'e', '+', '=', '-', ' ', ' ', ' ', ' '];
+
+const PIECE_2_BYTE: [char; 8] = ['K', 'A', 'E', 'H', 'R', 'C', 'P', ' '];
 
 const DIGIT_2_WORD: [char; 10] = ['一', '二', '三', '四', '五', '六', '七', '八', '九', '十'];
 
@@ -252,63 +348,309 @@ const DIRECT_2_WORD: [char; 4] = ['进', '平', '退', ' '];
 
 const POS_2_WORD: [char; 10] = ['一', '二', '三', '四', '五', '前', '中', '后', ' ', ' '];
 
-///中文纵线格式
-struct FixFile {}
-
-///着法表示成中文纵线格式
-// fn to_cvlf(board: &Board, mv: &Move) -> String {
-//     "".into()
-// }
-//
-// ///着法表示成数字纵线格式
-// fn to_dvlf(board: &Board, mv: &Move) -> String {
-//     // 0~9
-//     let row_src: usize = INDEX_ROW[mv.from];
-//     // 0~8
-//     let col_src: usize = INDEX_COLUMN[mv.from];
-//
-//     // 0~9
-//     let row_dst: usize = INDEX_ROW[mv.to];
-//     // 0~8
-//     let col_dst: usize = INDEX_COLUMN[mv.to];
-//     let piece=mv.piece;
-//     match mv.piece {
-//         RED_ADVISER => {
-//             let aa=board.search_piece_locations(piece);
-//
-//         },
-//     }
-//
-//     "".into()
-// }
+/// 纵线格式(中文纵线、WXF)共同的着法要素, 两种格式只是写法不同
+struct FileMove {
+    red: bool,
+    // 棋子类型, 0~6
+    pt: usize,
+    // 棋子所在的纵线(1~9, 从走棋方的右边数起)
+    file: usize,
+    // 同一纵线上有多个同类棋子时, 在POS_2_WORD/CC_POS_2_BYTE中的序号
+    pos: Option<usize>,
+    // 有两条以上纵线各有多个兵时, 纵线号不能省略
+    keep_file: bool,
+    // 进(0)、平(1)、退(2)
+    direct: usize,
+    // 目标纵线或前进后退的步数
+    target: usize,
+}
+
+/// 纵线号, 红黑双方都从自己的右边数起
+fn file_num(red: bool, sq: Square) -> usize {
+    if red { BOARD_FILES - sq.file() } else { sq.file() + 1 }
+}
+
+/// 离对方底线的距离, 越小越靠前
+fn front(red: bool, sq: Square) -> usize {
+    if red { BOARD_RANKS - 1 - sq.rank() } else { sq.rank() }
+}
+
+impl FileMove {
+    /// 只根据棋子和着法得到纵线格式, 不区分同一纵线上的棋子; 不知道棋子时按黑方写, 棋子留空
+    fn from_move(piece: Option<Piece>, mv: &Move) -> FileMove {
+        let red = piece.is_some_and(|p| p.color == Color::Red);
+        // 各棋子表的最后一项(7)是空白
+        let pt = piece.map_or(7, |p| p.kind as usize);
+        let (from, to) = (mv.from(), mv.to());
+        let (direct, target) = if from.rank() == to.rank() {
+            (1, file_num(red, to))
+        } else {
+            let direct = if front(red, to) < front(red, from) { 0 } else { 2 };
+            // 马相仕斜着走, 写目标纵线; 其他棋子直着走, 写步数
+            let target = if pt == KNIGHT_TYPE || pt == ADVISOR_TYPE || pt == BISHOP_TYPE {
+                file_num(red, to)
+            } else {
+                from.rank().abs_diff(to.rank())
+            };
+            (direct, target)
+        };
+        FileMove { red, pt, file: file_num(red, from), pos: None, keep_file: false, direct, target }
+    }
+
+    /// 根据局面得到纵线格式, 棋子以局面为准, 同一纵线上有多个同类棋子时区分前后
+    fn new(position: &Position, mv: &Move) -> FileMove {
+        let from = mv.from();
+        let Some(piece) = position.piece_at(from).or(mv.piece()) else {
+            return FileMove::from_move(None, mv);
+        };
+        let mut fm = FileMove::from_move(Some(piece), mv);
+
+        // 仕(士)相(象)同一纵线上的两个棋子总是一进一退, 不需要区分前后
+        if fm.pt == KING_TYPE || fm.pt == ADVISOR_TYPE || fm.pt == BISHOP_TYPE {
+            return fm;
+        }
+        let squares = position.pieces(piece.color, piece.kind);
+        let mut same_file: Vec<Square> = squares.iter().copied().filter(|sq| sq.file() == from.file()).collect();
+        if same_file.len() > 1 {
+            same_file.sort_by_key(|&sq| front(fm.red, sq));
+            let index = same_file.iter().position(|&sq| sq == from).unwrap();
+            fm.pos = Some(match same_file.len() {
+                2 => [5, 7][index],
+                3 => [5, 6, 7][index],
+                _ => index,
+            });
+            if fm.pt == PAWN_TYPE {
+                fm.keep_file = squares.iter().any(|sq| {
+                    sq.file() != from.file() && squares.iter().filter(|sq2| sq2.file() == sq.file()).count() > 1
+                });
+            }
+        }
+        fm
+    }
+
+    /// 中文纵线格式, 红方用中文数字, 黑方用阿拉伯数字
+    fn to_chinese(&self) -> String {
+        let digit = |n: usize| {
+            if self.red {
+                DIGIT_2_WORD[n - 1]
+            } else {
+                char::from_digit(n as u32, 10).unwrap()
+            }
+        };
+        let piece = PIECE_2_WORD[if self.red { 0 } else { 1 }][self.pt];
+        let (first, second) = match self.pos {
+            None => (piece, digit(self.file)),
+            Some(pos) if self.keep_file => (POS_2_WORD[pos], digit(self.file)),
+            Some(pos) => (POS_2_WORD[pos], piece),
+        };
+        format!("{}{}{}{}", first, second, DIRECT_2_WORD[self.direct], digit(self.target))
+    }
+}
 
 ///WXF纵线格式
-struct Wfx(String);
+/// 世界象棋联合会(WXF)推荐的格式, 如"C2=5"(炮二平五)、"H8+7"(马８进７);
+/// 同一纵线上有两个同类棋子时用"+"、"-"代替纵线号, 如"+R-1"(前车退一),
+/// 三个兵用"+"、"="、"-", 更多的兵用"a"~"e"从前往后编号;
+/// 有两条纵线都有多个兵时, 用纵线号代替棋子字母, 如"+7=6"
+struct Wxf(String);
 
-impl Display for Wfx {
+impl Display for Wxf {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
-impl From<Move> for Wfx {
-    fn from(value: Move) -> Self {
-        todo!()
+impl Wxf {
+    fn new(position: &Position, mv: &Move) -> Wxf {
+        let fm = FileMove::new(position, mv);
+        let piece = PIECE_2_BYTE[fm.pt];
+        let digit = |n: usize| char::from_digit(n as u32, 10).unwrap();
+        let (first, second) = match fm.pos {
+            None => (piece, digit(fm.file)),
+            Some(pos) if fm.keep_file => (CC_POS_2_BYTE[pos], digit(fm.file)),
+            Some(pos) => (CC_POS_2_BYTE[pos], piece),
+        };
+        Wxf(format!("{}{}{}{}", first, second, CC_DIRECT_2_BYTE[fm.direct], digit(fm.target)))
+    }
+}
+
+/// 统一WXF着法的写法: 大写, 相(B)马(N)写成E、H, 平也可以写成"."
+fn normalize_wxf(wxf: &str) -> String {
+    wxf.trim()
+        .chars()
+        .map(|ch| match ch.to_ascii_uppercase() {
+            'B' => 'E',
+            'N' => 'H',
+            '.' => '=',
+            ch => ch,
+        })
+        .collect()
+}
+
+impl Move {
+    /// 着法的WXF纵线格式, 如"C2=5", 需要局面来区分同一纵线上的棋子
+    pub fn to_wxf(&self, position: &Position) -> String {
+        Wxf::new(position, self).0
+    }
+
+    /// 解析WXF纵线格式, 在给定局面的合法着法中查找
+    pub fn from_wxf(position: &Position, wxf: &str) -> Result<Move, NotationError> {
+        let wxf_norm = normalize_wxf(wxf);
+        if wxf_norm.chars().count() != 4 {
+            return Err(NotationError::BadFormat(wxf.to_string()));
+        }
+        position
+            .gen_legal_moves()
+            .into_iter()
+            .find(|mv| mv.to_wxf(position) == wxf_norm)
+            .ok_or_else(|| NotationError::IllegalMove(wxf.to_string()))
+    }
+}
+
+/// 统一中文纵线格式的写法: 棋子、数字、方向和前中后都换成字母和符号,
+/// 兼容繁体字、全角数字以及黑方用中文数字的写法
+fn normalize_chinese(chinese: &str) -> String {
+    chinese
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .map(|ch| match ch {
+            '帅' | '帥' | '将' | '將' => 'K',
+            '仕' | '士' => 'A',
+            '相' | '象' => 'B',
+            '马' | '馬' | '傌' => 'N',
+            '车' | '車' | '俥' => 'R',
+            '炮' | '砲' | '包' => 'C',
+            '兵' | '卒' => 'P',
+            '进' | '進' | '前' => '+',
+            '平' | '中' => '=',
+            '退' | '后' | '後' => '-',
+            '１'..='９' => char::from_digit(ch as u32 - '０' as u32, 10).unwrap(),
+            ch => match DIGIT_2_WORD.iter().position(|&d| d == ch) {
+                Some(n) => char::from_digit(n as u32 + 1, 10).unwrap_or(ch),
+                None => ch,
+            },
+        })
+        .collect()
+}
+
+impl Move {
+    /// 着法的中文纵线格式, 如"炮二平五"、"马8进7", 需要局面来区分同一纵线上的棋子(如"前车进一")
+    pub fn to_chinese(&self, position: &Position) -> String {
+        FileMove::new(position, self).to_chinese()
+    }
+
+    /// 解析中文纵线格式, 在给定局面的合法着法中查找
+    pub fn from_chinese(position: &Position, chinese: &str) -> Result<Move, NotationError> {
+        let norm = normalize_chinese(chinese);
+        if norm.chars().count() != 4 {
+            return Err(NotationError::BadFormat(chinese.to_string()));
+        }
+        position
+            .gen_legal_moves()
+            .into_iter()
+            .find(|mv| normalize_chinese(&mv.to_chinese(position)) == norm)
+            .ok_or_else(|| NotationError::IllegalMove(chinese.to_string()))
+    }
+}
+
+/// 着法的记录格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Notation {
+    /// ICCS坐标格式, 如"h2e2"
+    #[default]
+    Iccs,
+    /// WXF纵线格式, 如"C2=5"
+    Wxf,
+    /// 中文纵线格式, 如"炮二平五"
+    Chinese,
+}
+
+impl Notation {
+    /// 按该格式写出着法
+    pub fn format(&self, position: &Position, mv: &Move) -> String {
+        match self {
+            Notation::Iccs => mv.to_iccs(),
+            Notation::Wxf => mv.to_wxf(position),
+            Notation::Chinese => mv.to_chinese(position),
+        }
+    }
+
+    /// 按该格式解析着法
+    pub fn parse(&self, position: &Position, s: &str) -> Result<Move, NotationError> {
+        match self {
+            Notation::Iccs => Move::from_iccs(position, s),
+            Notation::Wxf => Move::from_wxf(position, s),
+            Notation::Chinese => Move::from_chinese(position, s),
+        }
+    }
+}
+
+impl Display for Notation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Notation::Iccs => write!(f, "ICCS"),
+            Notation::Wxf => write!(f, "WXF"),
+            Notation::Chinese => write!(f, "Chinese"),
+        }
+    }
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "iccs" => Ok(Notation::Iccs),
+            "wxf" => Ok(Notation::Wxf),
+            "chinese" | "cn" => Ok(Notation::Chinese),
+            _ => Err(format!("未知的着法格式'{}', 应为iccs、wxf或chinese", s)),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Move;
+    use rstest::rstest;
+
+    use super::{Move, MoveError, Notation, NotationError, NUM_ACTION_INDICES};
+    use crate::fen::Fen;
+    use crate::pos::{side_tag, CANNON_FROM, KNIGHT_FROM, ROOK_FROM};
+    use crate::position::Position;
+    use crate::piece::Piece;
+    use crate::square::Square;
 
     #[test]
     fn move_index_roundtrip_preserves_board_squares() {
-        let mv = Move::new('R', 0x33, 0x3b);
+        let mv = Move::new((side_tag(0) + ROOK_FROM) as u8, 0x33, 0x3b, 0);
         let index: usize = mv.into();
-        let decoded = Move::from(index);
+        let decoded = Move::try_from(index).unwrap();
 
-        assert_eq!(decoded.from, 0x33);
-        assert_eq!(decoded.to, 0x3b);
+        assert_eq!(decoded.from().index(), 0x33);
+        assert_eq!(decoded.to().index(), 0x3b);
+        assert_eq!(decoded, mv);
+        assert_eq!(decoded.piece(), None);
+        assert_eq!(mv.piece(), Piece::from_char('R'));
+    }
+
+    #[test]
+    fn test_from_action() {
+        let position = Position::from_fen(&"4k4/9/9/9/9/9/9/4p4/4R4/4K4 w".parse().unwrap());
+        for mv in position.gen_legal_moves() {
+            let decoded = Move::from_action(&position, mv.into()).unwrap();
+            assert_eq!((decoded.piece(), decoded.captured()), (mv.piece(), mv.captured()));
+        }
+        let capture = Move::from_iccs(&position, "e1e2").unwrap();
+        assert_eq!((capture.piece(), capture.captured()), (Piece::from_char('R'), Piece::from_char('p')));
+
+        assert_eq!(
+            Move::from_action(&position, NUM_ACTION_INDICES),
+            Err(MoveError::BadActionIndex(NUM_ACTION_INDICES))
+        );
+        let empty = Move::try_from(0).unwrap();
+        assert_eq!(
+            Move::from_action(&position, empty.into()),
+            Err(MoveError::EmptySquare(Square::new(0x33).unwrap()))
+        );
     }
 
     #[test]
@@ -324,7 +666,7 @@ mod tests {
         let from = (12 << 4) | 10;  // rank=12, file=10
         let to = (10 << 4) | 9;     // rank=10, file=9
         
-        let mv = Move::new('N', from, to as u8);
+        let mv = Move::new((side_tag(0) + KNIGHT_FROM) as u8, from, to, 0);
         let notation = format!("{}", mv);
         
         println!("Knight move notation: {}", notation);
@@ -340,7 +682,7 @@ mod tests {
         let from = (12 << 4) | 11;  // rank=12, file=11 (纵线1)
         let to = (12 << 4) | 10;    // rank=12, file=10 (纵线2)
         
-        let mv = Move::new('R', from, to as u8);
+        let mv = Move::new((side_tag(0) + ROOK_FROM) as u8, from, to, 0);
         let notation = format!("{}", mv);
         
         println!("Rook horizontal move notation: {}", notation);
@@ -356,10 +698,181 @@ mod tests {
         let from = (12 << 4) | 10;  // rank=12, file=10
         let to = (8 << 4) | 10;     // rank=8, file=10
         
-        let mv = Move::new('C', from, to as u8);
+        let mv = Move::new((side_tag(0) + CANNON_FROM) as u8, from, to, 0);
         let notation = format!("{}", mv);
         
         println!("Cannon forward move notation: {}", notation);
         assert_eq!(notation, "炮二进四");
     }
+
+    #[rstest]
+    #[case("h2e2", 0xaa, 0xa7)]
+    #[case("H2-E2", 0xaa, 0xa7)]
+    #[case("h0g2", 0xca, 0xa9)]
+    #[case("a0a1", 0xc3, 0xb3)]
+    fn test_from_iccs(#[case] iccs: &str, #[case] from: usize, #[case] to: usize) {
+        let position = Position::from_fen(&Fen::init());
+        let mv = Move::from_iccs(&position, iccs).unwrap();
+        assert_eq!((mv.from().index(), mv.to().index()), (from, to));
+        assert_eq!(mv.to_iccs(), iccs.replace('-', "").to_lowercase());
+    }
+
+    #[rstest]
+    #[case("h2e", NotationError::BadFormat("h2e".to_string()))]
+    #[case("j2e2", NotationError::BadFile('j'))]
+    #[case("h2ex", NotationError::BadRank('x'))]
+    // 马腿被绊
+    #[case("b0d1", NotationError::IllegalMove("b0d1".to_string()))]
+    // 轮到红方走
+    #[case("h7e7", NotationError::IllegalMove("h7e7".to_string()))]
+    fn test_from_iccs_errors(#[case] iccs: &str, #[case] expected: NotationError) {
+        let position = Position::from_fen(&Fen::init());
+        assert_eq!(Move::from_iccs(&position, iccs), Err(expected));
+    }
+
+    #[test]
+    fn test_mirror() {
+        let position = Position::from_fen(&Fen::init());
+        let mv = Move::from_iccs(&position, "h2e2").unwrap();
+        let mirrored = mv.mirror();
+        assert_eq!(mirrored.to_iccs(), "b2e2");
+        assert_eq!(mirrored.piece(), mv.piece());
+        assert_eq!(mirrored.mirror(), mv);
+        for index in [0, 1234, NUM_ACTION_INDICES - 1] {
+            let mirrored = Move::try_from(index).unwrap().mirror();
+            assert_eq!(mirrored.mirror().action_index(), index);
+        }
+        assert_eq!(Move::try_from(0).unwrap().mirror().to_iccs(), "i9h9");
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w")]
+    #[case("2bak4/4a4/4n4/9/9/4pp3/4pp3/4p4/9/5K3 b")]
+    #[case("3k5/4a4/4b4/4N4/9/2B6/R8/3A5/4AK3/2C6 w")]
+    fn test_action_tables(#[case] fen: &str) {
+        for index in 0..NUM_ACTION_INDICES {
+            assert_eq!(Move::try_from(index).unwrap().action_index(), index);
+        }
+        let position = Position::from_fen(&fen.parse().unwrap());
+        for mv in position.gen_legal_moves() {
+            assert_eq!(Move::try_from(mv.action_index()), Ok(mv));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_impossible_action() {
+        // a9到c7不是象的田字
+        Move::new(0, 0x33, 0x55, 0).action_index();
+    }
+
+    #[test]
+    fn test_iccs_round_trip() {
+        let position = Position::from_fen(&Fen::init());
+        for mv in position.gen_legal_moves() {
+            assert_eq!(Move::from_iccs(&position, &mv.to_iccs()), Ok(mv));
+        }
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "h2e2", "C2=5")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "h0g2", "H2+3")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "c0e2", "E7+5")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "f0e1", "A4+5")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "e0e1", "K5+1")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b", "h9g7", "H8+7")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b", "a9a7", "R1+2")]
+    // 同一纵线上的两个车
+    #[case("3k5/9/9/9/9/4R4/9/4R4/9/5K3 w", "e4e3", "+R-1")]
+    #[case("3k5/9/9/9/9/4R4/9/4R4/9/5K3 w", "e2d2", "-R=6")]
+    #[case("4k4/9/9/4r4/9/4r4/9/9/9/3K5 b", "e4e3", "+R+1")]
+    // 同一纵线上的三个兵
+    #[case("3k5/9/9/4P4/4P4/4P4/9/9/9/5K3 w", "e6e7", "+P+1")]
+    #[case("3k5/9/9/4P4/4P4/4P4/9/9/9/5K3 w", "e5d5", "=P=6")]
+    // 两条纵线上都有多个兵
+    #[case("3k5/9/9/2P1P4/2P1P4/9/9/9/9/5K3 w", "e6d6", "+5=6")]
+    fn test_wxf(#[case] fen: &str, #[case] iccs: &str, #[case] wxf: &str) {
+        let position = Position::from_fen(&fen.parse().unwrap());
+        let mv = Move::from_iccs(&position, iccs).unwrap();
+        assert_eq!(mv.to_wxf(&position), wxf);
+        assert_eq!(Move::from_wxf(&position, wxf), Ok(mv));
+    }
+
+    #[rstest]
+    #[case("c2.5", Ok("h2e2"))]
+    #[case("N2+3", Ok("h0g2"))]
+    #[case("C2=", Err(NotationError::BadFormat("C2=".to_string())))]
+    #[case("C2+9", Err(NotationError::IllegalMove("C2+9".to_string())))]
+    fn test_from_wxf(#[case] wxf: &str, #[case] expected: Result<&str, NotationError>) {
+        let position = Position::from_fen(&Fen::init());
+        let expected = expected.map(|iccs| Move::from_iccs(&position, iccs).unwrap());
+        assert_eq!(Move::from_wxf(&position, wxf), expected);
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w")]
+    #[case("3k5/9/9/2P1P4/2P1P4/4P4/9/4R4/4R4/5K3 w")]
+    #[case("2bak4/4a4/4n4/9/9/4pp3/4pp3/4p4/9/5K3 b")]
+    fn test_wxf_unique(#[case] fen: &str) {
+        let position = Position::from_fen(&fen.parse().unwrap());
+        for mv in position.gen_legal_moves() {
+            let wxf = Notation::Wxf.format(&position, &mv);
+            assert_eq!(Notation::Wxf.parse(&position, &wxf), Ok(mv), "{}", wxf);
+        }
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "h2e2", "炮二平五")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "h0g2", "马二进三")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "c0e2", "相七进五")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "f0e1", "仕四进五")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b", "h9g7", "马8进7")]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b", "a9a7", "车1进2")]
+    // 同一纵线上的两个车
+    #[case("3k5/9/9/9/9/4R4/9/4R4/9/5K3 w", "e4e3", "前车退一")]
+    #[case("3k5/9/9/9/9/4R4/9/4R4/9/5K3 w", "e2d2", "后车平六")]
+    #[case("4k4/9/9/4r4/9/4r4/9/9/9/3K5 b", "e4e3", "前车进1")]
+    // 同一纵线上的三个兵
+    #[case("3k5/9/9/4P4/4P4/4P4/9/9/9/5K3 w", "e6e7", "前兵进一")]
+    #[case("3k5/9/9/4P4/4P4/4P4/9/9/9/5K3 w", "e5d5", "中兵平六")]
+    // 两条纵线上都有多个兵
+    #[case("3k5/9/9/2P1P4/2P1P4/9/9/9/9/5K3 w", "e6d6", "前五平六")]
+    fn test_chinese(#[case] fen: &str, #[case] iccs: &str, #[case] chinese: &str) {
+        let position = Position::from_fen(&fen.parse().unwrap());
+        let mv = Move::from_iccs(&position, iccs).unwrap();
+        assert_eq!(mv.to_chinese(&position), chinese);
+        assert_eq!(Move::from_chinese(&position, chinese), Ok(mv));
+        // 从动作序号还原的着法没有棋子字母, 以局面为准
+        let decoded = Move::try_from(usize::from(mv)).unwrap();
+        assert_eq!(decoded.to_chinese(&position), chinese);
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "馬二進三", Ok("h0g2"))]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "炮2平5", Ok("h2e2"))]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b", "马８进７", Ok("h9g7"))]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", "炮二平", Err(NotationError::BadFormat("炮二平".to_string())))]
+    // 同一纵线上有两个车, 必须说明前后
+    #[case("3k5/9/9/9/9/4R4/9/4R4/9/5K3 w", "车五退一", Err(NotationError::IllegalMove("车五退一".to_string())))]
+    fn test_from_chinese(
+        #[case] fen: &str,
+        #[case] chinese: &str,
+        #[case] expected: Result<&str, NotationError>,
+    ) {
+        let position = Position::from_fen(&fen.parse().unwrap());
+        let expected = expected.map(|iccs| Move::from_iccs(&position, iccs).unwrap());
+        assert_eq!(Move::from_chinese(&position, chinese), expected);
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w")]
+    #[case("3k5/9/9/2P1P4/2P1P4/4P4/9/4R4/4R4/5K3 w")]
+    #[case("2bak4/4a4/4n4/9/9/4pp3/4pp3/4p4/9/5K3 b")]
+    fn test_chinese_unique(#[case] fen: &str) {
+        let position = Position::from_fen(&fen.parse().unwrap());
+        for mv in position.gen_legal_moves() {
+            let chinese = Notation::Chinese.format(&position, &mv);
+            assert_eq!(Notation::Chinese.parse(&position, &chinese), Ok(mv), "{}", chinese);
+        }
+    }
 }
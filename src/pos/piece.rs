@@ -0,0 +1,158 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pos::{piece_type, ADVISOR_TYPE, BISHOP_TYPE, CANNON_TYPE, KING_TYPE, KNIGHT_TYPE, PAWN_TYPE, ROOK_TYPE};
+
+/// 红黑双方, 红方先走
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Color {
+    Red,
+    Black,
+}
+
+impl Color {
+    /// 对方
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::Red => Color::Black,
+            Color::Black => Color::Red,
+        }
+    }
+
+    /// 局面内部棋子序号的起点, 红方16, 黑方32
+    pub(crate) fn side_tag(self) -> usize {
+        16 + ((self as usize) << 4)
+    }
+}
+
+/// 棋子类型, 顺序和局面内部的棋子类型编号一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PieceKind {
+    King = KING_TYPE as isize,
+    Advisor = ADVISOR_TYPE as isize,
+    Bishop = BISHOP_TYPE as isize,
+    Knight = KNIGHT_TYPE as isize,
+    Rook = ROOK_TYPE as isize,
+    Cannon = CANNON_TYPE as isize,
+    Pawn = PAWN_TYPE as isize,
+}
+
+impl PieceKind {
+    pub const ALL: [PieceKind; 7] = [
+        PieceKind::King,
+        PieceKind::Advisor,
+        PieceKind::Bishop,
+        PieceKind::Knight,
+        PieceKind::Rook,
+        PieceKind::Cannon,
+        PieceKind::Pawn,
+    ];
+
+    /// FEN中的大写字母, 如车是'R'
+    pub fn to_char(self) -> char {
+        "KABNRCP".as_bytes()[self as usize] as char
+    }
+
+    /// 由FEN字母构造, 不区分大小写, 兼容WXF的E(相)和H(马)
+    pub fn from_char(ch: char) -> Option<PieceKind> {
+        let kind = match ch.to_ascii_uppercase() {
+            'K' => PieceKind::King,
+            'A' => PieceKind::Advisor,
+            'B' | 'E' => PieceKind::Bishop,
+            'N' | 'H' => PieceKind::Knight,
+            'R' => PieceKind::Rook,
+            'C' => PieceKind::Cannon,
+            'P' => PieceKind::Pawn,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// 子力价值, 以兵为10计; 帅(将)不计
+    pub fn value(self) -> i32 {
+        match self {
+            PieceKind::King => 0,
+            PieceKind::Advisor | PieceKind::Bishop => 20,
+            PieceKind::Knight => 40,
+            PieceKind::Rook => 90,
+            PieceKind::Cannon => 45,
+            PieceKind::Pawn => 10,
+        }
+    }
+
+    /// 每方这种棋子在局面内部棋子序号中的范围(相对于side_tag)
+    pub(crate) fn index_range(self) -> std::ops::RangeInclusive<usize> {
+        match self {
+            PieceKind::King => 0..=0,
+            PieceKind::Advisor => 1..=2,
+            PieceKind::Bishop => 3..=4,
+            PieceKind::Knight => 5..=6,
+            PieceKind::Rook => 7..=8,
+            PieceKind::Cannon => 9..=10,
+            PieceKind::Pawn => 11..=15,
+        }
+    }
+}
+
+/// 棋子: 哪一方的什么棋子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub color: Color,
+    pub kind: PieceKind,
+}
+
+impl Piece {
+    pub fn new(color: Color, kind: PieceKind) -> Piece {
+        Piece { color, kind }
+    }
+
+    /// FEN中的字母, 红方大写, 黑方小写
+    pub fn to_char(self) -> char {
+        match self.color {
+            Color::Red => self.kind.to_char(),
+            Color::Black => self.kind.to_char().to_ascii_lowercase(),
+        }
+    }
+
+    /// 由FEN字母构造, 大写是红方, 小写是黑方
+    pub fn from_char(ch: char) -> Option<Piece> {
+        let color = if ch.is_ascii_uppercase() { Color::Red } else { Color::Black };
+        PieceKind::from_char(ch).map(|kind| Piece { color, kind })
+    }
+
+    /// 由局面内部的棋子序号(16~47)构造, 0表示没有棋子
+    pub(crate) fn from_pc(pc: usize) -> Option<Piece> {
+        if !(16..48).contains(&pc) {
+            return None;
+        }
+        let color = if pc < 32 { Color::Red } else { Color::Black };
+        Some(Piece { color, kind: PieceKind::ALL[piece_type(pc) as usize] })
+    }
+}
+
+impl Display for Piece {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, Piece, PieceKind};
+
+    #[test]
+    fn test_piece_char() {
+        for kind in PieceKind::ALL {
+            for color in [Color::Red, Color::Black] {
+                let piece = Piece::new(color, kind);
+                assert_eq!(Piece::from_char(piece.to_char()), Some(piece));
+            }
+        }
+        assert_eq!(Piece::from_char('H'), Some(Piece::new(Color::Red, PieceKind::Knight)));
+        assert_eq!(Piece::from_char('x'), None);
+        assert_eq!(Piece::from_pc(16 + 7), Some(Piece::new(Color::Red, PieceKind::Rook)));
+        assert_eq!(Piece::from_pc(32 + 11), Some(Piece::new(Color::Black, PieceKind::Pawn)));
+        assert_eq!(Piece::from_pc(0), None);
+    }
+}
@@ -1,18 +1,24 @@
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use rand_distr::num_traits::ToPrimitive;
 use toto::Toi32;
 
 use crate::moves::Move;
+use crate::pos::movelist::MoveList;
+use crate::pos::piece::{Color, Piece, PieceKind};
+use crate::pos::square::Square;
 use crate::fen::Fen;
-use crate::pos::{ADVISOR_FROM, ADVISOR_TO, ADVISOR_TYPE, BISHOP_FROM, BISHOP_TO, BISHOP_TYPE, bit_piece, CANNON_FROM, CANNON_TO, CANNON_TYPE, ChessPlayer, coord_xy, file_disp, KING_FROM, KING_TYPE, KNIGHT_FROM, KNIGHT_TO, KNIGHT_TYPE, opp_side_tag2, PAWN_FROM, PAWN_TO, PAWN_TYPE, piece_char, piece_char_with_side, piece_type, rank_disp, ROOK_FROM, ROOK_TO, ROOK_TYPE, side_tag, side_tag2};
+use crate::pos::{ADVISOR_FROM, away_half, bishop_pin, home_half, in_fort, ADVISOR_TO, ADVISOR_TYPE, BISHOP_FROM, BISHOP_TO, BISHOP_TYPE, bit_piece, CANNON_FROM, CANNON_TO, CANNON_TYPE, coord_xy, file_disp, KING_FROM, KING_TYPE, KNIGHT_FROM, KNIGHT_TO, KNIGHT_TYPE, knight_pin, opp_side_tag, opp_side_tag2, PAWN_FROM, PAWN_TO, PAWN_TYPE, piece_char, piece_char_with_side, piece_type, rank_disp, ROOK_FROM, ROOK_TO, ROOK_TYPE, side_tag, side_tag2, square_backward, square_forward};
 use crate::pos::pregen::{PreGen, SlideMask, SlideMove, Zobrist};
 use crate::{FILE_LEFT, FILE_RIGHT, file_x, RANK_BOTTOM, RANK_TOP, rank_y};
 
 ///局面
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]  // 移除 Copy，因为 Box<PreGen> 不支持
+/// 局面的比较和哈希只看Zobrist键值(包括棋盘和走棋方), 不比较预置表和步数计数.
+/// 复制局面不复制撤销记录, 复制的开销与已走的步数无关; 复制出来的局面不能撤销到复制之前
+#[derive(Debug)]
 pub struct Position {
     // 轮到哪方走，0表示红方，1表示黑方
-    current_player: ChessPlayer,
+    side_to_move: Color,
     // 每个格子放的棋子，None表示没有棋子
     ucpc_squares: [u8; 256],
     // 每个棋子放的位置，0表示被吃
@@ -23,25 +29,88 @@ pub struct Position {
     // 位行数组，注意用法是"w_bit_ranks[RANK_Y(sq)]"
     w_bit_files: [u16; 16],
     // 位列数组，注意用法是"w_bit_files[FILE_X(sq)]"
-    pre_gen: Box<PreGen>,  // 使用 Box 将大型结构分配到堆上，避免栈溢出
+    pre_gen: &'static PreGen,  // 所有局面共用的预置表
     zobr: Zobrist, // Zobrist
-    winner: Option<ChessPlayer>,
+    // 自上次吃子以来的步数(半回合), 用于自然限着判和
+    halfmove_clock: u32,
+    // 回合数, 从1开始, 黑方走完后加1
+    fullmove_number: u32,
+    // 已走着法的记录, 用于撤销着法
+    undo_stack: Vec<UndoInfo>,
 }
 
+/// 撤销着法需要的信息
+#[derive(Debug, Clone, Copy)]
+struct UndoInfo {
+    // 走过的着法, 带有被吃掉的棋子
+    mv: Move,
+    // 走这步之前的Zobrist键值
+    zobr: Zobrist,
+    // 走这步之前的无吃子步数
+    halfmove_clock: u32,
+}
+
+/// 局面不合法的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionError {
+    /// 缺少帅(将)
+    MissingKing(Color),
+    /// 棋子不可能出现在这个格子上, 如九宫外的仕、不在相位上的相、退到初始位置后面的兵
+    BadPlacement(Piece, Square),
+    /// 不走棋的一方正被将军(包括帅将照面), 走棋方可以直接吃掉帅(将)
+    OpponentInCheck(Color),
+}
+
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let side = |color: &Color| match color {
+            Color::Red => "红方",
+            Color::Black => "黑方",
+        };
+        match self {
+            PositionError::MissingKing(color) => write!(f, "{}缺少帅(将)", side(color)),
+            PositionError::BadPlacement(piece, sq) => write!(f, "棋子'{}'不可能出现在{}", piece, sq),
+            PositionError::OpponentInCheck(color) => write!(f, "{}不走棋却正被将军", side(color)),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+/// 棋子能否出现在这个格子上(不管是怎么走到的): 帅(将)仕(士)在九宫的相应位置上,
+/// 相(象)在己方的七个相位上, 没过河的兵(卒)在初始位置或其前一格
+pub(crate) fn can_stand_on(piece: Piece, sq: Square) -> bool {
+    let file = sq.file();
+    // 从己方底线数起的横线
+    let rank = match piece.color {
+        Color::Red => sq.rank(),
+        Color::Black => 9 - sq.rank(),
+    };
+    let in_palace = (3..=5).contains(&file) && rank <= 2;
+    match piece.kind {
+        PieceKind::King => in_palace,
+        PieceKind::Advisor => in_palace && (file + rank) % 2 == 1,
+        PieceKind::Bishop => rank <= 4 && file % 2 == 0 && rank % 2 == 0 && (file / 2 + rank / 2) % 2 == 1,
+        PieceKind::Pawn => rank >= 5 || (rank >= 3 && file % 2 == 0),
+        PieceKind::Knight | PieceKind::Rook | PieceKind::Cannon => true,
+    }
+}
 
 impl Position {
     /// 新建一个棋盘对象。
     pub fn new() -> Position {
         Position {
-            current_player: ChessPlayer::Red,
+            side_to_move: Color::Red,
             ucpc_squares: [0; 256],
             ucsq_pieces: [0; 48],
             dw_bit_piece: 0,
             w_bit_ranks: [0; 16],
             w_bit_files: [0; 16],
-            pre_gen: Box::new(PreGen::new()),
-            zobr: Zobrist::init_rc4(),
-            winner: None,
+            pre_gen: PreGen::shared(),
+            zobr: Zobrist::default(),
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            undo_stack: Vec::new(),
         }
     }
     pub fn piece_loc(&self) -> Vec<(char, u8)> {
@@ -54,8 +123,77 @@ impl Position {
     }
 
     /// 获取当前走棋方
-    pub fn current_player(&self) -> ChessPlayer {
-        self.current_player
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    /// 格子上的棋子, 空格返回None
+    pub fn piece_at(&self, sq: Square) -> Option<Piece> {
+        Piece::from_pc(self.ucpc_squares[sq.index()] as usize)
+    }
+
+    /// 一方某种棋子所在的所有格子
+    pub fn pieces(&self, color: Color, kind: PieceKind) -> Vec<Square> {
+        kind.index_range()
+            .filter_map(|i| match self.ucsq_pieces[color.side_tag() + i] {
+                0 => None,
+                sq => Some(Square::new_unchecked(sq as usize)),
+            })
+            .collect()
+    }
+
+    /// 一方帅(将)所在的格子, 没有帅(将)时(如空棋盘)返回None
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        self.pieces(color, PieceKind::King).first().copied()
+    }
+
+    /// 检查局面是否可能在对局中出现: 双方都有帅(将), 每个棋子都在它能到达的格子上,
+    /// 并且不走棋的一方没有被将军. 棋子的数量上限由FEN解析保证
+    pub fn validate(&self) -> Result<(), PositionError> {
+        for color in [Color::Red, Color::Black] {
+            if self.king_square(color).is_none() {
+                return Err(PositionError::MissingKing(color));
+            }
+            for kind in PieceKind::ALL {
+                let piece = Piece::new(color, kind);
+                if let Some(sq) = self.pieces(color, kind).into_iter().find(|&sq| !can_stand_on(piece, sq)) {
+                    return Err(PositionError::BadPlacement(piece, sq));
+                }
+            }
+        }
+        let opponent = self.side_to_move.opponent();
+        if self.is_in_check(opponent) {
+            return Err(PositionError::OpponentInCheck(opponent));
+        }
+        Ok(())
+    }
+
+    /// 一方的子力总价值, 见PieceKind::value
+    pub fn material(&self, color: Color) -> i32 {
+        PieceKind::ALL
+            .iter()
+            .map(|&kind| kind.value() * self.pieces(color, kind).len() as i32)
+            .sum()
+    }
+
+    /// Zobrist键值, 随走子增量更新, 用于置换表寻址
+    pub fn hash(&self) -> u32 {
+        self.zobr.key()
+    }
+
+    /// Zobrist校验锁, 用于确认置换表中的局面
+    pub fn lock(&self) -> u64 {
+        self.zobr.lock()
+    }
+
+    /// 自上次吃子以来的步数(半回合)
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    /// 当前回合数
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
     }
 
     // pub(crate) fn search_piece_locations(&self, piece: char) -> Vec<usize> {
@@ -70,6 +208,7 @@ impl Position {
     //     res
     // }
 
+    /// 由FEN构造局面, 不检查局面是否合法; 用`parse`得到的Fen已经检查过, 否则需要时调用validate
     pub fn from_fen(fen: &Fen) -> Self {
         let sz_fen = fen.fen_str();
         let mut pc_white = [0; 7];
@@ -96,7 +235,9 @@ impl Position {
         let mut j = FILE_LEFT;
 
         while let Some(ch) = lp_fen.next() {
-            if ch == '/' {
+            if ch == ' ' { //棋盘部分结束, 后面是走棋方
+                break;
+            } else if ch == '/' {
                 j = FILE_LEFT;
                 i += 1;
                 if i > RANK_BOTTOM {
@@ -135,13 +276,54 @@ impl Position {
             }
         }
 
+        // 走棋方后面依次是两个不用的字段("- -"), 无吃子步数和回合数
+        let rest: String = lp_fen.collect();
+        let mut fields = rest.split_whitespace().skip(2);
+        if let Some(n) = fields.next().and_then(|n| n.parse().ok()) {
+            position.halfmove_clock = n;
+        }
+        if let Some(n) = fields.next().and_then(|n| n.parse().ok()) {
+            position.fullmove_number = n;
+        }
+
         position
     }
-    fn change_side2(&mut self) {
-        match self.current_player {
-            ChessPlayer::Red => self.current_player = ChessPlayer::Black,
-            ChessPlayer::Black => self.current_player = ChessPlayer::Red,
+
+    /// 由棋子列表构造局面, 调用方保证格子不重复; 与FEN一样, 超过数量上限的棋子被忽略
+    pub(crate) fn from_pieces(pieces: &[(Square, Piece)], side: Color) -> Position {
+        let mut position = Position::new();
+        for &(sq, piece) in pieces {
+            let tag = piece.color.side_tag();
+            if let Some(pc) = piece.kind.index_range().map(|i| tag + i).find(|&pc| position.ucsq_pieces[pc] == 0) {
+                position.add_piece(sq.index(), pc);
+            }
         }
+        if side == Color::Black {
+            position.change_side2();
+        }
+        position
+    }
+
+    /// 沿中间的纵线左右镜像的局面, 走棋方、棋子序号和步数计数不变, 不带走过的着法.
+    /// 象棋的规则左右对称, 镜像局面的着法正好是原局面着法的镜像
+    pub fn mirror(&self) -> Position {
+        let mut position = Position::new();
+        for pc in 16..48 {
+            if let Some(sq) = Square::new(self.ucsq_pieces[pc] as usize) {
+                position.add_piece(sq.mirror().index(), pc);
+            }
+        }
+        if self.side_to_move == Color::Black {
+            position.change_side2();
+        }
+        position.halfmove_clock = self.halfmove_clock;
+        position.fullmove_number = self.fullmove_number;
+        position
+    }
+
+    fn change_side2(&mut self) {
+        self.side_to_move = self.side_to_move.opponent();
+        self.zobr.xor(&self.pre_gen.zobr_player);
     }
     fn add_piece(&mut self, sq: usize, pc: usize) {
         self.ucpc_squares[sq] = pc as u8;
@@ -201,25 +383,19 @@ impl Position {
         fen.pop();
         fen.push(' ');
 
-        if self.current_player == ChessPlayer::Red {
+        if self.side_to_move == Color::Red {
             fen.push('w');
         } else {
             fen.push('b');
         }
+        fen.push_str(&format!(" - - {} {}", self.halfmove_clock, self.fullmove_number));
 
-        Fen::new(Box::leak(fen.into_boxed_str()))
+        Fen::new(fen)
     }
     fn w_bit_piece(&self) -> u32 {
-        match self.current_player {
-            ChessPlayer::Red => self.dw_bit_piece & 0xffff,
-            ChessPlayer::Black => self.dw_bit_piece >> 16,
-        }
-    }
-
-    fn get_piece_char(&self, pt: usize) -> char {
-        match self.current_player {
-            ChessPlayer::Red => piece_char(pt),
-            ChessPlayer::Black => piece_char(pt).to_ascii_lowercase(),
+        match self.side_to_move {
+            Color::Red => self.dw_bit_piece & 0xffff,
+            Color::Black => self.dw_bit_piece >> 16,
         }
     }
 
@@ -255,16 +431,481 @@ impl Position {
         res
     }
 
+    /// 生成全部合法着法, 走完后本方帅(将)被将军(包括将帅照面)的着法会被过滤掉
     pub fn gen_legal_moves(&self) -> Vec<Move> {
-        let mut res = Vec::new();
-        res.append(&mut self.gen_cap_moves());
-        res.append(&mut self.gen_nocap_moves());
-        res
+        self.legal_moves().to_vec()
     }
-    fn gen_king_moves(&self) -> Vec<Move> {
-        let mut res = Vec::new();
-        let n_side_tag = side_tag2(&self.current_player);
-        let n_opp_side_tag = opp_side_tag2(&self.current_player);
+
+    /// 同`gen_legal_moves`, 但着法放在定长的`MoveList`里, 不分配堆内存
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        if self.is_in_check(self.side_to_move) {
+            self.gen_evasions(&mut moves);
+        } else {
+            self.gen_captures(&mut moves);
+            self.gen_quiets(&mut moves);
+            self.retain_legal(&mut moves, 0);
+        }
+        moves
+    }
+
+    /// 生成伪合法着法(不检查走完后是否被将军), 供搜索内部使用
+    pub fn gen_pseudo_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.gen_captures(&mut moves);
+        self.gen_quiets(&mut moves);
+        moves
+    }
+
+    /// 某一方的伪合法着法, 不管轮到哪方走, 用于评估机动性
+    pub(crate) fn pseudo_moves_of(&self, color: Color) -> MoveList {
+        let mut board = self.clone();
+        board.side_to_move = color;
+        board.gen_pseudo_moves()
+    }
+
+    /// 分阶段生成着法: 追加伪合法的吃子着法
+    pub fn gen_captures(&self, moves: &mut MoveList) {
+        self.gen_cap_moves(moves);
+    }
+
+    /// 分阶段生成着法: 追加伪合法的不吃子着法
+    pub fn gen_quiets(&self, moves: &mut MoveList) {
+        self.gen_nocap_moves(moves);
+    }
+
+    /// 被将军时追加应将着法, 即走完后不再被将军的着法, 追加的都是合法着法; 吃子着法在前.
+    /// 只生成可能应将的着法: 帅(将)走动, 吃掉将军的棋子, 垫在车、炮、将与帅(将)之间,
+    /// 别住马腿, 以及挪开本方的炮架; 然后在棋盘副本上试走去掉不合法的
+    pub fn gen_evasions(&self, moves: &mut MoveList) {
+        let sd = self.side_to_move as usize;
+        let n_opp_side_tag = opp_side_tag(sd);
+        let sq_king = self.ucsq_pieces[side_tag(sd) + KING_FROM] as usize;
+        if sq_king == 0 {
+            return;
+        }
+        let mut candidates = MoveList::new();
+        self.gen_king_moves(&mut candidates);
+
+        // 1. 找出将军的棋子, 收集走到上面就能应将的格子; 将帅照面时对方的将(帅)也算
+        let mut checkers = self.attack_mask(sq_king, self.side_to_move.opponent());
+        if self.kings_facing() {
+            checkers |= 1 << KING_FROM;
+        }
+        let mut targets = [0usize; 48];
+        let mut num_targets = 0;
+        let mut screens = [0usize; 2];
+        let mut num_screens = 0;
+        let mut add_target = |sq: usize| {
+            if !targets[..num_targets].contains(&sq) {
+                targets[num_targets] = sq;
+                num_targets += 1;
+            }
+        };
+        for i in 0..16 {
+            if checkers & (1 << i) == 0 {
+                continue;
+            }
+            let sq_checker = self.ucsq_pieces[n_opp_side_tag + i] as usize;
+            add_target(sq_checker);
+            match i {
+                KNIGHT_FROM..=KNIGHT_TO => add_target(knight_pin(sq_checker as i32, sq_king as i32) as usize),
+                KING_FROM | ROOK_FROM..=CANNON_TO => {
+                    // 车、将之间都是空格; 炮和帅(将)之间有一个炮架, 本方的可以挪开,
+                    // 对方的吃掉后吃子的棋子又成了炮架
+                    let step = if rank_y(sq_checker) == rank_y(sq_king) { 1 } else { 16 };
+                    let (lo, hi) = (sq_checker.min(sq_king), sq_checker.max(sq_king));
+                    for sq in (lo + step..hi).step_by(step) {
+                        let pc = self.ucpc_squares[sq] as usize;
+                        if pc == 0 {
+                            add_target(sq);
+                        } else if pc & side_tag(sd) != 0 {
+                            screens[num_screens] = sq;
+                            num_screens += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // 2. 挪开本方的炮架
+        for &sq_screen in &screens[..num_screens] {
+            self.gen_piece_moves(sq_screen, &mut candidates);
+        }
+
+        // 3. 帅(将)以外的棋子走到这些格子上: 吃子或者垫子
+        for &sq_dst in &targets[..num_targets] {
+            let empty = self.ucpc_squares[sq_dst] == 0;
+            let mut movers = self.reach_mask(sq_dst, self.side_to_move, empty) & !(1 << KING_FROM);
+            while movers != 0 {
+                let i = movers.trailing_zeros() as usize;
+                movers &= movers - 1;
+                let sq_src = self.ucsq_pieces[side_tag(sd) + i] as usize;
+                if !screens[..num_screens].contains(&sq_src) {
+                    candidates.push(self.new_move(sq_src, sq_dst as u8));
+                }
+            }
+        }
+
+        let start = moves.len();
+        for capture in [true, false] {
+            for mv in candidates.iter().filter(|mv| mv.captured().is_some() == capture) {
+                moves.push(*mv);
+            }
+        }
+        self.retain_legal(moves, start);
+    }
+
+    /// 追加某个棋子(帅将除外)的伪合法着法, 包括吃子和不吃子
+    fn gen_piece_moves(&self, sq_src: usize, moves: &mut MoveList) {
+        let n_side_tag = side_tag(self.side_to_move as usize);
+        let n_opp_side_tag = opp_side_tag(self.side_to_move as usize);
+        let mut push = |sq_dst: u8| {
+            if sq_dst != 0 && self.ucpc_squares[sq_dst as usize] as usize & n_side_tag == 0 {
+                moves.push(self.new_move(sq_src, sq_dst));
+            }
+        };
+        let pt = piece_type(self.ucpc_squares[sq_src] as usize) as usize;
+        match pt {
+            ADVISOR_TYPE => self.pre_gen.ucsq_advisor_moves[sq_src].into_iter().for_each(push),
+            BISHOP_TYPE | KNIGHT_TYPE => {
+                let (dsts, pins) = if pt == BISHOP_TYPE {
+                    (&self.pre_gen.ucsq_bishop_moves[sq_src][..], &self.pre_gen.ucsq_bishop_pins[sq_src][..])
+                } else {
+                    (&self.pre_gen.ucsq_knight_moves[sq_src][..], &self.pre_gen.ucsq_knight_pins[sq_src][..])
+                };
+                for (&sq_dst, &sq_pin) in dsts.iter().zip(pins) {
+                    if sq_dst != 0 && self.ucpc_squares[sq_pin as usize] == 0 {
+                        push(sq_dst);
+                    }
+                }
+            }
+            ROOK_TYPE | CANNON_TYPE => {
+                let (x, y) = (file_x(sq_src), rank_y(sq_src));
+                for (lpsmv, disp, step) in [(self.rank_move(x, y), rank_disp(y), 1), (self.file_move(x, y), file_disp(x), 16)] {
+                    // 不吃子的着法从最远的空格往回走到起点
+                    let mut sq_dst = lpsmv.uc_non_cap[0] + disp;
+                    while sq_dst as usize != sq_src {
+                        push(sq_dst);
+                        sq_dst -= step;
+                    }
+                    let mut sq_dst = lpsmv.uc_non_cap[1] + disp;
+                    while sq_dst as usize != sq_src {
+                        push(sq_dst);
+                        sq_dst += step;
+                    }
+                    let caps = if pt == ROOK_TYPE { lpsmv.uc_rook_cap } else { lpsmv.uc_cannon_cap };
+                    for cap in caps {
+                        let sq_dst = cap + disp;
+                        if sq_dst as usize != sq_src && self.ucpc_squares[sq_dst as usize] as usize & n_opp_side_tag != 0 {
+                            push(sq_dst);
+                        }
+                    }
+                }
+            }
+            PAWN_TYPE => self.pre_gen.ucsq_pawn_moves[self.side_to_move as usize][sq_src].into_iter().for_each(push),
+            _ => {}
+        }
+    }
+
+    /// 去掉moves[start..]中走完后本方帅(将)被将军的着法, 在不带撤销记录的棋盘副本上试走
+    fn retain_legal(&self, moves: &mut MoveList, start: usize) {
+        let mut board = self.clone();
+        let mut kept = start;
+        for i in start..moves.len() {
+            let mv = moves[i];
+            board.move_piece(&mv);
+            let legal = !board.is_in_check(self.side_to_move);
+            board.undo_move_piece(&mv);
+            if legal {
+                moves[kept] = mv;
+                kept += 1;
+            }
+        }
+        moves.truncate(kept);
+    }
+
+
+    /// 判断某一方的帅(将)是否被将军, 包括将帅照面
+    pub fn is_in_check(&self, side: Color) -> bool {
+        let sd = side as usize;
+        let n_opp_side_tag = opp_side_tag(sd);
+        let sq_src = self.ucsq_pieces[side_tag(sd) + KING_FROM] as usize;
+        if sq_src == 0 {
+            return false;
+        }
+        let is_opp = |pc: u8, from: usize, to: usize| {
+            (n_opp_side_tag + from..=n_opp_side_tag + to).contains(&(pc as usize))
+        };
+
+        // 1. 判断是否被对方的兵(卒)将军
+        let sq_forward = square_forward(sq_src as i32, sd as i32) as usize;
+        for sq_dst in [sq_forward, sq_src - 1, sq_src + 1] {
+            if is_opp(self.ucpc_squares[sq_dst], PAWN_FROM, PAWN_TO) {
+                return true;
+            }
+        }
+
+        // 2. 判断是否被对方的马将军(以帅(将)为起点, 蹩马腿的位置与马走法相反)
+        for i in KNIGHT_FROM..=KNIGHT_TO {
+            let sq_dst = self.ucsq_pieces[n_opp_side_tag + i];
+            if sq_dst != 0 {
+                let sq_pin = knight_pin(sq_dst as i32, sq_src as i32);
+                if sq_pin != sq_dst && self.ucpc_squares[sq_pin as usize] == 0 {
+                    return true;
+                }
+            }
+        }
+
+        // 3. 判断是否被对方的车或炮将军(包括将帅照面)
+        let x = file_x(sq_src);
+        let y = rank_y(sq_src);
+        let lpsmv = self.rank_move(x, y);
+        for i in 0..2 {
+            let sq_dst = (lpsmv.uc_rook_cap[i] + rank_disp(y)) as usize;
+            if sq_dst != sq_src && is_opp(self.ucpc_squares[sq_dst], ROOK_FROM, ROOK_TO) {
+                return true;
+            }
+            let sq_dst = (lpsmv.uc_cannon_cap[i] + rank_disp(y)) as usize;
+            if sq_dst != sq_src && is_opp(self.ucpc_squares[sq_dst], CANNON_FROM, CANNON_TO) {
+                return true;
+            }
+        }
+        let lpsmv = self.file_move(x, y);
+        for i in 0..2 {
+            let sq_dst = (lpsmv.uc_rook_cap[i] + file_disp(x)) as usize;
+            if sq_dst != sq_src {
+                let pc = self.ucpc_squares[sq_dst];
+                if is_opp(pc, ROOK_FROM, ROOK_TO) || is_opp(pc, KING_FROM, KING_FROM) {
+                    return true;
+                }
+            }
+            let sq_dst = (lpsmv.uc_cannon_cap[i] + file_disp(x)) as usize;
+            if sq_dst != sq_src && is_opp(self.ucpc_squares[sq_dst], CANNON_FROM, CANNON_TO) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 某一方能吃到这个格子上棋子的所有棋子(不考虑牵制和将帅照面), 按帅仕相马车炮兵排列
+    pub fn attackers(&self, sq: Square, color: Color) -> Vec<Square> {
+        let mask = self.attack_mask(sq.index(), color);
+        (0..16)
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| Square::new_unchecked(self.ucsq_pieces[color.side_tag() + i] as usize))
+            .collect()
+    }
+
+    /// 判断某一方是否攻击(保护)某个格子, 即是否有棋子能吃到这个格子上的棋子
+    pub fn is_attacked(&self, sq: Square, color: Color) -> bool {
+        self.attack_mask(sq.index(), color) != 0
+    }
+
+    /// 攻击某个格子的棋子, 第i位表示序号为side_tag + i的棋子能吃到这个格子
+    fn attack_mask(&self, sq: usize, color: Color) -> u16 {
+        self.reach_mask(sq, color, false)
+    }
+
+    /// 能走到某个格子的棋子, 同`attack_mask`; `empty`表示格子上没有棋子, 这时炮不用炮架, 像车一样走过去
+    fn reach_mask(&self, sq: usize, color: Color, empty: bool) -> u16 {
+        let sd = color as usize;
+        let n_side_tag = color.side_tag();
+        let mut mask = 0u16;
+        // 某个方向上没有棋子时, 车炮的查表结果是目标格子自己, 棋子不攻击自己所在的格子
+        let mut add = |sq_src: usize, from: usize, to: usize| {
+            let pc = self.ucpc_squares[sq_src] as usize;
+            if sq_src != sq && (n_side_tag + from..=n_side_tag + to).contains(&pc) {
+                mask |= 1 << (pc - n_side_tag);
+            }
+        };
+
+        // 1. 帅(将)、仕(士)只在九宫里, 相(象)不过河
+        if in_fort(sq as i32) {
+            for sq_src in [sq - 16, sq - 1, sq + 1, sq + 16] {
+                add(sq_src, KING_FROM, KING_FROM);
+            }
+            for sq_src in [sq - 17, sq - 15, sq + 15, sq + 17] {
+                add(sq_src, ADVISOR_FROM, ADVISOR_TO);
+            }
+        }
+        if home_half(sq, sd) {
+            for sq_src in [sq - 34, sq - 30, sq + 30, sq + 34] {
+                if self.ucpc_squares[bishop_pin(sq_src as i32, sq as i32) as usize] == 0 {
+                    add(sq_src, BISHOP_FROM, BISHOP_TO);
+                }
+            }
+        }
+
+        // 2. 兵(卒)从后方进攻, 过河后还能从左右进攻
+        add(square_backward(sq, sd), PAWN_FROM, PAWN_TO);
+        if away_half(sq as i32, sd as i32) {
+            add(sq - 1, PAWN_FROM, PAWN_TO);
+            add(sq + 1, PAWN_FROM, PAWN_TO);
+        }
+
+        // 3. 马, 马腿上不能有棋子
+        for i in KNIGHT_FROM..=KNIGHT_TO {
+            let sq_src = self.ucsq_pieces[n_side_tag + i];
+            if sq_src != 0 {
+                let sq_pin = knight_pin(sq_src as i32, sq as i32);
+                if sq_pin != sq_src && self.ucpc_squares[sq_pin as usize] == 0 {
+                    add(sq_src as usize, KNIGHT_FROM, KNIGHT_TO);
+                }
+            }
+        }
+
+        // 4. 车和炮, 从目标格子往四个方向看, 第一个棋子是车、隔一个炮架的是炮
+        let x = file_x(sq);
+        let y = rank_y(sq);
+        let lpsmv = self.rank_move(x, y);
+        let cannon_cap = if empty { lpsmv.uc_rook_cap } else { lpsmv.uc_cannon_cap };
+        for i in 0..2 {
+            add((lpsmv.uc_rook_cap[i] + rank_disp(y)) as usize, ROOK_FROM, ROOK_TO);
+            add((cannon_cap[i] + rank_disp(y)) as usize, CANNON_FROM, CANNON_TO);
+        }
+        let lpsmv = self.file_move(x, y);
+        let cannon_cap = if empty { lpsmv.uc_rook_cap } else { lpsmv.uc_cannon_cap };
+        for i in 0..2 {
+            add((lpsmv.uc_rook_cap[i] + file_disp(x)) as usize, ROOK_FROM, ROOK_TO);
+            add((cannon_cap[i] + file_disp(x)) as usize, CANNON_FROM, CANNON_TO);
+        }
+        mask
+    }
+
+    /// 静态交换评估(SEE): 双方轮流用最便宜的棋子吃目标格子上的棋子, 每一方都可以停止交换,
+    /// 返回走这步着法的子力得失(兵为10, 见`PieceKind::value`); 不吃子的着法从0开始算.
+    /// 每次吃子后重新计算攻击者, 所以车炮后面的车、新出现或消失的炮架、让开的马腿都会算进去,
+    /// 但不考虑牵制和将帅照面
+    pub fn see(&self, mv: &Move) -> i32 {
+        let sq_dst = mv.to().index();
+        let mut board = self.clone();
+        let mut gain = [0; 33];
+        gain[0] = see_value(board.ucpc_squares[sq_dst]);
+        let mut attacker = see_value(board.ucpc_squares[mv.from().index()]);
+        board.move_piece(&board.new_move(mv.from().index(), sq_dst as u8));
+
+        let mut color = self.side_to_move.opponent();
+        let mut depth = 0;
+        while let Some(sq_src) = board.least_valuable_attacker(sq_dst, color) {
+            depth += 1;
+            // 假设对方不再吃回, 这一方的得失
+            gain[depth] = attacker - gain[depth - 1];
+            attacker = see_value(board.ucpc_squares[sq_src]);
+            board.move_piece(&board.new_move(sq_src, sq_dst as u8));
+            color = color.opponent();
+        }
+        // 从最后一次吃子往回倒推, 每一方都可以选择不吃
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    /// 能吃到目标格子的最便宜的棋子所在的格子
+    fn least_valuable_attacker(&self, sq: usize, color: Color) -> Option<usize> {
+        let mask = self.attack_mask(sq, color);
+        if mask == 0 {
+            return None;
+        }
+        const SEE_ORDER: [PieceKind; 7] = [
+            PieceKind::Pawn,
+            PieceKind::Advisor,
+            PieceKind::Bishop,
+            PieceKind::Knight,
+            PieceKind::Cannon,
+            PieceKind::Rook,
+            PieceKind::King,
+        ];
+        SEE_ORDER
+            .into_iter()
+            .flat_map(PieceKind::index_range)
+            .find(|&i| mask & (1 << i) != 0)
+            .map(|i| self.ucsq_pieces[color.side_tag() + i] as usize)
+    }
+
+    /// 判断刚走完的一步是否在捉子, 返回被捉的棋子(0表示没有捉子)
+    /// 与ElephantEye一样, 只考虑走动的马、车、炮:
+    /// 马、炮捉车, 以及捉无根的马、炮(不含同类互捉)和过河兵(卒)
+    pub(crate) fn chased_by(&self, mv: &Move) -> usize {
+        // 走完后轮到被捉的一方走棋
+        let sd = self.side_to_move as usize;
+        let n_side_tag = side_tag(sd);
+        let sq_src = mv.to().index();
+        let pt = piece_type(self.ucpc_squares[sq_src] as usize) as usize;
+
+        let mut targets = Vec::new();
+        match pt {
+            KNIGHT_TYPE => {
+                let lpucsq_dst = self.pre_gen.ucsq_knight_moves[sq_src];
+                let lpucsq_pin = self.pre_gen.ucsq_knight_pins[sq_src];
+                for (sq_dst, sq_pin) in lpucsq_dst.into_iter().zip(lpucsq_pin) {
+                    if sq_dst != 0 && self.ucpc_squares[sq_pin as usize] == 0 {
+                        targets.push(sq_dst as usize);
+                    }
+                }
+            }
+            ROOK_TYPE | CANNON_TYPE => {
+                let x = file_x(sq_src);
+                let y = rank_y(sq_src);
+                let rank = self.rank_move(x, y);
+                let file = self.file_move(x, y);
+                let (rank_cap, file_cap) = if pt == ROOK_TYPE {
+                    (rank.uc_rook_cap, file.uc_rook_cap)
+                } else {
+                    (rank.uc_cannon_cap, file.uc_cannon_cap)
+                };
+                for i in 0..2 {
+                    targets.push((rank_cap[i] + rank_disp(y)) as usize);
+                    targets.push((file_cap[i] + file_disp(x)) as usize);
+                }
+            }
+            _ => return 0,
+        }
+
+        for sq_dst in targets {
+            let pc = self.ucpc_squares[sq_dst] as usize;
+            if pc & n_side_tag == 0 {
+                continue;
+            }
+            let chased = match pc - n_side_tag {
+                ROOK_FROM..=ROOK_TO => pt != ROOK_TYPE,
+                KNIGHT_FROM..=KNIGHT_TO => pt != KNIGHT_TYPE && self.attack_mask(sq_dst, self.side_to_move) == 0,
+                CANNON_FROM..=CANNON_TO => pt != CANNON_TYPE && self.attack_mask(sq_dst, self.side_to_move) == 0,
+                PAWN_FROM..=PAWN_TO => away_half(sq_dst as i32, sd as i32) && self.attack_mask(sq_dst, self.side_to_move) == 0,
+                _ => false,
+            };
+            if chased {
+                return pc;
+            }
+        }
+        0
+    }
+
+    /// 判断走法是否吃子
+    pub(crate) fn is_capture(&self, mv: &Move) -> bool {
+        self.ucpc_squares[mv.to().index()] != 0
+    }
+
+    /// 判断将帅是否照面(同一列且中间没有棋子)
+    pub fn kings_facing(&self) -> bool {
+        let sq_red = self.ucsq_pieces[side_tag(0) + KING_FROM] as usize;
+        let sq_black = self.ucsq_pieces[side_tag(1) + KING_FROM] as usize;
+        if sq_red == 0 || sq_black == 0 || file_x(sq_red) != file_x(sq_black) {
+            return false;
+        }
+        let x = file_x(sq_red);
+        let lpsmv = self.file_move(x, rank_y(sq_red));
+        lpsmv
+            .uc_rook_cap
+            .iter()
+            .any(|&sq_dst| (sq_dst + file_disp(x)) as usize == sq_black)
+    }
+    fn gen_king_moves(&self, moves: &mut MoveList) {
+        let n_side_tag = side_tag2(&self.side_to_move);
+        let n_opp_side_tag = opp_side_tag2(&self.side_to_move);
         // 1. 生成帅(将)的着法
         let sq_src = self.ucsq_pieces[n_side_tag as usize + KING_FROM];
         if sq_src != 0 {
@@ -274,30 +915,20 @@ impl Position {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured == 0 {
                         //不吃子着法
-                        res.push(Move::new(
-                            self.get_piece_char(KING_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                     if (pc_captured & n_opp_side_tag as u8) != 0 {
                         // 吃子着法
-                        res.push(Move::new(
-                            self.get_piece_char(KING_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
             }
         }
-        res
     }
 
-    fn gen_cap_moves(&self) -> Vec<Move> {
-        let mut res = Vec::new();
-        let n_side_tag = side_tag2(&self.current_player);
-        let n_opp_side_tag = opp_side_tag2(&self.current_player);
+    fn gen_cap_moves(&self, moves: &mut MoveList) {
+        let n_side_tag = side_tag2(&self.side_to_move);
+        let n_opp_side_tag = opp_side_tag2(&self.side_to_move);
 
         // 1. 生成帅(将)的着法
         let sq_src = self.ucsq_pieces[n_side_tag as usize + KING_FROM];
@@ -307,14 +938,8 @@ impl Position {
                 if sq_dst != 0 {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if (pc_captured & n_opp_side_tag as u8) != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(KING_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
-                } else {
-                    break;
                 }
             }
         }
@@ -329,14 +954,8 @@ impl Position {
                     if sq_dst != &0 {
                         let pc_captured = self.ucpc_squares[*sq_dst as usize];
                         if (pc_captured & n_opp_side_tag as u8) != 0 {
-                            res.push(Move::new(
-                                self.get_piece_char(ADVISOR_TYPE),
-                                sq_src as usize,
-                                *sq_dst,
-                            ));
+                            moves.push(self.new_move(sq_src as usize, *sq_dst));
                         }
-                    } else {
-                        break;
                     }
                 }
             }
@@ -354,15 +973,9 @@ impl Position {
                         if self.ucpc_squares[*pin as usize] == 0 {
                             let pc_captured = self.ucpc_squares[*sq_dst as usize];
                             if (pc_captured & n_opp_side_tag as u8) != 0 {
-                                res.push(Move::new(
-                                    self.get_piece_char(BISHOP_TYPE),
-                                    sq_src as usize,
-                                    *sq_dst,
-                                ));
+                                moves.push(self.new_move(sq_src as usize, *sq_dst));
                             }
                         }
-                    } else {
-                        break;
                     }
                 }
             }
@@ -378,11 +991,7 @@ impl Position {
                     if self.ucpc_squares[*pin as usize] == 0 {
                         let pc_captured = self.ucpc_squares[*sq_dst as usize];
                         if (pc_captured & n_opp_side_tag as u8) != 0 {
-                            res.push(Move::new(
-                                self.get_piece_char(KNIGHT_TYPE),
-                                sq_src as usize,
-                                *sq_dst,
-                            ));
+                            moves.push(self.new_move(sq_src as usize, *sq_dst));
                         }
                     }
                 }
@@ -400,22 +1009,14 @@ impl Position {
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(ROOK_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
                 let sq_dst = lpsmv.uc_rook_cap[1] + rank_disp(y);
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(ROOK_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
 
@@ -424,22 +1025,14 @@ impl Position {
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(ROOK_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
                 let sq_dst = lpsmv.uc_rook_cap[1] + file_disp(x);
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(ROOK_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
             }
@@ -457,22 +1050,14 @@ impl Position {
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(CANNON_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
                 let sq_dst = lpsmv.uc_cannon_cap[1] + rank_disp(y);
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(CANNON_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
 
@@ -481,11 +1066,7 @@ impl Position {
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(CANNON_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
                 let sq_dst = lpsmv.uc_cannon_cap[1] + file_disp(x);
@@ -493,11 +1074,7 @@ impl Position {
                 if sq_dst != sq_src {
                     let pc_captured = self.ucpc_squares[sq_dst as usize];
                     if pc_captured & n_opp_side_tag as u8 != 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(CANNON_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
             }
@@ -508,27 +1085,21 @@ impl Position {
             let sq_src = self.ucsq_pieces[n_side_tag as usize + i];
             if sq_src != 0 {
                 let lpucsq_dst =
-                    self.pre_gen.ucsq_pawn_moves[self.current_player as usize][sq_src as usize];
+                    self.pre_gen.ucsq_pawn_moves[self.side_to_move as usize][sq_src as usize];
                 for sq_dst in lpucsq_dst {
                     if sq_dst != 0 {
                         let pc_captured = self.ucpc_squares[sq_dst as usize];
                         if pc_captured & n_opp_side_tag as u8 != 0 {
-                            res.push(Move::new(
-                                self.get_piece_char(PAWN_TYPE),
-                                sq_src as usize,
-                                sq_dst,
-                            ));
+                            moves.push(self.new_move(sq_src as usize, sq_dst));
                         }
                     }
                 }
             }
         }
-        res
     }
 
-    fn gen_nocap_moves(&self) -> Vec<Move> {
-        let mut res = Vec::new();
-        let n_side_tag = side_tag2(&self.current_player);
+    fn gen_nocap_moves(&self, moves: &mut MoveList) {
+        let n_side_tag = side_tag2(&self.side_to_move);
         // 1. 生成帅(将)的着法
         let sq_src = self.ucsq_pieces[n_side_tag as usize + KING_FROM];
         if sq_src != 0 {
@@ -536,11 +1107,7 @@ impl Position {
             for sq_dst in lpucsq_dst {
                 if sq_dst != 0 {
                     if self.ucpc_squares[sq_dst as usize] == 0 {
-                        res.push(Move::new(
-                            self.get_piece_char(KING_TYPE),
-                            sq_src as usize,
-                            sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, sq_dst));
                     }
                 }
             }
@@ -554,11 +1121,7 @@ impl Position {
                 for sq_dst in lpucsq_dst {
                     if sq_dst != 0 {
                         if self.ucpc_squares[sq_dst as usize] == 0 {
-                            res.push(Move::new(
-                                self.get_piece_char(ADVISOR_TYPE),
-                                sq_src as usize,
-                                sq_dst,
-                            ));
+                            moves.push(self.new_move(sq_src as usize, sq_dst));
                         }
                     }
                 }
@@ -571,17 +1134,12 @@ impl Position {
             if sq_src != 0 {
                 let lpucsq_dst = self.pre_gen.ucsq_bishop_moves[sq_src as usize];
                 let lpucsq_pin = self.pre_gen.ucsq_bishop_pins[sq_src as usize];
-                let mut lpucsq_pin_iter = lpucsq_pin.iter();
-                for sq_dst in lpucsq_dst {
+                for (sq_dst, sq_pin) in lpucsq_dst.into_iter().zip(lpucsq_pin) {
                     if sq_dst != 0 {
-                        if self.ucpc_squares[*lpucsq_pin_iter.next().unwrap() as usize] == 0
+                        if self.ucpc_squares[sq_pin as usize] == 0
                             && self.ucpc_squares[sq_dst as usize] == 0
                         {
-                            res.push(Move::new(
-                                self.get_piece_char(BISHOP_TYPE),
-                                sq_src as usize,
-                                sq_dst,
-                            ));
+                            moves.push(self.new_move(sq_src as usize, sq_dst));
                         }
                     }
                 }
@@ -599,11 +1157,7 @@ impl Position {
                         && self.ucpc_squares[sq_pin as usize] == 0
                         && self.ucpc_squares[*sq_dst as usize] == 0
                     {
-                        res.push(Move::new(
-                            self.get_piece_char(KNIGHT_TYPE),
-                            sq_src as usize,
-                            *sq_dst,
-                        ));
+                        moves.push(self.new_move(sq_src as usize, *sq_dst));
                     }
                 }
             }
@@ -617,33 +1171,28 @@ impl Position {
                 let y = rank_y(sq_src as usize);
                 let lpsmv = self.rank_move(x, y);
                 let mut sq_dst = lpsmv.uc_non_cap[0] + rank_disp(y);
-                let piece_type = if i < CANNON_FROM {
-                    ROOK_TYPE
-                } else {
-                    CANNON_TYPE
-                };
                 while sq_dst != sq_src && sq_dst > 0 {
-                    let mv = Move::new(self.get_piece_char(piece_type), sq_src as usize, sq_dst);
-                    res.push(mv);
+                    let mv = self.new_move(sq_src as usize, sq_dst);
+                    moves.push(mv);
                     sq_dst -= 1;
                 }
                 sq_dst = lpsmv.uc_non_cap[1] + rank_disp(y);
                 while sq_dst != sq_src && sq_dst > 0 {
-                    let mv = Move::new(self.get_piece_char(piece_type), sq_src as usize, sq_dst);
-                    res.push(mv);
+                    let mv = self.new_move(sq_src as usize, sq_dst);
+                    moves.push(mv);
                     sq_dst += 1;
                 }
                 let lpsmv = self.file_move(x, y);
                 sq_dst = lpsmv.uc_non_cap[0] + file_disp(x);
                 while sq_dst != sq_src && sq_dst > 0 {
-                    let mv = Move::new(self.get_piece_char(piece_type), sq_src as usize, sq_dst);
-                    res.push(mv);
+                    let mv = self.new_move(sq_src as usize, sq_dst);
+                    moves.push(mv);
                     sq_dst -= 16;
                 }
                 sq_dst = lpsmv.uc_non_cap[1] + file_disp(x);
                 while sq_dst != sq_src {
-                    let mv = Move::new(self.get_piece_char(piece_type), sq_src as usize, sq_dst);
-                    res.push(mv);
+                    let mv = self.new_move(sq_src as usize, sq_dst);
+                    moves.push(mv);
                     sq_dst += 16;
                 }
             }
@@ -654,42 +1203,194 @@ impl Position {
             let sq_src = self.ucsq_pieces[n_side_tag as usize + i];
             if sq_src != 0 {
                 let lpucsq_dst =
-                    self.pre_gen.ucsq_pawn_moves[self.current_player as usize][sq_src as usize];
+                    self.pre_gen.ucsq_pawn_moves[self.side_to_move as usize][sq_src as usize];
                 for sq_dst in lpucsq_dst {
                     if sq_dst != 0 {
                         if self.ucpc_squares[sq_dst as usize] == 0 {
-                            res.push(Move::new(
-                                self.get_piece_char(PAWN_TYPE),
-                                sq_src as usize,
-                                sq_dst,
-                            ));
+                            moves.push(self.new_move(sq_src as usize, sq_dst));
                         }
                     }
                 }
             }
         }
-        res
     }
 
-    pub fn make_move(&mut self, mv: Move) {
-        let pc_dst = self.ucpc_squares[mv.to as usize];
-        if (pc_dst > 0) { //目标位置有棋子, 先去掉目标位置的棋子,
-            self.del_piece(mv.to as usize, pc_dst as usize);
-            // 检查是否吃掉将/帅
-            let pt = piece_type(pc_dst as usize) as usize;
-            if pt == KING_TYPE {
-               self.winner=Some(self.current_player);
-            }
+    /// 用棋盘上的棋子生成着法, 填上走动的棋子和被吃的棋子
+    fn new_move(&self, sq_src: usize, sq_dst: u8) -> Move {
+        Move::new(self.ucpc_squares[sq_src], sq_src, sq_dst as usize, self.ucpc_squares[sq_dst as usize])
+    }
+
+    /// 格子上的棋子序号, 0表示没有棋子
+    pub(crate) fn pc_on(&self, sq: usize) -> u8 {
+        self.ucpc_squares[sq]
+    }
+
+    /// 移动棋子, 不切换走棋方; 着法中的被吃棋子必须与棋盘一致
+    fn move_piece(&mut self, mv: &Move) {
+        let sq_src = mv.from().index();
+        let sq_dst = mv.to().index();
+        let pc_captured = mv.pc_captured();
+        if pc_captured != 0 { //目标位置有棋子, 先去掉目标位置的棋子
+            self.del_piece(sq_dst, pc_captured);
         }
         // 把源位置上的棋子移动到目标位置
-        let pc = self.ucpc_squares[mv.from];
-        self.add_piece(mv.to as usize, pc as usize); //添加目标位置棋子
-        self.del_piece(mv.from as usize, pc as usize); //移除源位置棋子
+        let pc = self.ucpc_squares[sq_src] as usize;
+        self.del_piece(sq_src, pc); //移除源位置棋子
+        self.add_piece(sq_dst, pc); //添加目标位置棋子
+    }
+
+    /// 撤销move_piece, 恢复被吃掉的棋子
+    fn undo_move_piece(&mut self, mv: &Move) {
+        let sq_src = mv.from().index();
+        let sq_dst = mv.to().index();
+        let pc = self.ucpc_squares[sq_dst] as usize;
+        self.del_piece(sq_dst, pc);
+        self.add_piece(sq_src, pc);
+        if mv.pc_captured() != 0 {
+            self.add_piece(sq_dst, mv.pc_captured());
+        }
+    }
+
+    /// 走一步棋, 着法中的棋子以棋盘为准(可以是只从动作序号还原的着法)
+    pub fn make_move(&mut self, mv: Move) {
+        let mv = self.new_move(mv.from().index(), mv.to().index() as u8);
+        let zobr = self.zobr;
+        let halfmove_clock = self.halfmove_clock;
+        self.move_piece(&mv);
+        self.undo_stack.push(UndoInfo { mv, zobr, halfmove_clock });
+        // 更新无吃子步数和回合数
+        if mv.pc_captured() != 0 {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.side_to_move == Color::Black {
+            self.fullmove_number += 1;
+        }
         // 切换走棋方
         self.change_side2();
     }
-    fn check_mate(&self) -> bool {
-        todo!()
+
+    /// 撤销最后一步着法, 返回被撤销的着法, 没有可撤销的着法时返回None
+    pub fn undo_move(&mut self) -> Option<Move> {
+        let undo = self.undo_stack.pop()?;
+        self.unmake(&undo.mv, undo.halfmove_clock);
+        debug_assert_eq!(self.zobr, undo.zobr);
+        Some(undo.mv)
+    }
+
+    /// 撤销最后走的着法`mv`(带有被吃的棋子), 恢复走之前的无吃子步数; 撤销记录由调用方保存,
+    /// 用于复制出来、没有撤销记录的局面
+    pub(crate) fn unmake_move(&mut self, mv: Move, halfmove_clock: u32) {
+        if let Some(undo) = self.undo_stack.pop() {
+            debug_assert_eq!(undo.mv, mv);
+        }
+        self.unmake(&mv, halfmove_clock);
+    }
+
+    fn unmake(&mut self, mv: &Move, halfmove_clock: u32) {
+        self.change_side2();
+        if self.side_to_move == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        self.undo_move_piece(mv);
+        self.halfmove_clock = halfmove_clock;
+    }
+
+    /// 判断走棋方是否被将死
+    pub fn check_mate(&self) -> bool {
+        self.is_in_check(self.side_to_move) && self.legal_moves().is_empty()
+    }
+
+    /// 判断走棋方是否被困毙(没有被将军, 但已无棋可走)
+    pub fn stale_mate(&self) -> bool {
+        !self.is_in_check(self.side_to_move) && self.legal_moves().is_empty()
+    }
+
+    /// 对局的胜方: 走棋方被将死或困毙(象棋中困毙也判负)时, 对方获胜;
+    /// 局面中缺少帅(将)的一方直接判负
+    pub fn winner(&self) -> Option<Color> {
+        let opponent = self.side_to_move.opponent();
+        if self.ucsq_pieces[side_tag(opponent as usize) + KING_FROM] == 0 {
+            return Some(self.side_to_move);
+        }
+        if self.ucsq_pieces[side_tag(self.side_to_move as usize) + KING_FROM] == 0
+            || self.legal_moves().is_empty()
+        {
+            return Some(opponent);
+        }
+        None
+    }
+
+    /// 走法生成器的校验: 统计从当前局面出发走depth步后的所有合法局面数
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        self.clone().perft_inner(depth)
+    }
+
+    /// 按第一步着法分别统计perft(depth - 1), 便于与其他引擎逐个着法比对
+    pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return vec![];
+        }
+        let mut position = self.clone();
+        position
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                position.make_move(mv);
+                let nodes = if depth == 1 { 1 } else { position.perft_inner(depth - 1) };
+                position.undo_move();
+                (mv, nodes)
+            })
+            .collect()
+    }
+
+    fn perft_inner(&mut self, depth: u32) -> u64 {
+        let moves = self.legal_moves();
+        // 最后一层直接计数, 不必走子
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            self.make_move(mv);
+            nodes += self.perft_inner(depth - 1);
+            self.undo_move();
+        }
+        nodes
+    }
+}
+
+impl Clone for Position {
+    fn clone(&self) -> Self {
+        Position { undo_stack: Vec::new(), ..*self }
+    }
+}
+
+impl PartialEq for Position {
+    fn eq(&self, other: &Self) -> bool {
+        self.zobr == other.zobr
+    }
+}
+
+impl Eq for Position {}
+
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u32(self.zobr.key());
+        state.write_u64(self.zobr.lock());
+    }
+}
+
+/// SEE中棋子的价值; 帅(将)被吃就输了, 比其他棋子都大得多
+fn see_value(pc: u8) -> i32 {
+    match Piece::from_pc(pc as usize) {
+        Some(piece) if piece.kind == PieceKind::King => 1000,
+        Some(piece) => piece.kind.value(),
+        None => 0,
     }
 }
 
@@ -713,6 +1414,10 @@ mod tests {
     use crate::position::{rank_y, Position};
     use crate::{away_half, square_forward};
     use crate::fen::Fen;
+    use crate::movelist::MoveList;
+    use crate::moves::Move;
+    use crate::piece::{Color, Piece, PieceKind};
+    use crate::square::Square;
 
     #[test]
     fn test_fen() {
@@ -724,11 +1429,74 @@ mod tests {
         //let moves = board.generate_all_moves(&Side::Red);
     }
 
+    #[test]
+    fn test_move_counters() {
+        let mut position = Position::from_fen(&Fen::new("3k5/9/9/9/9/9/9/9/3Rp4/4K4 b - - 7 30"));
+        assert_eq!(position.halfmove_clock(), 7);
+        assert_eq!(position.fullmove_number(), 30);
+        // 卒吃车, 无吃子步数清零, 黑方走完回合数加1
+        let mv = position.gen_legal_moves().into_iter().find(|mv| mv.to().index() == 0xb6).unwrap();
+        assert_eq!((mv.piece(), mv.captured()), (Piece::from_char('p'), Piece::from_char('R')));
+        // 只从动作序号还原的着法, 被吃的棋子以棋盘为准
+        position.make_move(Move::try_from(usize::from(mv)).unwrap());
+        assert_eq!(position.halfmove_clock(), 0);
+        assert_eq!(position.fullmove_number(), 31);
+        assert_eq!(position.to_fen(), Fen::new("3k5/9/9/9/9/9/9/9/3p5/4K4 w - - 0 31"));
+        assert_eq!(position.undo_move().and_then(|mv| mv.captured()), Piece::from_char('R'));
+    }
+
+    #[test]
+    fn test_zobrist_incremental() {
+        let start = Position::from_fen(&Fen::init());
+        assert_eq!(start.hash(), Position::from_fen(&Fen::init()).hash());
+        assert_ne!(start.hash(), 0);
+
+        // 走子后增量更新的键值和重新从FEN生成的一致
+        let mut position = start.clone();
+        for _ in 0..6 {
+            let mv = position.gen_legal_moves()[3];
+            position.make_move(mv);
+            let fresh = Position::from_fen(&position.to_fen());
+            assert_eq!(position.hash(), fresh.hash());
+            assert_eq!(position.lock(), fresh.lock());
+            assert_eq!(position, fresh);
+        }
+
+        // 只有走棋方不同的局面键值不同
+        let black = Position::from_fen(&Fen::new(
+            "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b - - 0 1",
+        ));
+        assert_ne!(start.hash(), black.hash());
+        assert_ne!(start, black);
+    }
+
+    #[test]
+    fn test_undo_move() {
+        let start = Position::from_fen(&Fen::new("3k5/9/9/9/9/9/9/9/3Rp4/4K4 b - - 7 30"));
+        let mut position = start.clone();
+        let mut moves = Vec::new();
+        for _ in 0..4 {
+            // 优先吃子, 检查被吃的棋子能恢复
+            let mv = position.gen_legal_moves()[0];
+            moves.push(mv);
+            position.make_move(mv);
+        }
+        // 复制的局面不带撤销记录
+        assert_eq!(position.clone().undo_move(), None);
+        while let Some(mv) = position.undo_move() {
+            assert_eq!(Some(mv), moves.pop());
+        }
+        assert_eq!(position, start);
+        assert_eq!(position.to_fen(), start.to_fen());
+        assert_eq!(position.gen_legal_moves(), start.gen_legal_moves());
+    }
+
     #[test]
     fn test_gen_cap_moves() {
         let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
         let position = Position::from_fen(&Fen::new(fen));
-        let moves = position.gen_cap_moves();
+        let mut moves = MoveList::new();
+        position.gen_captures(&mut moves);
         assert_eq!(moves.len(), 2);
     }
 
@@ -736,8 +1504,9 @@ mod tests {
     fn test_gen_nocap_moves() {
         let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
         let position = Position::from_fen(&Fen::new(fen));
-        let moves = position.gen_nocap_moves();
-        for mv in moves.iter().filter(|mv| mv.piece == 'C' || mv.piece == 'R') {
+        let mut moves = MoveList::new();
+        position.gen_quiets(&mut moves);
+        for mv in moves.iter().filter(|mv| matches!(mv.piece().map(|p| p.kind), Some(PieceKind::Cannon | PieceKind::Rook))) {
             println!("{}", &mv.to_string());
         }
 
@@ -750,6 +1519,246 @@ mod tests {
         let moves = position.gen_legal_moves();
         assert_eq!(moves.len(), 44);
     }
+
+    #[rstest]
+    // 卒将军, 吃卒会与将照面, 帅只能左右闪开
+    #[case("4k4/9/9/9/9/9/9/9/4p4/4K4 w", &["e0d0", "e0f0"])]
+    // 车将军, 只能出帅或者垫仕
+    #[case("4k4/9/9/9/9/4r4/9/9/9/3AK4 w", &["e0f0", "d0e1"])]
+    fn test_gen_evasions(#[case] fen: &str, #[case] expected: &[&str]) {
+        let position = Position::from_fen(&Fen::new(fen));
+        assert!(position.is_in_check(position.side_to_move()));
+        let mut moves = MoveList::new();
+        position.gen_evasions(&mut moves);
+        assert_eq!(moves.iter().map(Move::to_iccs).collect::<Vec<_>>(), expected);
+        assert_eq!(position.legal_moves(), moves);
+    }
+
+    #[rstest]
+    // 马将军, 车塞马腿
+    #[case("3k5/9/9/9/9/9/9/3n5/R8/4K4 w", "a1d1")]
+    // 炮隔着本方的马将军, 马跳开或者车吃炮
+    #[case("3k5/9/9/9/R3c4/9/9/4N4/9/4K4 w", "e2c3")]
+    #[case("3k5/9/9/9/R3c4/9/9/4N4/9/4K4 w", "a5e5")]
+    // 炮隔着对方的卒将军, 吃卒还是炮架, 车垫在炮和卒之间
+    #[case("3k5/9/9/9/4c4/R8/4p4/9/9/4K4 w", "a4e4")]
+    // 将帅照面, 车垫在中间
+    #[case("4k4/9/9/9/R8/9/9/9/9/4K4 w", "a5e5")]
+    // 车和以车为炮架的炮同时将军, 吃车后车又成了炮架, 只能出帅
+    #[case("3k5/9/4c4/9/R3r4/9/9/9/9/4K4 w", "e0f0")]
+    fn test_gen_evasions_matches_filtered_moves(#[case] fen: &str, #[case] evasion: &str) {
+        let position = Position::from_fen(&Fen::new(fen));
+        assert!(position.is_in_check(position.side_to_move()));
+        let mut moves = MoveList::new();
+        position.gen_evasions(&mut moves);
+        let captures = moves.iter().take_while(|mv| position.is_capture(mv)).count();
+        assert!(moves[captures..].iter().all(|mv| !position.is_capture(mv)));
+
+        let mut expected = position.gen_pseudo_moves();
+        position.retain_legal(&mut expected, 0);
+        let mut iccs: Vec<_> = moves.iter().map(Move::to_iccs).collect();
+        iccs.sort();
+        let mut expected: Vec<_> = expected.iter().map(Move::to_iccs).collect();
+        expected.sort();
+        assert_eq!(iccs, expected);
+        assert!(iccs.contains(&evasion.to_string()));
+    }
+
+    #[test]
+    fn test_staged_moves() {
+        let fen = "4k4/9/3n5/2P1N4/4p4/3N5/2n6/9/4A4/3AK4 w";
+        let position = Position::from_fen(&Fen::new(fen));
+        let mut staged = MoveList::new();
+        position.gen_captures(&mut staged);
+        let captures = staged.len();
+        position.gen_quiets(&mut staged);
+        assert!(staged[..captures].iter().all(|mv| position.is_capture(mv)));
+        assert!(staged[captures..].iter().all(|mv| !position.is_capture(mv)));
+        assert_eq!(staged, position.gen_pseudo_moves());
+
+        let legal = position.legal_moves();
+        assert_eq!(legal.len(), 19);
+        assert!(legal.iter().all(|mv| staged.contains(mv)));
+    }
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", false)]
+    #[case("4k4/9/9/9/9/9/9/9/4p4/4K4 w", true)]
+    #[case("3k5/9/9/9/9/9/9/3n5/9/4K4 w", true)]
+    #[case("3k5/9/9/9/9/9/9/3n5/3P5/4K4 w", false)]
+    #[case("4k4/9/9/9/4c4/9/9/4P4/9/4K4 w", true)]
+    #[case("4k4/9/9/9/4r4/9/9/4P4/9/4K4 w", false)]
+    #[case("4k4/9/9/9/9/9/9/9/9/4K4 w", true)]
+    fn test_is_in_check(#[case] fen: &'static str, #[case] expected: bool) {
+        let position = Position::from_fen(&Fen::new(fen));
+        assert_eq!(position.is_in_check(position.side_to_move()), expected);
+    }
+
+    #[test]
+    fn test_kings_facing() {
+        let position = Position::from_fen(&Fen::new("4k4/9/9/9/9/9/9/9/9/4K4 w"));
+        assert!(position.kings_facing());
+        let position = Position::from_fen(&Fen::new("4k4/9/9/9/4P4/9/9/9/9/4K4 w"));
+        assert!(!position.kings_facing());
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1")]
+    #[case("r1bakab1r/9/1cn4cn/p1p1p1p1p/9/2P6/P3P1P1P/1CN1C1N2/9/R1BAKAB1R b - - 3 5")]
+    #[case("3k5/4c4/4n4/9/4p4/9/p8/9/R3R4/4K4 w")]
+    #[case("4k4/9/9/9/4r4/9/9/4R4/9/4K4 w")]
+    fn test_mirror(#[case] fen: &str) {
+        let position = Position::from_fen(&Fen::new(fen));
+        let mirrored = position.mirror();
+        assert_eq!(mirrored.mirror(), position);
+        assert_eq!(mirrored.side_to_move(), position.side_to_move());
+        assert_eq!(mirrored.fullmove_number(), position.fullmove_number());
+        assert!(Square::all().all(|sq| mirrored.piece_at(sq.mirror()) == position.piece_at(sq)));
+
+        // 着法生成左右对称
+        let mut expected: Vec<String> = position.legal_moves().iter().map(|mv| mv.mirror().to_iccs()).collect();
+        let mut actual: Vec<String> = mirrored.legal_moves().iter().map(|mv| mv.to_iccs()).collect();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+        assert_eq!(mirrored.perft(2), position.perft(2));
+        assert_eq!(mirrored.evaluate(), position.evaluate());
+
+        // 镜像局面走镜像着法, 得到走完以后的局面的镜像
+        for mv in position.legal_moves() {
+            let mut child = position.clone();
+            child.make_move(mv);
+            let mut mirrored_child = mirrored.clone();
+            mirrored_child.make_move(mv.mirror());
+            assert_eq!(mirrored_child, child.mirror());
+            assert_eq!(mirrored_child.is_in_check(mirrored_child.side_to_move()), child.is_in_check(child.side_to_move()));
+        }
+    }
+
+    #[rstest]
+    // 车被对方车牵制, 只能在同一列上移动
+    #[case("4k4/9/9/9/4r4/9/9/4R4/9/4K4 w", 15, 7)]
+    // 帅不能走到与将照面的位置
+    #[case("3k5/9/9/9/9/9/9/9/9/4K4 w", 3, 2)]
+    fn test_gen_legal_moves_filter(
+        #[case] fen: &'static str,
+        #[case] pseudo: usize,
+        #[case] legal: usize,
+    ) {
+        let position = Position::from_fen(&Fen::new(fen));
+        assert_eq!(position.gen_pseudo_moves().len(), pseudo);
+        let moves = position.gen_legal_moves();
+        assert_eq!(moves.len(), legal);
+        for mv in moves {
+            let mut next = position.clone();
+            next.make_move(mv);
+            assert!(!next.is_in_check(position.side_to_move()));
+        }
+    }
+
+    #[rstest]
+    // 车将军, 帅控制中路
+    #[case("3k5/9/3R5/9/9/9/9/9/9/4K4 b", true, false)]
+    // 黑将没有被将军, 但无棋可走
+    #[case("3k5/R8/9/9/9/9/9/9/9/4K4 b", false, true)]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", false, false)]
+    fn test_check_mate(#[case] fen: &'static str, #[case] mate: bool, #[case] stale: bool) {
+        let position = Position::from_fen(&Fen::new(fen));
+        assert_eq!(position.check_mate(), mate);
+        assert_eq!(position.stale_mate(), stale);
+        let expected = if mate || stale { Some(Color::Red) } else { None };
+        assert_eq!(position.winner(), expected);
+    }
+
+    #[rstest]
+    // 车捉无根马
+    #[case("3k5/9/9/9/9/4n4/9/9/9/R3K4 w", true)]
+    // 马有卒保护, 不算捉
+    #[case("3k5/9/9/9/4p4/4n4/9/9/9/R3K4 w", false)]
+    fn test_chased_by(#[case] fen: &'static str, #[case] expected: bool) {
+        let mut position = Position::from_fen(&Fen::new(fen));
+        let mv = position
+            .gen_legal_moves()
+            .into_iter()
+            .find(|mv| mv.from().index() == 0xc3 && mv.to().index() == 0x83)
+            .unwrap();
+        position.make_move(mv);
+        assert_eq!(position.chased_by(&mv) != 0, expected);
+    }
+
+    #[rstest]
+    // 炮隔着兵攻击e4, 但打不到e3上的兵
+    #[case("4k4/9/9/9/4p4/9/4P4/4C4/9/4K4 w", "e4", Color::Red, &["e2", "e3"])]
+    #[case("4k4/9/9/9/4p4/9/4P4/4C4/9/4K4 w", "e5", Color::Red, &["e2"])]
+    // 马腿被兵蹩住
+    #[case("4k4/9/9/9/9/9/4P4/4N4/9/4K4 w", "d4", Color::Red, &[])]
+    #[case("4k4/9/9/9/9/9/9/4N4/9/4K4 w", "d4", Color::Red, &["e2"])]
+    // 帅仕相只在本方的九宫或半边
+    #[case("4k4/9/9/9/9/9/9/9/4A4/2B1K4 w", "d0", Color::Red, &["e0", "e1"])]
+    #[case("4k4/9/9/9/9/9/9/9/4A4/2B1K4 w", "e2", Color::Red, &["c0"])]
+    // 过河卒可以横着吃, 没过河的兵不行
+    #[case("4k4/9/9/9/9/9/3pP4/9/9/4K4 w", "e3", Color::Black, &["d3"])]
+    #[case("4k4/9/9/9/9/9/3pP4/9/9/4K4 w", "d3", Color::Red, &[])]
+    // 车炮所在的格子, 横线上没有棋子时不算攻击自己
+    #[case("4k4/9/9/9/9/9/9/9/4R4/4K4 w", "e1", Color::Red, &["e0"])]
+    #[case("4k4/9/9/9/9/9/9/9/4C4/3K5 w", "e1", Color::Red, &[])]
+    fn test_attackers(#[case] fen: &str, #[case] sq: &str, #[case] color: Color, #[case] expected: &[&str]) {
+        let position = Position::from_fen(&Fen::new(fen));
+        let sq: Square = sq.parse().unwrap();
+        let attackers: Vec<String> = position.attackers(sq, color).iter().map(Square::to_string).collect();
+        assert_eq!(attackers, expected);
+        assert_eq!(position.is_attacked(sq, color), !expected.is_empty());
+    }
+
+    #[rstest]
+    // 吃无根卒
+    #[case("3k5/9/9/9/4p4/9/9/9/4R4/4K4 w", "e1e5", 10)]
+    // 车吃卒后被隔着马的炮打掉
+    #[case("3k5/4c4/4n4/9/4p4/9/9/9/4R4/4K4 w", "e1e5", -80)]
+    // 炮紧挨着卒, 没有炮架吃不回来
+    #[case("5k3/9/9/4c4/4p4/9/9/9/4R4/3K5 w", "e1e5", 10)]
+    // 车吃有卒保护的马
+    #[case("3k5/9/9/4p4/4n4/9/9/9/4R4/4K4 w", "e1e5", -50)]
+    // 帅吃车会被后面的车吃掉, 所以不吃
+    #[case("3k5/9/9/4r4/9/9/9/4r4/4A4/4K4 b", "e2e1", 20)]
+    #[case("3k5/9/9/9/9/9/9/4r4/4A4/4K4 b", "e2e1", -70)]
+    // 不吃子的着法: 车走到卒能吃到的格子
+    #[case("3k5/9/9/3p5/9/9/9/9/3R5/4K4 w", "d1d5", -90)]
+    fn test_see(#[case] fen: &str, #[case] iccs: &str, #[case] expected: i32) {
+        let position = Position::from_fen(&Fen::new(fen));
+        let mv = Move::from_iccs(&position, iccs).unwrap();
+        assert_eq!(position.see(&mv), expected);
+    }
+
+    #[rstest]
+    #[case("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w", [44, 1920, 79666])]
+    #[case("r1bakab1r/9/1cn4cn/p1p1p1p1p/9/9/P1P1P1P1P/1CN4CN/9/R1BAKAB1R w", [36, 1301, 48202])]
+    // 炮架: 炮隔子吃子, 中路炮和车互为炮架
+    #[case("3ak4/4a4/4c4/9/2r1C1c2/9/2P1R1P2/9/4A4/4KA3 w", [15, 347, 6175])]
+    #[case("3k5/9/9/4c4/9/9/4C4/9/9/3AKA3 w", [10, 173, 2920])]
+    // 蹩马腿: 马被己方和对方的棋子绊住
+    #[case("4k4/9/3n5/2P1N4/4p4/3N5/2n6/9/4A4/3AK4 w", [19, 326, 6116])]
+    #[case("3k5/4a4/2n1b4/2p1N4/3N5/9/5n3/9/9/4KA3 w", [16, 298, 3827])]
+    // 将帅对脸: 中间唯一的棋子不能离开中路, 帅不能走到将所在的纵线
+    #[case("4k4/9/9/9/9/9/9/9/4R4/4K4 b", [2, 34, 30])]
+    #[case("4k4/4a4/9/9/9/9/9/9/4A4/4K4 w", [6, 18, 74])]
+    #[case("5k3/9/9/9/9/9/9/9/9/3K5 w", [2, 3, 7])]
+    fn test_perft(#[case] fen: &'static str, #[case] expected: [u64; 3]) {
+        let position = Position::from_fen(&fen.parse().unwrap());
+        for (depth, nodes) in expected.into_iter().enumerate() {
+            assert_eq!(position.perft(depth as u32 + 1), nodes, "depth {}", depth + 1);
+        }
+    }
+
+    #[test]
+    fn test_divide() {
+        let position = Position::from_fen(&Fen::init());
+        let divide = position.divide(2);
+        assert_eq!(divide.len(), 44);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), position.perft(2));
+        // 局面不受影响
+        assert_eq!(position.to_fen(), Fen::init());
+    }
+
     #[test]
     fn test_piece_loc() {
         let fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w - - 0 1";
@@ -759,6 +1768,32 @@ mod tests {
             println!("{} at {:x}", i.0, i.1);
         }
     }
+    #[test]
+    fn test_board_queries() {
+        let position = Position::from_fen(&Fen::init());
+        assert_eq!(position.side_to_move(), Color::Red);
+        let sq = |name: &str| name.parse::<Square>().unwrap();
+        assert_eq!(position.piece_at(sq("h2")), Piece::from_char('C'));
+        assert_eq!(position.piece_at(sq("a9")), Piece::from_char('r'));
+        assert_eq!(position.piece_at(sq("e4")), None);
+        assert_eq!(position.king_square(Color::Red), Some(sq("e0")));
+        assert_eq!(position.king_square(Color::Black), Some(sq("e9")));
+        let mut cannons = position.pieces(Color::Black, PieceKind::Cannon);
+        cannons.sort();
+        assert_eq!(cannons, vec![sq("b7"), sq("h7")]);
+        assert_eq!(position.pieces(Color::Red, PieceKind::Pawn).len(), 5);
+        assert_eq!(position.material(Color::Red), position.material(Color::Black));
+
+        // 红方少一车
+        let position = Position::from_fen(&"rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABN1 b".parse().unwrap());
+        assert_eq!(position.side_to_move(), Color::Black);
+        assert_eq!(
+            position.material(Color::Black) - position.material(Color::Red),
+            PieceKind::Rook.value()
+        );
+        assert_eq!(Position::new().king_square(Color::Red), None);
+    }
+
     #[test]
     fn test_as() {
         let x = -1;
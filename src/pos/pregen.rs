@@ -1,5 +1,5 @@
 use std::mem::size_of;
-use rand::random;
+use std::sync::LazyLock;
 use serde::{Deserialize, Serialize, Serializer};
 use serde::ser::SerializeStruct;
 use toto::{Toi32, Tou8};
@@ -53,15 +53,61 @@ const CN_BISHOP_MOVE_TAB: [i8; 4] = [-0x22, -0x1e, 0x1e, 0x22];
 const CN_KNIGHT_MOVE_TAB: [i8; 8] = [-0x21, -0x1f, -0x12, -0x0e, 0x0e, 0x12, 0x1f, 0x21];
 
 
+// RC4密码流生成器, 和ElephantEye一样用空密钥初始化, 保证每次生成的Zobrist键值都相同
+struct Rc4 {
+    s: [u8; 256],
+    x: u8,
+    y: u8,
+}
+
+impl Rc4 {
+    // 用空密钥初始化密码流生成器
+    fn init_zero() -> Self {
+        let mut s = [0u8; 256];
+        for (i, uc) in s.iter_mut().enumerate() {
+            *uc = i as u8;
+        }
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            j = j.wrapping_add(s[i]);
+            s.swap(i, j as usize);
+        }
+        Rc4 { s, x: 0, y: 0 }
+    }
+
+    // 生成密码流的下一个字节
+    fn next_byte(&mut self) -> u8 {
+        self.x = self.x.wrapping_add(1);
+        self.y = self.y.wrapping_add(self.s[self.x as usize]);
+        self.s.swap(self.x as usize, self.y as usize);
+        self.s[self.s[self.x as usize].wrapping_add(self.s[self.y as usize]) as usize]
+    }
+
+    // 生成密码流的下四个字节
+    fn next_long(&mut self) -> u32 {
+        u32::from_le_bytes([self.next_byte(), self.next_byte(), self.next_byte(), self.next_byte()])
+    }
+}
+
 impl Zobrist {
-    pub(crate) fn init_rc4() -> Self {
+    fn init_rc4(rc4: &mut Rc4) -> Self {
         Zobrist {
-            dw_key: random(),
-            dw_lock0: random(),
-            dw_lock1: random(),
+            dw_key: rc4.next_long(),
+            dw_lock0: rc4.next_long(),
+            dw_lock1: rc4.next_long(),
         }
     }
 
+    /// 用于置换表寻址的键值
+    pub(crate) fn key(&self) -> u32 {
+        self.dw_key
+    }
+
+    /// 用于校验的两个锁值
+    pub(crate) fn lock(&self) -> u64 {
+        ((self.dw_lock1 as u64) << 32) | self.dw_lock0 as u64
+    }
+
     pub(crate) fn xor(&mut self, zobr: &Zobrist) {
         self.dw_key ^= zobr.dw_key;
         self.dw_lock0 ^= zobr.dw_lock0;
@@ -78,7 +124,7 @@ impl Zobrist {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct PreGen {
 
-    zobr_player: Zobrist,
+    pub(crate) zobr_player: Zobrist,
 
     pub(crate) zobr_table: [[Zobrist; 14]; 256],
     //以“位”的形式记录棋盘上某一行所有的格子的状态(仅仅指是否有子)，就称为“位行”(BitRank)，与之对应的是“位列”(BitFile)，棋盘结构应该包含10个位行和9个位列
@@ -131,14 +177,23 @@ pub(crate) struct PreGen {
 //     }
 // }
 
+// 预置表只和棋盘有关, 整个进程共用一份, 第一次使用时生成
+static PRE_GEN: LazyLock<PreGen> = LazyLock::new(PreGen::new);
+
 impl PreGen {
+    /// 进程共用的预置表
+    pub(crate) fn shared() -> &'static PreGen {
+        &PRE_GEN
+    }
+
     pub(crate) fn new() -> Self {
-        // 首先初始化Zobrist键值表
+        // 首先初始化Zobrist键值表, 生成顺序和ElephantEye一致
+        let mut rc4 = Rc4::init_zero();
+        let zobr_player = Zobrist::init_rc4(&mut rc4);
         let mut zobr_table: [[Zobrist; 14]; 256] = [[Zobrist::default(); 14]; 256];
-
-        for i in 0..256 {
-            for j in 0..14 {
-                zobr_table[i][j] = Zobrist::init_rc4();
+        for j in 0..14 {
+            for i in 0..256 {
+                zobr_table[i][j] = Zobrist::init_rc4(&mut rc4);
             }
         }
 
@@ -391,16 +446,16 @@ impl PreGen {
                 for i in 0..2 {// 0:兵(上方),1:卒两种
                     let mut n = 0;
                     let mut sq_dst = square_forward(sq_src, i);
-                    sq_dst = sq_src + if i == 0 { -16 } else { 16 };
                     if in_board(sq_dst) {
                         ucsq_pawn_moves[i as usize][sq_src as usize][n] = sq_dst.to_u8();
+                        n += 1;
                     }
-                    if away_half(sq_src, i) { //过了河
-                        for j in -1..=1 {
+                    if away_half(sq_src, i) { //过了河, 可以左右平移
+                        for j in [-1, 1] {
                             sq_dst = sq_src + j;
-                            n = (j + 1) as usize;
                             if in_board(sq_dst) {
                                 ucsq_pawn_moves[i as usize][sq_src as usize][n] = sq_dst.to_u8();
+                                n += 1;
                             }
                         }
                     }
@@ -410,7 +465,7 @@ impl PreGen {
 
 
         let pregen = PreGen {
-            zobr_player: Zobrist::init_rc4(),
+            zobr_player,
             zobr_table,
             w_bit_rank_mask,
             w_bit_file_mask,
@@ -0,0 +1,487 @@
+use std::time::{Duration, Instant};
+
+use crate::pos::movelist::{MoveList, MAX_MOVES};
+use crate::pos::moves::{Move, NUM_ACTION_INDICES};
+use crate::pos::position::Position;
+use crate::pos::tt::{Bound, SearchEntry, TranspositionTable};
+
+/// 搜索的最大步数(半回合), 包括将军延伸和静态搜索
+pub const MAX_PLY: usize = 64;
+/// 将死的分数, 实际的分数要减去走到将死的步数, 所以步数越少分数越高
+pub const MATE_SCORE: i32 = 30000;
+/// 绝对值超过这个分数的都是将死分数
+pub const MATE_BOUND: i32 = MATE_SCORE - MAX_PLY as i32;
+const INFINITY: i32 = MATE_SCORE + 1;
+/// `search`自己建的置换表的大小(MB)
+pub const DEFAULT_TT_MB: usize = 16;
+
+/// 搜索的限制, 哪个先到就停在上一层完成的结果; 第一层总会搜完
+#[derive(Debug, Clone, Copy)]
+pub struct SearchLimits {
+    /// 迭代加深的最大深度
+    pub depth: u32,
+    /// 最多搜索的节点数, 包括静态搜索的节点
+    pub nodes: Option<u64>,
+    /// 最长的搜索时间
+    pub time: Option<Duration>,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self { depth: 6, nodes: None, time: None }
+    }
+}
+
+/// 搜索结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// 最好的着法, 走棋方没有合法着法时是None
+    pub best_move: Option<Move>,
+    /// 走棋方角度的分数, 以兵为100; 绝对值超过`MATE_BOUND`的是将死分数
+    pub score: i32,
+    /// 主要变例, 第一步就是best_move
+    pub pv: Vec<Move>,
+    /// 搜索的节点数
+    pub nodes: u64,
+    /// 完成的迭代深度
+    pub depth: u32,
+}
+
+impl SearchResult {
+    /// 走棋方几步(半回合)后能将死对方, 负数表示几步后被将死
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() > MATE_BOUND {
+            Some(self.score.signum() * (MATE_SCORE - self.score.abs()))
+        } else {
+            None
+        }
+    }
+}
+
+/// 用Alpha-Beta搜索局面: 迭代加深、主要变例搜索(PVS)、置换表、吃子的静态搜索、杀手着法和历史表排序、将军延伸.
+/// 中国象棋中困毙也算输; 搜索路径上的重复局面按和棋计, 不区分长将长捉
+pub fn search(position: &Position, limits: SearchLimits) -> SearchResult {
+    search_with_table(position, limits, &TranspositionTable::new(DEFAULT_TT_MB))
+}
+
+/// 同`search`, 但使用调用方的置换表. 对局中每步都用同一张表, 可以复用上一步搜索的结果
+pub fn search_with_table(position: &Position, limits: SearchLimits, tt: &TranspositionTable) -> SearchResult {
+    tt.new_generation();
+    let mut searcher = Searcher::new(position, limits, tt);
+    let mut result = SearchResult { best_move: None, score: -MATE_SCORE, pv: vec![], nodes: 0, depth: 0 };
+    for depth in 1..=limits.depth.clamp(1, MAX_PLY as u32 / 2) {
+        searcher.root_depth = depth;
+        let score = searcher.alpha_beta(depth as i32, 0, -INFINITY, INFINITY);
+        if searcher.stopped {
+            break;
+        }
+        let pv = searcher.pv[0][..searcher.pv_len[0]].to_vec();
+        result = SearchResult { best_move: pv.first().copied(), score, pv, nodes: searcher.nodes, depth };
+        // 没有着法, 或者已经找到了这个深度内的杀棋
+        if result.best_move.is_none() || MATE_SCORE - score.abs() <= depth as i32 {
+            break;
+        }
+        searcher.prev_pv = result.pv.clone();
+    }
+    result.nodes = searcher.nodes;
+    result
+}
+
+struct Searcher<'a> {
+    position: Position,
+    tt: &'a TranspositionTable,
+    limits: SearchLimits,
+    start: Instant,
+    root_depth: u32,
+    nodes: u64,
+    stopped: bool,
+    // 每一步产生过截断的两个不吃子着法
+    killers: [[Move; 2]; MAX_PLY],
+    // 不吃子着法产生截断的次数, 按深度的平方累加, 用动作序号索引
+    history: Vec<i32>,
+    // 三角形的主要变例表: pv[ply][ply..pv_len[ply]]是从这一步开始的主要变例
+    pv: Box<[[Move; MAX_PLY]; MAX_PLY]>,
+    pv_len: [usize; MAX_PLY],
+    // 上一层迭代的主要变例, 优先搜索
+    prev_pv: Vec<Move>,
+    // 搜索路径上的局面, 用于判断重复
+    keys: Vec<u64>,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(position: &Position, limits: SearchLimits, tt: &'a TranspositionTable) -> Searcher<'a> {
+        Searcher {
+            position: position.clone(),
+            tt,
+            limits,
+            start: Instant::now(),
+            root_depth: 0,
+            nodes: 0,
+            stopped: false,
+            killers: [[Move::default(); 2]; MAX_PLY],
+            history: vec![0; NUM_ACTION_INDICES],
+            pv: Box::new([[Move::default(); MAX_PLY]; MAX_PLY]),
+            pv_len: [0; MAX_PLY],
+            prev_pv: vec![],
+            keys: vec![position.lock()],
+        }
+    }
+
+    /// 每隔一段节点检查是否超出限制, 第一层不检查
+    fn check_limits(&mut self) {
+        if self.root_depth <= 1 || self.nodes & 1023 != 0 {
+            return;
+        }
+        let out_of_nodes = self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes);
+        let out_of_time = self.limits.time.is_some_and(|time| self.start.elapsed() >= time);
+        self.stopped = out_of_nodes || out_of_time;
+    }
+
+    /// 走子并记下局面
+    fn make(&mut self, mv: Move) {
+        self.position.make_move(mv);
+        self.keys.push(self.position.lock());
+    }
+
+    fn undo(&mut self) {
+        self.position.undo_move();
+        self.keys.pop();
+    }
+
+    /// 当前局面是否在搜索路径上出现过
+    fn is_repetition(&self) -> bool {
+        let (current, earlier) = self.keys.split_last().unwrap();
+        earlier.contains(current)
+    }
+
+    /// 把子节点的主要变例接到着法后面
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        self.pv[ply][ply] = mv;
+        let child_len = self.pv_len[ply + 1].max(ply + 1);
+        for i in ply + 1..child_len {
+            self.pv[ply][i] = self.pv[ply + 1][i];
+        }
+        self.pv_len[ply] = child_len;
+    }
+
+    fn alpha_beta(&mut self, mut depth: i32, ply: usize, mut alpha: i32, mut beta: i32) -> i32 {
+        let original_alpha = alpha;
+        self.pv_len[ply] = ply;
+        if ply > 0 {
+            if self.is_repetition() {
+                return 0;
+            }
+            // 杀棋步数裁剪: 再好也不会比在这一步将死对方更好
+            alpha = alpha.max(-MATE_SCORE + ply as i32);
+            beta = beta.min(MATE_SCORE - ply as i32 - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.position.evaluate();
+        }
+
+        let in_check = self.position.is_in_check(self.position.side_to_move());
+        if in_check {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiesce(ply, alpha, beta);
+        }
+        self.nodes += 1;
+        self.check_limits();
+
+        // 零窗口的节点直接用足够深的置换表结果, 主要变例上的节点只用它的着法排序
+        let entry = self.tt.probe(&self.position);
+        if let Some(entry) = entry {
+            let score = score_from_tt(entry.data.score, ply);
+            let usable = match entry.data.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if ply > 0 && beta - alpha == 1 && entry.depth as i32 >= depth && usable {
+                return score;
+            }
+        }
+
+        let mut moves = self.position.legal_moves();
+        if moves.is_empty() {
+            return -MATE_SCORE + ply as i32;
+        }
+        let pv_move = self.prev_pv.get(ply).copied();
+        let tt_move = entry.and_then(|entry| entry.data.best_move);
+        self.order_moves(&mut moves, ply, pv_move, tt_move);
+
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for (i, &mv) in moves.iter().enumerate() {
+            self.make(mv);
+            let score = if i == 0 {
+                -self.alpha_beta(depth - 1, ply + 1, -beta, -alpha)
+            } else {
+                // 先用零窗口证明这步不比最好的着法好, 失败了再用完整窗口重搜
+                let score = -self.alpha_beta(depth - 1, ply + 1, -alpha - 1, -alpha);
+                if score > alpha && score < beta {
+                    -self.alpha_beta(depth - 1, ply + 1, -beta, -alpha)
+                } else {
+                    score
+                }
+            };
+            self.undo();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(mv);
+                    self.update_pv(ply, mv);
+                    if alpha >= beta {
+                        if mv.captured().is_none() {
+                            self.record_cutoff(mv, ply, depth);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        let data = SearchEntry { best_move, score: score_to_tt(best, ply), bound };
+        self.tt.store(&self.position, depth.min(u8::MAX as i32) as u8, data);
+        best
+    }
+
+    /// 静态搜索: 只搜不丢子的吃子着法, 被将军时搜所有应将着法
+    fn quiesce(&mut self, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv_len[ply] = ply;
+        self.nodes += 1;
+        self.check_limits();
+        if ply >= MAX_PLY - 1 {
+            return self.position.evaluate();
+        }
+
+        let side = self.position.side_to_move();
+        let mut moves = MoveList::new();
+        let mut scores = [0; MAX_MOVES];
+        let mut best;
+        if self.position.is_in_check(side) {
+            self.position.gen_evasions(&mut moves);
+            if moves.is_empty() {
+                return -MATE_SCORE + ply as i32;
+            }
+            best = -INFINITY;
+            for (i, mv) in moves.iter().enumerate() {
+                scores[i] = self.position.see(mv);
+            }
+        } else {
+            // 站着不动的分数, 不吃子也至少有这么多
+            best = self.position.evaluate();
+            if best >= beta {
+                return best;
+            }
+            alpha = alpha.max(best);
+            self.position.gen_captures(&mut moves);
+            let mut kept = 0;
+            for i in 0..moves.len() {
+                let see = self.position.see(&moves[i]);
+                if see >= 0 {
+                    moves[kept] = moves[i];
+                    scores[kept] = see;
+                    kept += 1;
+                }
+            }
+            moves.truncate(kept);
+        }
+
+        for i in 0..moves.len() {
+            let mv = pick_next(&mut moves, &mut scores, i);
+            self.make(mv);
+            // 吃子着法是伪合法的, 走完后自己被将军就跳过
+            if self.position.is_in_check(side) {
+                self.undo();
+                continue;
+            }
+            let score = -self.quiesce(ply + 1, -beta, -alpha);
+            self.undo();
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// 着法排序: 置换表的着法, 上一层的主要变例, 不丢子的吃子(按SEE), 杀手着法, 历史表, 最后是丢子的吃子
+    fn order_moves(&self, moves: &mut MoveList, ply: usize, pv_move: Option<Move>, tt_move: Option<Move>) {
+        let mut scores = [0; MAX_MOVES];
+        for (i, mv) in moves.iter().enumerate() {
+            scores[i] = if Some(*mv) == tt_move {
+                (1 << 30) + 1
+            } else if Some(*mv) == pv_move {
+                1 << 30
+            } else if mv.captured().is_some() {
+                let see = self.position.see(mv);
+                if see >= 0 { (1 << 28) + see } else { -(1 << 28) + see }
+            } else if *mv == self.killers[ply][0] {
+                (1 << 27) + 1
+            } else if *mv == self.killers[ply][1] {
+                1 << 27
+            } else {
+                self.history[usize::from(*mv)]
+            };
+        }
+        for i in 0..moves.len() {
+            pick_next(moves, &mut scores, i);
+        }
+    }
+
+    /// 不吃子着法产生截断, 记为杀手着法并加历史分
+    fn record_cutoff(&mut self, mv: Move, ply: usize, depth: i32) {
+        if self.killers[ply][0] != mv {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = mv;
+        }
+        let history = &mut self.history[usize::from(mv)];
+        *history = (*history + depth * depth).min(1 << 26);
+    }
+}
+
+/// 置换表里的将死分数按到存入局面的距离保存, 这样从不同的路径走到这个局面都能用
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score + ply as i32
+    } else if score < -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score - ply as i32
+    } else if score < -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// 把moves[i..]中分数最高的着法换到第i个位置并返回它
+fn pick_next(moves: &mut MoveList, scores: &mut [i32; MAX_MOVES], i: usize) -> Move {
+    let mut best = i;
+    for j in i + 1..moves.len() {
+        if scores[j] > scores[best] {
+            best = j;
+        }
+    }
+    moves.swap(i, best);
+    scores.swap(i, best);
+    moves[i]
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{search, search_with_table, SearchLimits, MATE_BOUND, MATE_SCORE};
+    use crate::fen::Fen;
+    use crate::position::Position;
+    use crate::tt::TranspositionTable;
+
+    fn limits(depth: u32) -> SearchLimits {
+        SearchLimits { depth, ..SearchLimits::default() }
+    }
+
+    #[rstest]
+    // 一车封住下二路, 另一车沉底或者照将, 帅控制中路
+    #[case("3k5/R8/1R7/9/9/9/9/9/9/4K4 w", 1)]
+    #[case("3k5/7R1/9/9/9/9/9/9/R8/5K3 w", 1)]
+    // 单车配合帅, 两步才能杀
+    #[case("4k4/9/9/9/9/9/9/9/9/R2K5 w", 3)]
+    fn test_mate(#[case] fen: &str, #[case] mate_in: i32) {
+        let mut position = Position::from_fen(&Fen::new(fen));
+        let result = search(&position, limits(4));
+        assert_eq!(result.score, MATE_SCORE - mate_in);
+        assert_eq!(result.mate_in(), Some(mate_in));
+        assert_eq!(result.pv.len(), mate_in as usize);
+        for mv in &result.pv {
+            position.make_move(*mv);
+        }
+        // 将死或者困毙
+        assert!(position.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn test_mated() {
+        // 走棋方被将死, 困毙也一样
+        for fen in ["1R1k5/R8/9/9/9/9/9/9/9/4K4 b", "3k5/9/9/9/4r4/9/9/9/6p2/5K3 w"] {
+            let position = Position::from_fen(&Fen::new(fen));
+            let result = search(&position, limits(3));
+            assert_eq!(result.best_move, None);
+            assert_eq!(result.score, -MATE_SCORE);
+            assert_eq!(result.mate_in(), Some(0));
+        }
+    }
+
+    #[test]
+    fn test_wins_material() {
+        // 吃掉无根的车, 而不是吃有保护的卒
+        let position = Position::from_fen(&Fen::new("3k5/9/9/4p4/2r1p4/9/9/9/2R1R4/4K4 w"));
+        let result = search(&position, limits(3));
+        assert_eq!(result.best_move.map(|mv| mv.to_iccs()), Some("c1c5".to_string()));
+        assert!(result.score > 800 && result.score < MATE_BOUND);
+    }
+
+    #[test]
+    fn test_pv_and_limits() {
+        let position = Position::from_fen(&Fen::init());
+        let result = search(&position, limits(4));
+        assert_eq!(result.depth, 4);
+        assert_eq!(result.pv.first().copied(), result.best_move);
+        // 主要变例是一串合法着法
+        let mut line = position.clone();
+        for mv in &result.pv {
+            assert!(line.legal_moves().contains(mv));
+            line.make_move(*mv);
+        }
+
+        // 节点数用完就停在上一层的结果
+        let result = search(&position, SearchLimits { depth: 20, nodes: Some(5000), time: None });
+        assert!(result.depth >= 1 && result.depth < 20);
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 5000 + 1024);
+    }
+
+    #[test]
+    fn test_reuse_table() {
+        // 第二次搜索同一个局面, 置换表里已经有上一次的结果
+        let position = Position::from_fen(&Fen::new("r1bakab1r/9/1cn4cn/p1p1p1p1p/9/2P6/P3P1P1P/1CN1C1N2/9/R1BAKAB1R w"));
+        let tt = TranspositionTable::new(4);
+        let first = search_with_table(&position, limits(4), &tt);
+        let second = search_with_table(&position, limits(4), &tt);
+        assert!(second.nodes < first.nodes);
+        assert_eq!(first.score, search(&position, limits(4)).score);
+        assert!(tt.hashfull() > 0);
+    }
+}
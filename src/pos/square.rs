@@ -0,0 +1,144 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::pos::moves::NotationError;
+use crate::pos::{coord_xy, file_x, in_board, rank_y, FILE_LEFT, FILE_RIGHT, RANK_BOTTOM, RANK_TOP};
+
+/// 棋盘上的格子, 内部用16x16棋盘上的序号表示, 与`Position`一致(如0x33是左上角, 0xcb是右下角)
+///
+/// 对外用ICCS坐标: 纵线`file`为0~8(a~i, 从红方左边数起), 横线`rank`为0~9(从红方底线数起),
+/// 如红方右边的炮在"h2"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    /// 由16x16棋盘上的序号构造, 不在9x10的棋盘内时返回None
+    pub fn new(sq: usize) -> Option<Square> {
+        if sq < 256 && in_board(sq as i32) {
+            Some(Square(sq as u8))
+        } else {
+            None
+        }
+    }
+
+    /// 调用方保证格子在棋盘内, 如着法生成器产生的格子
+    pub(crate) fn new_unchecked(sq: usize) -> Square {
+        debug_assert!(in_board(sq as i32), "square {:#x} is off the board", sq);
+        Square(sq as u8)
+    }
+
+    /// 由ICCS纵线(0~8)和横线(0~9)构造, 超出范围时返回None
+    pub fn from_coords(file: usize, rank: usize) -> Option<Square> {
+        if file < 9 && rank < 10 {
+            Some(Square(coord_xy(FILE_LEFT + file, RANK_BOTTOM - rank) as u8))
+        } else {
+            None
+        }
+    }
+
+    /// 棋盘上的全部90个格子, 从黑方底线(左上角)开始逐行往下, 与FEN的顺序一致
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..90).map(|i| Square(coord_xy(FILE_LEFT + i % 9, RANK_TOP + i / 9) as u8))
+    }
+
+    /// 16x16棋盘上的序号
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// ICCS纵线, 0~8对应a~i
+    pub fn file(self) -> usize {
+        file_x(self.index()) - FILE_LEFT
+    }
+
+    /// ICCS横线, 0~9, 红方底线是0
+    pub fn rank(self) -> usize {
+        RANK_BOTTOM - rank_y(self.index())
+    }
+
+    /// 沿中间的纵线左右镜像的格子, 如"h2"变成"b2"
+    pub fn mirror(self) -> Square {
+        Square(coord_xy(FILE_LEFT + FILE_RIGHT - file_x(self.index()), rank_y(self.index())) as u8)
+    }
+}
+
+/// ICCS坐标, 如"h2"
+impl Display for Square {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", (b'a' + self.file() as u8) as char, self.rank())
+    }
+}
+
+/// 解析ICCS坐标, 不区分大小写, 如"h2"、"H2"
+impl FromStr for Square {
+    type Err = NotationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let (Some(file), Some(rank), None) = (chars.next(), chars.next(), chars.next()) else {
+            return Err(NotationError::BadFormat(s.to_string()));
+        };
+        let col = match file.to_ascii_lowercase() {
+            c @ 'a'..='i' => c as usize - 'a' as usize,
+            _ => return Err(NotationError::BadFile(file)),
+        };
+        let row = rank.to_digit(10).ok_or(NotationError::BadRank(rank))? as usize;
+        Ok(Square::from_coords(col, row).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::Square;
+    use crate::moves::NotationError;
+
+    #[test]
+    fn test_new() {
+        assert_eq!(Square::new(0x33).map(Square::index), Some(0x33));
+        assert_eq!(Square::new(0xcb).map(Square::index), Some(0xcb));
+        assert_eq!(Square::new(0x32), None);
+        assert_eq!(Square::new(0xd3), None);
+        assert_eq!(Square::new(256), None);
+    }
+
+    #[rstest]
+    #[case("a9", 0x33, 0, 9)]
+    #[case("i0", 0xcb, 8, 0)]
+    #[case("h2", 0xaa, 7, 2)]
+    #[case("e0", 0xc7, 4, 0)]
+    fn test_iccs_name(#[case] name: &str, #[case] index: usize, #[case] file: usize, #[case] rank: usize) {
+        let sq: Square = name.parse().unwrap();
+        assert_eq!((sq.index(), sq.file(), sq.rank()), (index, file, rank));
+        assert_eq!(Square::from_coords(file, rank), Some(sq));
+        assert_eq!(sq.to_string(), name);
+        assert_eq!(name.to_uppercase().parse::<Square>(), Ok(sq));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("j2".parse::<Square>(), Err(NotationError::BadFile('j')));
+        assert_eq!("hx".parse::<Square>(), Err(NotationError::BadRank('x')));
+        assert_eq!("h10".parse::<Square>(), Err(NotationError::BadFormat("h10".to_string())));
+        assert_eq!(Square::from_coords(9, 0), None);
+    }
+
+    #[test]
+    fn test_all() {
+        let all: Vec<Square> = Square::all().collect();
+        assert_eq!(all.len(), 90);
+        assert_eq!((all[0].to_string(), all[89].to_string()), ("a9".to_string(), "i0".to_string()));
+        assert!(all.iter().all(|sq| Square::new(sq.index()) == Some(*sq)));
+    }
+
+    #[rstest]
+    #[case("h2", "b2")]
+    #[case("a9", "i9")]
+    #[case("e0", "e0")]
+    fn test_mirror(#[case] name: &str, #[case] mirrored: &str) {
+        let sq: Square = name.parse().unwrap();
+        assert_eq!(sq.mirror().to_string(), mirrored);
+        assert_eq!(sq.mirror().mirror(), sq);
+    }
+}
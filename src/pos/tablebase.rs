@@ -0,0 +1,783 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::pos::moves::Move;
+use crate::pos::piece::{Color, Piece, PieceKind};
+use crate::pos::position::{can_stand_on, Position};
+use crate::pos::square::Square;
+
+// 文件头: 标识和格式版本
+const MAGIC: &[u8; 4] = b"CCTB";
+const VERSION: u32 = 1;
+// 残局库文件的扩展名
+const EXTENSION: &str = "cctb";
+
+// 每个局面一个字节: 0是和棋, 1~127是胜(到将死的步数为2v-1), 128~253是负(步数为2(v-128)), 255是不可能的局面.
+// 超出范围的步数按最大值保存, 胜负不受影响
+const DRAW: u8 = 0;
+const LOSS_BASE: u8 = 128;
+const INVALID: u8 = 255;
+// 生成过程中还没有结果的局面
+const UNKNOWN: u8 = 254;
+// 格子不在棋子的活动范围内
+const NO_SLOT: u8 = u8::MAX;
+
+static SHARED: OnceLock<Tablebase> = OnceLock::new();
+
+/// 胜负和, 从走棋方来看
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    Draw,
+    Win,
+}
+
+/// 查库的结果: 胜负和, 以及双方都走最好的着法时到将死(困毙)的步数(半回合), 和棋时为0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub wdl: Wdl,
+    pub dtm: u32,
+}
+
+/// 残局的子力组合, 双方都有帅(将), 只列出其他棋子, 如"KRKAABB"是单车对士象全
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Material {
+    red: Vec<PieceKind>,
+    black: Vec<PieceKind>,
+}
+
+impl Material {
+    /// 局面的子力组合, 缺帅(将)时返回None
+    pub fn of(position: &Position) -> Option<Material> {
+        let side = |color: Color| -> Option<Vec<PieceKind>> {
+            position.king_square(color)?;
+            Some(PieceKind::ALL[1..].iter().flat_map(|&kind| vec![kind; position.pieces(color, kind).len()]).collect())
+        };
+        Some(Material { red: side(Color::Red)?, black: side(Color::Black)? })
+    }
+
+    /// 交换红黑双方
+    pub fn swapped(&self) -> Material {
+        Material { red: self.black.clone(), black: self.red.clone() }
+    }
+
+    /// 包括双方帅(将)在内的棋子数
+    pub fn piece_count(&self) -> usize {
+        self.red.len() + self.black.len() + 2
+    }
+
+    fn side(&self, color: Color) -> &[PieceKind] {
+        match color {
+            Color::Red => &self.red,
+            Color::Black => &self.black,
+        }
+    }
+
+    /// 吃掉一个子以后的子力组合, 生成时要先有这些库
+    fn captures(&self) -> Vec<Material> {
+        let mut res: Vec<Material> = vec![];
+        for color in [Color::Red, Color::Black] {
+            for i in 0..self.side(color).len() {
+                let mut material = self.clone();
+                match color {
+                    Color::Red => material.red.remove(i),
+                    Color::Black => material.black.remove(i),
+                };
+                if !res.contains(&material) {
+                    res.push(material);
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Display for Material {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let side = |kinds: &[PieceKind]| kinds.iter().map(|kind| kind.to_char()).collect::<String>();
+        write!(f, "K{}K{}", side(&self.red), side(&self.black))
+    }
+}
+
+impl FromStr for Material {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+        let (red, black) = upper
+            .strip_prefix('K')
+            .and_then(|rest| rest.split_once('K'))
+            .ok_or_else(|| format!("子力组合'{}'应该以K开头, 并且恰好有两个K, 如KRKAABB", s))?;
+        let side = |part: &str| -> Result<Vec<PieceKind>, String> {
+            let mut kinds = part
+                .chars()
+                .map(|ch| match PieceKind::from_char(ch) {
+                    Some(kind) if kind != PieceKind::King => Ok(kind),
+                    _ => Err(format!("子力组合'{}'中有无效的棋子'{}'", s, ch)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            kinds.sort();
+            if let Some(&kind) = kinds.iter().find(|&&k| kinds.iter().filter(|&&x| x == k).count() > k.index_range().count()) {
+                return Err(format!("子力组合'{}'中{}太多", s, kind.to_char()));
+            }
+            Ok(kinds)
+        };
+        Ok(Material { red: side(red)?, black: side(black)? })
+    }
+}
+
+/// 一方的一种棋子, 同种的几个棋子不分先后, 用组合数编号
+struct Group {
+    piece: Piece,
+    count: usize,
+    // 第一个棋子在格子列表中的位置
+    offset: usize,
+    // 能站的格子, 以及格子到其序号的映射
+    cells: Vec<u8>,
+    slots: [u8; 90],
+    size: usize,
+}
+
+/// 某个子力组合的全部摆法到序号的映射. 格子用0~89表示(横线*9+纵线, 红方底线是0),
+/// 依次是红帅、黑将和其他各组棋子
+struct Layout {
+    groups: Vec<Group>,
+    pieces: usize,
+    size: usize,
+}
+
+impl Layout {
+    fn new(material: &Material) -> Layout {
+        let mut kinds = vec![(Piece::new(Color::Red, PieceKind::King), 1), (Piece::new(Color::Black, PieceKind::King), 1)];
+        for color in [Color::Red, Color::Black] {
+            for &kind in material.side(color) {
+                match kinds.last_mut() {
+                    Some((piece, count)) if *piece == Piece::new(color, kind) => *count += 1,
+                    _ => kinds.push((Piece::new(color, kind), 1)),
+                }
+            }
+        }
+
+        let mut groups = vec![];
+        let mut offset = 0;
+        for (piece, count) in kinds {
+            let cells: Vec<u8> = (0..90u8).filter(|&cell| can_stand_on(piece, to_square(cell))).collect();
+            let mut slots = [NO_SLOT; 90];
+            for (i, &cell) in cells.iter().enumerate() {
+                slots[cell as usize] = i as u8;
+            }
+            let size = binomial(cells.len(), count);
+            groups.push(Group { piece, count, offset, cells, slots, size });
+            offset += count;
+        }
+        let size = groups.iter().map(|group| group.size).product();
+        Layout { groups, pieces: offset, size }
+    }
+
+    /// 按序号摆出各个棋子的格子
+    fn decode(&self, mut index: usize, cells: &mut [u8]) {
+        for group in self.groups.iter().rev() {
+            let mut rest = index % group.size;
+            index /= group.size;
+            let mut top = group.cells.len();
+            for i in (0..group.count).rev() {
+                top -= 1;
+                while binomial(top, i + 1) > rest {
+                    top -= 1;
+                }
+                rest -= binomial(top, i + 1);
+                cells[group.offset + i] = group.cells[top];
+            }
+        }
+    }
+
+    /// 各个棋子的格子对应的序号, 同种棋子可以是任意顺序; 有棋子不在活动范围内或者重叠时返回None
+    fn encode(&self, cells: &[u8]) -> Option<usize> {
+        let mut index = 0;
+        for group in &self.groups {
+            let mut slots = [0usize; 5];
+            let slots = &mut slots[..group.count];
+            for (slot, &cell) in slots.iter_mut().zip(&cells[group.offset..group.offset + group.count]) {
+                *slot = match group.slots[cell as usize] {
+                    NO_SLOT => return None,
+                    s => s as usize,
+                };
+            }
+            slots.sort_unstable();
+            if slots.windows(2).any(|w| w[0] == w[1]) {
+                return None;
+            }
+            index = index * group.size + slots.iter().enumerate().map(|(i, &s)| binomial(s, i + 1)).sum::<usize>();
+        }
+        Some(index)
+    }
+
+    /// 格子列表对应的棋子, 不同的棋子重叠时返回None
+    fn pieces(&self, cells: &[u8]) -> Option<Vec<(Square, Piece)>> {
+        let mut occupied = [false; 90];
+        let mut res = Vec::with_capacity(self.pieces);
+        for group in &self.groups {
+            for &cell in &cells[group.offset..group.offset + group.count] {
+                if std::mem::replace(&mut occupied[cell as usize], true) {
+                    return None;
+                }
+                res.push((to_square(cell), group.piece));
+            }
+        }
+        Some(res)
+    }
+}
+
+/// 一种子力组合的库, 每个摆法有红先、黑先两个局面
+struct Table {
+    layout: Layout,
+    values: Vec<u8>,
+}
+
+impl Table {
+    /// 查局面, `flip`表示库的红黑和局面相反, 要把棋盘上下翻转并交换红黑
+    fn probe(&self, position: &Position, flip: bool) -> Option<Probe> {
+        let mut cells = vec![0; self.layout.pieces];
+        let mut filled = vec![0; self.layout.groups.len()];
+        for sq in Square::all() {
+            let Some(mut piece) = position.piece_at(sq) else {
+                continue;
+            };
+            let mut rank = sq.rank();
+            if flip {
+                piece = Piece::new(piece.color.opponent(), piece.kind);
+                rank = 9 - rank;
+            }
+            let g = self.layout.groups.iter().position(|group| group.piece == piece)?;
+            let group = &self.layout.groups[g];
+            if filled[g] == group.count {
+                return None;
+            }
+            cells[group.offset + filled[g]] = (rank * 9 + sq.file()) as u8;
+            filled[g] += 1;
+        }
+        let side = position.side_to_move() != Color::Red;
+        let index = self.layout.encode(&cells)? * 2 + (side != flip) as usize;
+        decode(self.values[index])
+    }
+}
+
+/// 残局库: 若干子力组合的胜负和与到将死的步数, 由逆向分析生成.
+///
+/// 不考虑长将、长捉等禁着规则, 也不考虑自然限着, 只判断能否在有限步内将死或困毙对方;
+/// 子力组合的红黑可以和局面相反, 查库时自动翻转
+#[derive(Default)]
+pub struct Tablebase {
+    tables: HashMap<Material, Table>,
+}
+
+impl Tablebase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设为进程共用的残局库, 供`CChess`在MCTS和自对弈中判定局面; 只能设置一次, 已经设置过时返回false
+    pub fn install(self) -> bool {
+        SHARED.set(self).is_ok()
+    }
+
+    /// 进程共用的残局库, 没有设置时返回None
+    pub fn shared() -> Option<&'static Tablebase> {
+        SHARED.get()
+    }
+
+    /// 已有的子力组合
+    pub fn materials(&self) -> impl Iterator<Item = &Material> {
+        self.tables.keys()
+    }
+
+    /// 是否有这个子力组合的库(不分红黑)
+    pub fn contains(&self, material: &Material) -> bool {
+        self.tables.contains_key(material) || self.tables.contains_key(&material.swapped())
+    }
+
+    /// 生成子力组合的库, 吃子以后的子力组合先生成, 已经有的跳过
+    pub fn generate(&mut self, material: &Material) {
+        if self.contains(material) {
+            return;
+        }
+        for sub in material.captures() {
+            self.generate(&sub);
+        }
+        let table = Generator::new(self, material).run();
+        self.tables.insert(material.clone(), table);
+    }
+
+    /// 查局面, 子力组合不在库中时返回None
+    pub fn probe(&self, position: &Position) -> Option<Probe> {
+        let material = Material::of(position)?;
+        if let Some(table) = self.tables.get(&material) {
+            return table.probe(position, false);
+        }
+        self.tables.get(&material.swapped())?.probe(position, true)
+    }
+
+    /// 按库走的最好的着法: 能赢时最快将死, 要输时拖得最久
+    pub fn best_move(&self, position: &Position) -> Option<Move> {
+        self.probe(position)?;
+        position
+            .legal_moves()
+            .into_iter()
+            .filter_map(|mv| {
+                let mut child = position.clone();
+                child.make_move(mv);
+                let probe = self.probe(&child)?;
+                let dtm = probe.dtm as i64;
+                // 从走这步棋的一方来看
+                let key = match probe.wdl {
+                    Wdl::Loss => (2, -dtm),
+                    Wdl::Draw => (1, 0),
+                    Wdl::Win => (0, dtm),
+                };
+                Some((mv, key))
+            })
+            .max_by_key(|&(_, key)| key)
+            .map(|(mv, _)| mv)
+    }
+
+    /// 每个子力组合保存成目录下的一个文件, 如"KRKAABB.cctb"
+    pub fn save_dir(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).with_context(|| format!("无法创建残局库目录: {:?}", dir))?;
+        for (material, table) in &self.tables {
+            let path = dir.join(format!("{}.{}", material, EXTENSION));
+            let file = File::create(&path).with_context(|| format!("无法创建残局库文件: {:?}", path))?;
+            let mut writer = BufWriter::new(file);
+            write_table(&mut writer, material, table)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// 读取目录下的全部残局库文件
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut tablebase = Tablebase::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("无法打开残局库目录: {:?}", dir))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                let file = File::open(&path).with_context(|| format!("无法打开残局库文件: {:?}", path))?;
+                let (material, table) = read_table(&mut BufReader::new(file))
+                    .with_context(|| format!("无法读取残局库文件: {:?}", path))?;
+                tablebase.tables.insert(material, table);
+            }
+        }
+        Ok(tablebase)
+    }
+}
+
+fn write_table<W: Write>(writer: &mut W, material: &Material, table: &Table) -> Result<()> {
+    let name = material.to_string();
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&[name.len() as u8])?;
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&(table.values.len() as u64).to_le_bytes())?;
+    writer.write_all(&table.values)?;
+    Ok(())
+}
+
+fn read_table<R: Read>(reader: &mut R) -> Result<(Material, Table)> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != *MAGIC {
+        bail!("不是残局库文件");
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        bail!("不支持的残局库版本: {}", version);
+    }
+    let mut len = [0; 1];
+    reader.read_exact(&mut len)?;
+    let mut name = vec![0; len[0] as usize];
+    reader.read_exact(&mut name)?;
+    let material: Material = String::from_utf8(name)?.parse().map_err(anyhow::Error::msg)?;
+
+    let layout = Layout::new(&material);
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    if u64::from_le_bytes(len) != layout.size as u64 * 2 {
+        bail!("{}的局面数不对", material);
+    }
+    let mut values = vec![0; layout.size * 2];
+    reader.read_exact(&mut values)?;
+    Ok((material, Table { layout, values }))
+}
+
+/// 逆向分析: 先找出被将死、困毙的局面和吃子后能查到结果的局面,
+/// 再按步数从小到大往前推: 能走到负局面的是胜局面, 所有着法都走到胜局面的是负局面, 最后剩下的是和棋
+struct Generator<'a> {
+    tablebase: &'a Tablebase,
+    layout: Layout,
+    values: Vec<u8>,
+    // 还没有结果的不吃子着法数
+    quiet_left: Vec<u16>,
+    // 有吃子着法不会输(吃子后和棋或者对方输)
+    escape: Vec<bool>,
+    // 吃子着法都输时, 最多能拖的步数
+    loss_floor: Vec<u8>,
+    // 按步数排队等待确定结果的局面
+    wins: Vec<Vec<u32>>,
+    losses: Vec<Vec<u32>>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(tablebase: &'a Tablebase, material: &Material) -> Self {
+        let layout = Layout::new(material);
+        let len = layout.size * 2;
+        Generator {
+            tablebase,
+            layout,
+            values: vec![UNKNOWN; len],
+            quiet_left: vec![0; len],
+            escape: vec![false; len],
+            loss_floor: vec![0; len],
+            wins: vec![],
+            losses: vec![],
+        }
+    }
+
+    fn run(mut self) -> Table {
+        self.init();
+        self.retrograde();
+        for value in self.values.iter_mut().filter(|value| **value == UNKNOWN) {
+            *value = DRAW;
+        }
+        Table { layout: self.layout, values: self.values }
+    }
+
+    fn init(&mut self) {
+        let mut cells = vec![0; self.layout.pieces];
+        for i in 0..self.layout.size {
+            self.layout.decode(i, &mut cells);
+            let Some(pieces) = self.layout.pieces(&cells) else {
+                self.values[i * 2] = INVALID;
+                self.values[i * 2 + 1] = INVALID;
+                continue;
+            };
+            for side in [Color::Red, Color::Black] {
+                let index = i * 2 + side as usize;
+                let position = Position::from_pieces(&pieces, side);
+                if position.kings_facing() || position.is_in_check(side.opponent()) {
+                    self.values[index] = INVALID;
+                    continue;
+                }
+                self.init_position(index, &position);
+            }
+        }
+    }
+
+    /// 数不吃子的着法, 吃子着法直接查吃子后的库
+    fn init_position(&mut self, index: usize, position: &Position) {
+        let mut quiet = 0;
+        let mut win = None;
+        for mv in position.legal_moves() {
+            if mv.captured().is_none() {
+                quiet += 1;
+                continue;
+            }
+            let mut child = position.clone();
+            child.make_move(mv);
+            match self.tablebase.probe(&child) {
+                Some(Probe { wdl: Wdl::Loss, dtm }) => win = Some(win.map_or(dtm + 1, |d: u32| d.min(dtm + 1))),
+                Some(Probe { wdl: Wdl::Win, dtm }) => {
+                    self.loss_floor[index] = self.loss_floor[index].max((dtm + 1).min(u8::MAX as u32) as u8)
+                }
+                _ => self.escape[index] = true,
+            }
+        }
+        self.quiet_left[index] = quiet;
+        if let Some(dtm) = win {
+            self.escape[index] = true;
+            push(&mut self.wins, dtm as usize, index);
+        } else if quiet == 0 && !self.escape[index] {
+            push(&mut self.losses, self.loss_floor[index] as usize, index);
+        }
+    }
+
+    fn retrograde(&mut self) {
+        let mut predecessors = vec![];
+        let mut depth = 0;
+        while depth < self.wins.len().max(self.losses.len()) {
+            for index in take(&mut self.losses, depth) {
+                if self.values[index] != UNKNOWN {
+                    continue;
+                }
+                self.values[index] = encode(Wdl::Loss, depth);
+                self.predecessors(index, &mut predecessors);
+                for &prev in &predecessors {
+                    if self.values[prev] == UNKNOWN {
+                        push(&mut self.wins, depth + 1, prev);
+                    }
+                }
+            }
+            for index in take(&mut self.wins, depth) {
+                if self.values[index] != UNKNOWN {
+                    continue;
+                }
+                self.values[index] = encode(Wdl::Win, depth);
+                self.predecessors(index, &mut predecessors);
+                for &prev in &predecessors {
+                    if self.values[prev] != UNKNOWN {
+                        continue;
+                    }
+                    self.quiet_left[prev] -= 1;
+                    if self.quiet_left[prev] == 0 && !self.escape[prev] {
+                        push(&mut self.losses, (depth + 1).max(self.loss_floor[prev] as usize), prev);
+                    }
+                }
+            }
+            depth += 1;
+        }
+    }
+
+    /// 走一步不吃子的棋能到达这个局面的所有局面
+    fn predecessors(&self, index: usize, res: &mut Vec<usize>) {
+        res.clear();
+        let mover = if index % 2 == 0 { Color::Black } else { Color::Red };
+        let mut cells = vec![0; self.layout.pieces];
+        self.layout.decode(index / 2, &mut cells);
+        let mut occupied = [false; 90];
+        for &cell in &cells {
+            occupied[cell as usize] = true;
+        }
+
+        let mut froms = vec![];
+        for group in self.layout.groups.iter().filter(|group| group.piece.color == mover) {
+            for i in group.offset..group.offset + group.count {
+                unmoves(group.piece, cells[i], &occupied, &mut froms);
+                let to = cells[i];
+                for &from in &froms {
+                    cells[i] = from;
+                    if let Some(prev) = self.layout.encode(&cells) {
+                        res.push(prev * 2 + mover as usize);
+                    }
+                }
+                cells[i] = to;
+            }
+        }
+    }
+}
+
+/// 棋子不吃子走到`to`之前可能在的格子, 不管是否在它的活动范围内
+fn unmoves(piece: Piece, to: u8, occupied: &[bool; 90], res: &mut Vec<u8>) {
+    res.clear();
+    let (file, rank) = ((to % 9) as i32, (to / 9) as i32);
+    let empty = |f: i32, r: i32| -> Option<u8> {
+        let cell = ((0..9).contains(&f) && (0..10).contains(&r)).then_some((r * 9 + f) as u8)?;
+        (!occupied[cell as usize]).then_some(cell)
+    };
+    const ORTHOGONAL: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    const DIAGONAL: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+    const KNIGHT: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+    match piece.kind {
+        PieceKind::King => res.extend(ORTHOGONAL.iter().filter_map(|&(df, dr)| empty(file + df, rank + dr))),
+        PieceKind::Advisor => res.extend(DIAGONAL.iter().filter_map(|&(df, dr)| empty(file + df, rank + dr))),
+        PieceKind::Bishop => res.extend(
+            DIAGONAL
+                .iter()
+                .filter(|&&(df, dr)| empty(file + df, rank + dr).is_some())
+                .filter_map(|&(df, dr)| empty(file + df * 2, rank + dr * 2)),
+        ),
+        PieceKind::Knight => {
+            for (df, dr) in KNIGHT {
+                // 马腿在出发的格子旁边, 朝着走得远的方向
+                let leg = if df.abs() == 2 { empty(file + df / 2, rank + dr) } else { empty(file + df, rank + dr / 2) };
+                if leg.is_some() {
+                    res.extend(empty(file + df, rank + dr));
+                }
+            }
+        }
+        PieceKind::Rook | PieceKind::Cannon => {
+            for (df, dr) in ORTHOGONAL {
+                let (mut f, mut r) = (file + df, rank + dr);
+                while let Some(cell) = empty(f, r) {
+                    res.push(cell);
+                    f += df;
+                    r += dr;
+                }
+            }
+        }
+        PieceKind::Pawn => {
+            let (forward, crossed) = match piece.color {
+                Color::Red => (1, rank >= 5),
+                Color::Black => (-1, rank <= 4),
+            };
+            res.extend(empty(file, rank - forward));
+            if crossed {
+                res.extend([empty(file - 1, rank), empty(file + 1, rank)].into_iter().flatten());
+            }
+        }
+    }
+}
+
+fn push(buckets: &mut Vec<Vec<u32>>, depth: usize, index: usize) {
+    if buckets.len() <= depth {
+        buckets.resize_with(depth + 1, Vec::new);
+    }
+    buckets[depth].push(index as u32);
+}
+
+fn take(buckets: &mut [Vec<u32>], depth: usize) -> impl Iterator<Item = usize> {
+    buckets.get_mut(depth).map(std::mem::take).unwrap_or_default().into_iter().map(|index| index as usize)
+}
+
+fn encode(wdl: Wdl, dtm: usize) -> u8 {
+    match wdl {
+        Wdl::Win => dtm.div_ceil(2).clamp(1, 127) as u8,
+        Wdl::Loss => LOSS_BASE + (dtm / 2).min(125) as u8,
+        Wdl::Draw => DRAW,
+    }
+}
+
+fn decode(value: u8) -> Option<Probe> {
+    match value {
+        DRAW => Some(Probe { wdl: Wdl::Draw, dtm: 0 }),
+        INVALID => None,
+        v if v < LOSS_BASE => Some(Probe { wdl: Wdl::Win, dtm: v as u32 * 2 - 1 }),
+        v => Some(Probe { wdl: Wdl::Loss, dtm: (v - LOSS_BASE) as u32 * 2 }),
+    }
+}
+
+fn to_square(cell: u8) -> Square {
+    Square::from_coords(cell as usize % 9, cell as usize / 9).unwrap()
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if n < k {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{read_table, write_table, Layout, Material, Probe, Tablebase, Wdl};
+    use crate::fen::Fen;
+    use crate::piece::Color;
+    use crate::position::Position;
+
+    fn probe(tablebase: &Tablebase, fen: &str) -> Option<Probe> {
+        tablebase.probe(&Position::from_fen(&Fen::new(fen)))
+    }
+
+    /// 库里每个局面的结果都要和走一步以后的结果相符
+    fn check_consistent(tablebase: &Tablebase, material: &Material) {
+        let layout = Layout::new(material);
+        let mut cells = vec![0; layout.pieces];
+        for i in 0..layout.size {
+            layout.decode(i, &mut cells);
+            assert_eq!(layout.encode(&cells), Some(i));
+            let Some(pieces) = layout.pieces(&cells) else {
+                continue;
+            };
+            for side in [Color::Red, Color::Black] {
+                let position = Position::from_pieces(&pieces, side);
+                let Some(actual) = tablebase.probe(&position) else {
+                    continue;
+                };
+                let children: Vec<Probe> = position
+                    .legal_moves()
+                    .into_iter()
+                    .map(|mv| {
+                        let mut child = position.clone();
+                        child.make_move(mv);
+                        tablebase.probe(&child).unwrap()
+                    })
+                    .collect();
+                let dtm = |wdl: Wdl| children.iter().filter(move |p| p.wdl == wdl).map(|p| p.dtm + 1);
+                let expected = if let Some(dtm) = dtm(Wdl::Loss).min() {
+                    Probe { wdl: Wdl::Win, dtm }
+                } else if children.iter().any(|p| p.wdl == Wdl::Draw) {
+                    Probe { wdl: Wdl::Draw, dtm: 0 }
+                } else {
+                    Probe { wdl: Wdl::Loss, dtm: dtm(Wdl::Win).max().unwrap_or(0) }
+                };
+                assert_eq!(actual, expected, "{}", position.to_fen().fen_str());
+            }
+        }
+    }
+
+    #[test]
+    fn test_material() {
+        let material: Material = "krkaabb".parse().unwrap();
+        assert_eq!(material.to_string(), "KRKAABB");
+        assert_eq!(material.swapped().to_string(), "KAABBKR");
+        assert_eq!(material.piece_count(), 7);
+        assert_eq!("KPCKAA".parse::<Material>().unwrap().to_string(), "KCPKAA");
+        for bad in ["RK", "KR", "KRRRK", "KXK", "KKK"] {
+            assert!(bad.parse::<Material>().is_err(), "{}", bad);
+        }
+
+        let start = Position::from_fen(&Fen::init());
+        assert_eq!(Material::of(&start).unwrap().to_string(), "KAABBNNRRCCPPPPPKAABBNNRRCCPPPPP");
+        assert_eq!(Material::of(&Position::new()), None);
+    }
+
+    #[test]
+    fn test_krk() {
+        let mut tablebase = Tablebase::new();
+        tablebase.generate(&"KRK".parse().unwrap());
+        let mut materials: Vec<String> = tablebase.materials().map(|m| m.to_string()).collect();
+        materials.sort();
+        assert_eq!(materials, ["KK", "KRK"]);
+
+        // 车守住下二路, 黑将不能和帅照面, 被困毙
+        assert_eq!(probe(&tablebase, "3k5/R8/9/9/9/9/9/9/9/4K4 b"), Some(Probe { wdl: Wdl::Loss, dtm: 0 }));
+        assert_eq!(probe(&tablebase, "3k5/9/R8/9/9/9/9/9/9/4K4 w"), Some(Probe { wdl: Wdl::Win, dtm: 1 }));
+        // 红黑交换后一样
+        assert_eq!(probe(&tablebase, "4k4/9/9/9/9/9/9/9/r8/3K5 w"), Some(Probe { wdl: Wdl::Loss, dtm: 0 }));
+        // 黑将吃掉没有保护的车
+        assert_eq!(probe(&tablebase, "4k4/4R4/9/9/9/9/9/9/9/3K5 b"), Some(Probe { wdl: Wdl::Draw, dtm: 0 }));
+        // 不在库里的子力组合和不可能的局面
+        assert_eq!(probe(&tablebase, "4k4/9/9/9/9/9/9/9/9/3KR4 w"), None);
+        assert_eq!(probe(&tablebase, "4k4/9/9/9/9/9/9/9/9/3KC4 w"), None);
+        assert_eq!(probe(&tablebase, "4k4/9/9/9/9/9/9/9/9/4K3R w"), None);
+
+        let position = Position::from_fen(&Fen::new("3k5/9/R8/9/9/9/9/9/9/4K4 w"));
+        assert_eq!(tablebase.best_move(&position).unwrap().to_iccs(), "a7a8");
+    }
+
+    #[rstest]
+    #[case("KRK")]
+    #[case("KPK")]
+    #[case("KNKA")]
+    #[case("KCKB")]
+    fn test_consistent(#[case] material: &str) {
+        let material: Material = material.parse().unwrap();
+        let mut tablebase = Tablebase::new();
+        tablebase.generate(&material);
+        check_consistent(&tablebase, &material);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let material: Material = "KPK".parse().unwrap();
+        let mut tablebase = Tablebase::new();
+        tablebase.generate(&material);
+        let table = &tablebase.tables[&material];
+
+        let mut bytes = vec![];
+        write_table(&mut bytes, &material, table).unwrap();
+        let (loaded, loaded_table) = read_table(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded, material);
+        assert!(loaded_table.values == table.values);
+
+        assert!(read_table(&mut &b"CCBK"[..]).is_err());
+        assert!(read_table(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use crate::pos::moves::Move;
+use crate::pos::position::Position;
+
+// 每个桶的槽数, 同一个桶里的局面互相竞争
+const BUCKET_SIZE: usize = 4;
+// 分片数, 每个分片一把锁, 多个线程可以同时访问不同的分片
+const SHARDS: usize = 16;
+// 每旧一代相当于少这么多层深度
+const AGE_WEIGHT: i32 = 4;
+
+/// 搜索结果相对于搜索窗口的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// 准确值
+    Exact,
+    /// 产生了截断, 真实值不低于分数
+    Lower,
+    /// 所有着法都不超过alpha, 真实值不高于分数
+    Upper,
+}
+
+/// Alpha-Beta搜索存到置换表的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchEntry {
+    /// 最好的着法, 所有着法都不超过alpha时没有
+    pub best_move: Option<Move>,
+    /// 分数, 将死分数按到这个局面的距离保存
+    pub score: i32,
+    pub bound: Bound,
+}
+
+/// 从置换表取出的一项
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtEntry<T> {
+    /// 存入时的搜索深度, 不分深度的内容(如神经网络的评估)用0
+    pub depth: u8,
+    pub data: T,
+}
+
+#[derive(Clone, Copy)]
+struct Slot<T> {
+    lock: u64,
+    depth: u8,
+    generation: u8,
+    data: T,
+}
+
+/// 定长的置换表, 用Zobrist键值寻址、校验锁确认局面, 按分片加锁, 可以在多个线程间共享.
+/// 桶满时替换深度最浅、最旧的一项: 每次新的搜索调用`new_generation`, 上一代的内容就更容易被替换
+pub struct TranspositionTable<T = SearchEntry> {
+    shards: Vec<Mutex<Vec<Option<Slot<T>>>>>,
+    buckets_per_shard: usize,
+    generation: AtomicU8,
+}
+
+impl<T: Copy> TranspositionTable<T> {
+    /// 按占用的内存(MB)建表, 太小时每个分片至少有一个桶
+    pub fn new(mb: usize) -> Self {
+        let slots = (mb << 20) / size_of::<Option<Slot<T>>>();
+        let buckets_per_shard = (slots / BUCKET_SIZE / SHARDS).max(1);
+        TranspositionTable {
+            shards: (0..SHARDS).map(|_| Mutex::new(vec![None; buckets_per_shard * BUCKET_SIZE])).collect(),
+            buckets_per_shard,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// 能存放的局面数
+    pub fn capacity(&self) -> usize {
+        SHARDS * self.buckets_per_shard * BUCKET_SIZE
+    }
+
+    /// 开始新一轮搜索, 以前存的内容变旧
+    pub fn new_generation(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            shard.lock().unwrap().fill(None);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// 查找局面, 找到时顺便把它标记为当前这一代
+    pub fn probe(&self, position: &Position) -> Option<TtEntry<T>> {
+        self.probe_key(position.hash() as u64, position.lock())
+    }
+
+    /// 按键值和校验锁查找, 用于除局面外还要区分其他内容(如历史局面)的场合
+    pub fn probe_key(&self, key: u64, lock: u64) -> Option<TtEntry<T>> {
+        let (shard, bucket) = self.locate(key);
+        let mut slots = self.shards[shard].lock().unwrap();
+        let generation = self.generation.load(Ordering::Relaxed);
+        slots[bucket..bucket + BUCKET_SIZE].iter_mut().flatten().find(|slot| slot.lock == lock).map(|slot| {
+            slot.generation = generation;
+            TtEntry { depth: slot.depth, data: slot.data }
+        })
+    }
+
+    /// 存入局面. 同一个局面只有在更深或者已经过时的时候才覆盖;
+    /// 否则放到空槽里, 没有空槽就替换深度最浅、最旧的一项
+    pub fn store(&self, position: &Position, depth: u8, data: T) {
+        self.store_key(position.hash() as u64, position.lock(), depth, data);
+    }
+
+    /// 按键值和校验锁存入, 见`probe_key`
+    pub fn store_key(&self, key: u64, lock: u64, depth: u8, data: T) {
+        let (shard, bucket) = self.locate(key);
+        let mut slots = self.shards[shard].lock().unwrap();
+        let generation = self.generation.load(Ordering::Relaxed);
+        let new_slot = Slot { lock, depth, generation, data };
+        let bucket = &mut slots[bucket..bucket + BUCKET_SIZE];
+
+        if let Some(slot) = bucket.iter_mut().flatten().find(|slot| slot.lock == lock) {
+            if depth >= slot.depth || slot.generation != generation {
+                *slot = new_slot;
+            }
+            return;
+        }
+        let victim = bucket
+            .iter_mut()
+            .min_by_key(|slot| match slot {
+                None => i32::MIN,
+                Some(slot) => slot.depth as i32 - AGE_WEIGHT * generation.wrapping_sub(slot.generation) as i32,
+            })
+            .unwrap();
+        *victim = Some(new_slot);
+    }
+
+    /// 已用槽数的千分比, 只统计第一个分片
+    pub fn hashfull(&self) -> usize {
+        let slots = self.shards[0].lock().unwrap();
+        slots.iter().flatten().count() * 1000 / slots.len()
+    }
+
+    /// 键值所在的分片和桶的第一个槽
+    fn locate(&self, key: u64) -> (usize, usize) {
+        let key = key as usize;
+        (key % SHARDS, (key / SHARDS) % self.buckets_per_shard * BUCKET_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TranspositionTable;
+    use crate::fen::Fen;
+    use crate::position::Position;
+
+    /// 初始局面走一步、两步得到的局面
+    fn positions() -> Vec<Position> {
+        let start = Position::from_fen(&Fen::init());
+        let mut res = vec![];
+        for mv in start.legal_moves() {
+            let mut position = start.clone();
+            position.make_move(mv);
+            for reply in position.legal_moves() {
+                let mut child = position.clone();
+                child.make_move(reply);
+                res.push(child);
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_probe_store() {
+        let table = TranspositionTable::<i32>::new(1);
+        let positions = positions();
+        for (i, position) in positions.iter().enumerate().take(100) {
+            table.store(position, 3, i as i32);
+        }
+        for (i, position) in positions.iter().enumerate().take(100) {
+            let entry = table.probe(position).unwrap();
+            assert_eq!((entry.depth, entry.data), (3, i as i32));
+        }
+        assert_eq!(table.probe(&positions[100]), None);
+
+        // 同一个局面, 浅的不覆盖深的
+        table.store(&positions[0], 2, -1);
+        assert_eq!(table.probe(&positions[0]).unwrap().data, 0);
+        table.store(&positions[0], 5, -1);
+        assert_eq!(table.probe(&positions[0]).unwrap().data, -1);
+
+        table.clear();
+        assert_eq!(table.probe(&positions[0]), None);
+        assert_eq!(table.hashfull(), 0);
+    }
+
+    #[test]
+    fn test_replacement() {
+        // 每个分片只有一个桶, 同一分片的局面挤在一起
+        let table = TranspositionTable::<usize>::new(0);
+        assert_eq!(table.capacity(), 64);
+        let positions = positions();
+        let shard: Vec<&Position> = positions.iter().filter(|p| p.hash() % 16 == 0).take(6).collect();
+
+        for (i, position) in shard.iter().enumerate().take(4) {
+            table.store(position, [5, 1, 7, 3][i] as u8, i);
+        }
+        // 桶满了, 替换最浅的
+        table.store(shard[4], 4, 4);
+        assert_eq!(table.probe(shard[1]), None);
+        assert!(table.probe(shard[4]).is_some());
+
+        // 新的一代, 没被用到的旧内容先被替换, 即使更深
+        table.new_generation();
+        for position in [shard[0], shard[2], shard[4]] {
+            table.probe(position);
+        }
+        table.store(shard[5], 1, 5);
+        assert_eq!(table.probe(shard[3]), None);
+        assert!([0, 2, 4, 5].iter().all(|&i| table.probe(shard[i]).is_some()));
+    }
+}
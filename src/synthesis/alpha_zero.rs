@@ -43,6 +43,10 @@ pub trait AlphaZeroTrainer<G: Game<N>, const N: usize> {
 
     fn save_checkpoint(&mut self, path: &Path) -> Result<()>;
     fn load_policy(&self, path: &Path) -> Result<Self::Policy>;
+    /// 自对弈每局的初始局面, 可以带上与网络输入一致的设置
+    fn new_game(&self) -> G {
+        G::new()
+    }
     fn train(
         &mut self,
         batch: &FlatBatch<G, N>,
@@ -223,7 +227,13 @@ where
 
     for game_idx in 0..num_games {
         buffer.new_game();
-        run_game::<G, _, _, N>(&cfg.rollout_cfg, &mut cached_policy, &mut rng, &mut buffer);
+        run_game::<G, _, _, N>(
+            trainer.new_game(),
+            &cfg.rollout_cfg,
+            &mut cached_policy,
+            &mut rng,
+            &mut buffer,
+        );
         progress_bar.inc(1);
         if !interactive_terminal
             && (game_idx + 1 == num_games
@@ -265,6 +275,7 @@ impl StateInfo {
 }
 
 fn run_game<G, P, R, const N: usize>(
+    mut game: G,
     cfg: &RolloutConfig,
     policy: &mut P,
     rng: &mut R,
@@ -274,7 +285,6 @@ fn run_game<G, P, R, const N: usize>(
     P: Policy<G, N>,
     R: Rng,
 {
-    let mut game = G::new();
     let mut solution = None;
     let mut search_policy = [0.0; N];
     let mut num_turns = 0;
@@ -295,6 +305,9 @@ fn run_game<G, P, R, const N: usize>(
         let is_over = game.step(&action);
         if is_over {
             solution = Some(game.reward(game.player()).into());
+        } else if let Some(outcome) = game.known_outcome() {
+            // 残局库能判定的局面直接按库的结果结束对局
+            solution = Some(outcome);
         } else if !cfg.stop_games_when_solved {
             solution = None;
         }
@@ -333,7 +346,9 @@ where
     if num_turns < cfg.sample_actions_until && (solution.is_none() || !cfg.stop_games_when_solved) {
         if let Ok(dist) = WeightedIndex::new(search_policy.iter().copied()) {
             let choice = dist.sample(rng);
-            return G::Action::from(choice);
+            if let Ok(action) = G::Action::try_from(choice) {
+                return action;
+            }
         }
     }
 
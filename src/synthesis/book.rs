@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use rand::distr::{weighted::WeightedIndex, Distribution};
+use rand::Rng;
+
+use crate::cchess::{CChess, GameResult};
+use crate::pos::moves::Move;
+use crate::pos::piece::Color;
+use crate::pos::position::Position;
+use crate::synthesis::game::Game;
+use crate::synthesis::pgn::PgnGame;
+
+// 文件头: 标识和格式版本. 版本2起动作序号只编号几何上可能的着法
+const MAGIC: &[u8; 4] = b"CCBK";
+const VERSION: u32 = 2;
+
+/// 开局库中某个局面下的一个着法, 胜负和从走这步棋的一方来看
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub mv: Move,
+    /// 选中这步棋的权重, 建库时为出现的次数
+    pub weight: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+// 存在库里的着法只记动作序号, 查询时在局面中还原并检查是否合法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Record {
+    action: u16,
+    weight: u32,
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+/// 开局库: 按局面的Zobrist校验锁保存每个局面下走过的着法和结果, 不同着法顺序到达的同一局面合在一起.
+/// 可以从PGN棋谱或者自对弈的对局生成, 保存成紧凑的二进制文件; 对局时在前`max_depth`步(半回合)内按权重随机选择着法
+#[derive(Debug, Clone)]
+pub struct OpeningBook {
+    positions: HashMap<u64, Vec<Record>>,
+    /// 建库时只记录、对局时只使用前这么多步(半回合)
+    pub max_depth: usize,
+}
+
+impl OpeningBook {
+    pub fn new(max_depth: usize) -> Self {
+        Self { positions: HashMap::new(), max_depth }
+    }
+
+    /// 收录的局面数
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// 加入一局棋: 从起始局面依次走出着法, 前`max_depth`步的每个着法计一次. 结果未知时只计次数
+    pub fn add_game(&mut self, start: &Position, moves: &[Move], result: Option<GameResult>) {
+        let mut position = start.clone();
+        for &mv in moves {
+            if ply(&position) >= self.max_depth {
+                break;
+            }
+            let mover = position.side_to_move();
+            let records = self.positions.entry(position.lock()).or_default();
+            let action = mv.action_index() as u16;
+            let record = match records.iter_mut().position(|record| record.action == action) {
+                Some(i) => &mut records[i],
+                None => {
+                    records.push(Record { action, weight: 0, wins: 0, draws: 0, losses: 0 });
+                    records.last_mut().unwrap()
+                }
+            };
+            record.weight += 1;
+            match result {
+                Some(GameResult::Win(winner)) if winner == mover => record.wins += 1,
+                Some(GameResult::Win(_)) => record.losses += 1,
+                Some(GameResult::Draw) => record.draws += 1,
+                None => {}
+            }
+            position.make_move(mv);
+        }
+    }
+
+    /// 加入一局PGN棋谱, 结果取自棋谱末尾或Result头部, "1-0"表示红胜
+    pub fn add_pgn(&mut self, game: &PgnGame) -> Result<()> {
+        let moves = game.replay()?;
+        let result = match game.result.as_deref().or(game.get_header("Result")) {
+            Some("1-0") => Some(GameResult::Win(Color::Red)),
+            Some("0-1") => Some(GameResult::Win(Color::Black)),
+            Some("1/2-1/2") => Some(GameResult::Draw),
+            _ => None,
+        };
+        self.add_game(&game.start_position()?, &moves, result);
+        Ok(())
+    }
+
+    /// 去掉出现次数少于min_weight的着法, 以及因此没有着法的局面
+    pub fn prune(&mut self, min_weight: u32) {
+        self.positions.retain(|_, records| {
+            records.retain(|record| record.weight >= min_weight);
+            !records.is_empty()
+        });
+    }
+
+    /// 查询局面, 返回库里的合法着法, 按权重从高到低排列
+    pub fn probe(&self, position: &Position) -> Vec<BookEntry> {
+        let Some(records) = self.positions.get(&position.lock()) else {
+            return vec![];
+        };
+        let legal = position.legal_moves();
+        let mut entries: Vec<BookEntry> = records
+            .iter()
+            .filter_map(|record| {
+                let mv = Move::from_action(position, record.action as usize).ok()?;
+                legal.contains(&mv).then_some(BookEntry {
+                    mv,
+                    weight: record.weight,
+                    wins: record.wins,
+                    draws: record.draws,
+                    losses: record.losses,
+                })
+            })
+            .collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.weight));
+        entries
+    }
+
+    /// 对局中按权重随机选一个库里的着法; 超过`max_depth`步或者库里没有这个局面时返回None, 由搜索决定
+    pub fn pick<R: Rng>(&self, game: &CChess, rng: &mut R) -> Option<Move> {
+        if game.is_over() || ply(game.position()) >= self.max_depth {
+            return None;
+        }
+        let entries = self.probe(game.position());
+        let dist = WeightedIndex::new(entries.iter().map(|entry| entry.weight)).ok()?;
+        Some(entries[dist.sample(rng)].mv)
+    }
+
+    /// 写成二进制格式: 文件头之后每个局面是校验锁和着法数, 再跟着每个着法的动作序号、权重和胜和负的次数.
+    /// 局面按校验锁排序, 同样的库总是写出同样的文件
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.max_depth as u32).to_le_bytes())?;
+        writer.write_all(&(self.positions.len() as u32).to_le_bytes())?;
+
+        let mut keys: Vec<&u64> = self.positions.keys().collect();
+        keys.sort();
+        for key in keys {
+            let records = &self.positions[key];
+            writer.write_all(&key.to_le_bytes())?;
+            writer.write_all(&(records.len() as u16).to_le_bytes())?;
+            for record in records {
+                writer.write_all(&record.action.to_le_bytes())?;
+                for count in [record.weight, record.wins, record.draws, record.losses] {
+                    writer.write_all(&count.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 读取`write_to`写出的二进制格式
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != *MAGIC {
+            bail!("不是开局库文件");
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            bail!("不支持的开局库版本: {}", version);
+        }
+        let max_depth = read_u32(reader)? as usize;
+        let num_positions = read_u32(reader)? as usize;
+
+        let mut book = OpeningBook::new(max_depth);
+        book.positions.reserve(num_positions);
+        for _ in 0..num_positions {
+            let mut key = [0; 8];
+            reader.read_exact(&mut key)?;
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            let records = (0..u16::from_le_bytes(len))
+                .map(|_| {
+                    let mut action = [0; 2];
+                    reader.read_exact(&mut action)?;
+                    Ok(Record {
+                        action: u16::from_le_bytes(action),
+                        weight: read_u32(reader)?,
+                        wins: read_u32(reader)?,
+                        draws: read_u32(reader)?,
+                        losses: read_u32(reader)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            book.positions.insert(u64::from_le_bytes(key), records);
+        }
+        Ok(book)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("无法创建开局库文件: {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// 读取开局库文件, 使用时的最大步数沿用建库时的设置, 需要时再修改`max_depth`
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("无法打开开局库文件: {:?}", path))?;
+        Self::read_from(&mut BufReader::new(file)).with_context(|| format!("无法读取开局库文件: {:?}", path))
+    }
+}
+
+/// 局面在对局中的步数(半回合), 由回合数和走棋方算出, 从FEN开始的局面也适用
+fn ply(position: &Position) -> usize {
+    (position.fullmove_number().max(1) as usize - 1) * 2 + (position.side_to_move() == Color::Black) as usize
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+
+    use super::OpeningBook;
+    use crate::cchess::CChess;
+    use crate::fen::Fen;
+    use crate::moves::Move;
+    use crate::position::Position;
+    use crate::synthesis::game::Game;
+    use crate::synthesis::pgn::parse_pgn;
+
+    const GAMES: &str = "[Format \"ICCS\"]\n\n1. H2-E2 H9-G7 2. B0-C2 I9-H9 1-0\n\n\
+                         [Format \"ICCS\"]\n\n1. H2-E2 H7-E7 2. H0-G2 1/2-1/2\n\n\
+                         [Format \"ICCS\"]\n\n1. B0-C2 H9-G7 2. H2-E2 0-1\n\n\
+                         [Format \"ICCS\"]\n\n1. C3-C4 *";
+
+    fn build(max_depth: usize) -> OpeningBook {
+        let mut book = OpeningBook::new(max_depth);
+        for game in parse_pgn(GAMES).unwrap() {
+            book.add_pgn(&game).unwrap();
+        }
+        book
+    }
+
+    #[test]
+    fn test_build_and_probe() {
+        let book = build(3);
+        let start = Position::from_fen(&Fen::init());
+        let entries = book.probe(&start);
+        let summary: Vec<_> =
+            entries.iter().map(|e| (e.mv.to_iccs(), e.weight, e.wins, e.draws, e.losses)).collect();
+        assert_eq!(summary[0], ("h2e2".to_string(), 2, 1, 1, 0));
+        assert!(summary.contains(&("b0c2".to_string(), 1, 0, 0, 1)));
+        assert!(summary.contains(&("c3c4".to_string(), 1, 0, 0, 0)));
+
+        // 第一局和第三局着法顺序不同, 走了三步后到达同一个局面, 但第四步超出了max_depth
+        let mut position = start.clone();
+        for iccs in ["h2e2", "h9g7", "b0c2"] {
+            position.make_move(Move::from_iccs(&position, iccs).unwrap());
+        }
+        assert!(book.probe(&position).is_empty());
+        let deeper = build(4);
+        let entries = deeper.probe(&position);
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].mv.to_iccs().as_str(), entries[0].weight), ("i9h9", 1));
+        assert_eq!(deeper.len(), 7);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut book = build(4);
+        book.prune(2);
+        assert_eq!(book.len(), 1);
+
+        let mut bytes = vec![];
+        book.write_to(&mut bytes).unwrap();
+        let loaded = OpeningBook::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.max_depth, 4);
+        let start = Position::from_fen(&Fen::init());
+        assert_eq!(loaded.probe(&start), book.probe(&start));
+        assert_eq!(loaded.probe(&start).len(), 1);
+
+        assert!(OpeningBook::read_from(&mut &b"PGN?"[..]).is_err());
+        assert!(OpeningBook::read_from(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut book = build(2);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut game = CChess::new();
+        for _ in 0..20 {
+            let mv = book.pick(&game, &mut rng).unwrap();
+            assert!(["h2e2", "b0c2", "c3c4"].contains(&mv.to_iccs().as_str()));
+        }
+
+        game.step(&Move::from_iccs(game.position(), "h2e2").unwrap());
+        assert!(book.pick(&game, &mut rng).is_some());
+        game.step(&Move::from_iccs(game.position(), "h9g7").unwrap());
+        assert_eq!(book.pick(&game, &mut rng), None);
+        book.max_depth = 0;
+        assert_eq!(book.pick(&CChess::new(), &mut rng), None);
+    }
+}
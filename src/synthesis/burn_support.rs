@@ -1,24 +1,69 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use burn::backend::{Autodiff, Wgpu};
 use burn::module::Module;
 use burn::optim::{AdamConfig, GradientsParams, Optimizer};
 use burn::record::{FullPrecisionSettings, NamedMpkFileRecorder};
 use burn::tensor::activation::{log_softmax, softmax};
 use burn::tensor::{Tensor, TensorData};
+use serde::{Deserialize, Serialize};
 
-use crate::cchess::{CChess, BOARD_FILES, BOARD_RANKS, INPUT_PLANES, MAX_NUM_ACTIONS};
+use crate::cchess::{CChess, FeatureEncoder, BOARD_FILES, BOARD_RANKS, MAX_NUM_ACTIONS};
 use crate::net::{Net, NetConfig};
 use crate::synthesis::data::FlatBatch;
-use crate::synthesis::{AlphaZeroTrainer, Game, LearningConfig, Policy, TrainingMetrics};
+use crate::synthesis::{AlphaZeroTrainer, LearningConfig, Policy, TrainingMetrics};
 
 pub type BurnBackend = Wgpu<f32, i32>;
 pub type BurnAutodiffBackend = Autodiff<BurnBackend>;
 
+// 以前的动作空间是起点和终点的所有组合
+const LEGACY_NUM_ACTIONS: usize = 90 * 90;
+
+// 记录特征编码之前, checkpoint路径里只写了这个标记
+const LEGACY_METADATA: &[u8] = b"burn checkpoint metadata";
+
+/// 与权重一起保存的checkpoint元数据, 写在checkpoint路径(.ot)里
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CheckpointMeta {
+    encoder: FeatureEncoder,
+    /// 策略输出的大小, 没有记录的是旧的动作空间
+    #[serde(default = "legacy_num_actions")]
+    num_actions: usize,
+}
+
+fn legacy_num_actions() -> usize {
+    LEGACY_NUM_ACTIONS
+}
+
+/// 读取checkpoint训练时使用的特征编码. 只写了旧标记的checkpoint按默认编码和旧的动作空间处理;
+/// 读不了或者解析不了的元数据, 以及用旧的动作空间训练的checkpoint, 返回错误
+pub fn checkpoint_encoder(path: &Path) -> Result<FeatureEncoder> {
+    if !path.exists() {
+        return Err(anyhow::anyhow!("Checkpoint file not found: {:?}", path));
+    }
+    let content = std::fs::read(path).with_context(|| format!("Failed to read checkpoint {:?}", path))?;
+    let meta = if content == LEGACY_METADATA {
+        CheckpointMeta { encoder: FeatureEncoder::default(), num_actions: LEGACY_NUM_ACTIONS }
+    } else {
+        serde_json::from_slice::<CheckpointMeta>(&content)
+            .with_context(|| format!("Invalid checkpoint metadata in {:?}", path))?
+    };
+    if meta.num_actions != MAX_NUM_ACTIONS {
+        bail!(
+            "Checkpoint {:?} has {} policy outputs, but the network uses {} actions",
+            path,
+            meta.num_actions,
+            MAX_NUM_ACTIONS
+        );
+    }
+    Ok(meta.encoder)
+}
+
 #[derive(Debug, Clone)]
 pub struct BurnPolicy {
     model: Net<BurnBackend>,
+    encoder: FeatureEncoder,
     device: <BurnBackend as burn::prelude::Backend>::Device,
 }
 
@@ -27,7 +72,20 @@ impl BurnPolicy {
         model: Net<BurnBackend>,
         device: <BurnBackend as burn::prelude::Backend>::Device,
     ) -> Self {
-        Self { model, device }
+        Self::with_encoder(model, FeatureEncoder::default(), device)
+    }
+
+    /// 网络按`encoder`的编码训练, 评估时不管对局自己的编码设置, 都按它编码
+    pub fn with_encoder(
+        model: Net<BurnBackend>,
+        encoder: FeatureEncoder,
+        device: <BurnBackend as burn::prelude::Backend>::Device,
+    ) -> Self {
+        Self { model, encoder, device }
+    }
+
+    pub fn encoder(&self) -> FeatureEncoder {
+        self.encoder
     }
 }
 
@@ -41,7 +99,7 @@ impl Policy<CChess, MAX_NUM_ACTIONS> for BurnPolicy {
             return Vec::new();
         }
 
-        let states: Vec<_> = games.iter().map(CChess::features).collect();
+        let states: Vec<_> = games.iter().map(|game| self.encoder.encode(game)).collect();
         let input = state_tensor::<BurnBackend>(&states, &self.device);
         let (policy_logits, value_logits) = self.model.forward(input);
         let value_probs = softmax(value_logits, 1);
@@ -68,6 +126,7 @@ impl Policy<CChess, MAX_NUM_ACTIONS> for BurnPolicy {
 pub struct BurnTrainer {
     model: Net<BurnAutodiffBackend>,
     model_config: NetConfig,
+    encoder: FeatureEncoder,
     device: <BurnBackend as burn::prelude::Backend>::Device,
 }
 
@@ -76,14 +135,29 @@ impl BurnTrainer {
         model_config: NetConfig,
         device: <BurnBackend as burn::prelude::Backend>::Device,
     ) -> Self {
+        Self::with_encoder(model_config, FeatureEncoder::default(), device)
+    }
+
+    /// 按指定的特征编码训练, 网络的输入平面数跟着编码走
+    pub fn with_encoder(
+        model_config: NetConfig,
+        encoder: FeatureEncoder,
+        device: <BurnBackend as burn::prelude::Backend>::Device,
+    ) -> Self {
+        let model_config = model_config.with_input_planes(encoder.planes());
         let model = model_config.init::<BurnAutodiffBackend>(&device);
         Self {
             model,
             model_config,
+            encoder,
             device,
         }
     }
 
+    pub fn encoder(&self) -> FeatureEncoder {
+        self.encoder
+    }
+
     fn checkpoint_base(path: &Path) -> PathBuf {
         let mut base = path.to_path_buf();
         if base.extension().is_some() {
@@ -93,22 +167,22 @@ impl BurnTrainer {
     }
 
     /// 从已有 checkpoint 加载权重，用于断点续训。
-    /// 网络结构（hidden_size / num_blocks）必须与保存时完全一致。
+    /// 网络结构（hidden_size / num_blocks）和特征编码必须与保存时完全一致。
     pub fn load_weights(&mut self, path: &Path) -> Result<()> {
         let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
         let base = Self::checkpoint_base(path);
-        
-        // 读取 .ot 文件确认存在
-        if !path.exists() {
-            return Err(anyhow::anyhow!(
-                "Checkpoint file not found: {:?}",
-                path
-            ));
+
+        // 读取 checkpoint 元数据，确认存在且特征编码一致
+        let encoder = checkpoint_encoder(path)?;
+        if encoder != self.encoder {
+            bail!(
+                "Checkpoint {:?} was trained with {:?}, but the trainer uses {:?}",
+                path,
+                encoder,
+                self.encoder
+            );
         }
         
-        // 读取 checkpoint 元数据文件到内存，避免 Windows 文件锁定问题
-        let _metadata_content = std::fs::read(path)?;
-        
         // 使用当前模型配置加载权重
         let loaded = self
             .model_config
@@ -151,18 +225,27 @@ impl AlphaZeroTrainer<CChess, MAX_NUM_ACTIONS> for BurnTrainer {
         self.model
             .clone()
             .save_file(base, &recorder)?;
-        std::fs::write(path, b"burn checkpoint metadata")?;
+        let meta = CheckpointMeta { encoder: self.encoder, num_actions: MAX_NUM_ACTIONS };
+        std::fs::write(path, serde_json::to_string(&meta)?)?;
         Ok(())
     }
 
+    /// 按checkpoint记录的特征编码建网络, 所以可以加载与训练器设置不同的checkpoint
     fn load_policy(&self, path: &Path) -> Result<Self::Policy> {
         let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
         let base = Self::checkpoint_base(path);
+        let encoder = checkpoint_encoder(path)?;
         let model = self
             .model_config
+            .clone()
+            .with_input_planes(encoder.planes())
             .init::<BurnBackend>(&self.device)
             .load_file(base, &recorder, &self.device)?;
-        Ok(BurnPolicy::new(model, self.device.clone()))
+        Ok(BurnPolicy::with_encoder(model, encoder, self.device.clone()))
+    }
+
+    fn new_game(&self) -> CChess {
+        CChess::with_encoder(self.encoder)
     }
 
     fn train(
@@ -191,7 +274,7 @@ impl AlphaZeroTrainer<CChess, MAX_NUM_ACTIONS> for BurnTrainer {
         for _ in 0..cfg.num_epochs {
             indices.shuffle(&mut rng);
             for chunk in indices.chunks(batch_size) {
-                let states: Vec<_> = chunk.iter().map(|&idx| batch.states[idx]).collect();
+                let states: Vec<_> = chunk.iter().map(|&idx| batch.states[idx].clone()).collect();
                 let pis: Vec<_> = chunk.iter().map(|&idx| batch.pis[idx]).collect();
                 let values: Vec<_> = chunk.iter().map(|&idx| batch.vs[idx]).collect();
 
@@ -232,7 +315,8 @@ fn state_tensor<B: burn::prelude::Backend>(
     states: &[<CChess as crate::synthesis::Game<MAX_NUM_ACTIONS>>::Features],
     device: &B::Device,
 ) -> Tensor<B, 4> {
-    let mut data = Vec::with_capacity(states.len() * INPUT_PLANES * BOARD_RANKS * BOARD_FILES);
+    let planes = states.first().map_or(0, Vec::len);
+    let mut data = Vec::with_capacity(states.len() * planes * BOARD_RANKS * BOARD_FILES);
     for state in states {
         for plane in state {
             for row in plane {
@@ -246,7 +330,7 @@ fn state_tensor<B: burn::prelude::Backend>(
     Tensor::<B, 4>::from_data(
         TensorData::new(
             data,
-            [states.len(), INPUT_PLANES, BOARD_RANKS, BOARD_FILES],
+            [states.len(), planes, BOARD_RANKS, BOARD_FILES],
         ),
         device,
     )
@@ -236,3 +236,22 @@ impl<G: Game<N>, const N: usize> ReplayBuffer<G, N> {
         FlatBatch { states, pis, vs }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ReplayBuffer;
+    use crate::cchess::{repeated_start, CChess, MAX_NUM_ACTIONS};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(false, 1)]
+    #[case(true, 2)]
+    fn test_deduplicate_keeps_repeated_position_apart(#[case] repetitions: bool, #[case] entries: usize) {
+        let (start, repeated) = repeated_start(repetitions);
+        let mut buffer = ReplayBuffer::<CChess, MAX_NUM_ACTIONS>::new(2);
+        buffer.new_game();
+        buffer.add(&start, &[0.0; MAX_NUM_ACTIONS], [1.0, 0.0, 0.0]);
+        buffer.add(&repeated, &[0.0; MAX_NUM_ACTIONS], [0.0, 0.0, 1.0]);
+        assert_eq!(buffer.deduplicate().states.len(), entries);
+    }
+}
@@ -388,7 +388,7 @@ impl<'a, G: Game<N>, P: Policy<G, N>, const N: usize> FrozenMCTS<'a, G, P, N> {
             };
             if best_action.is_none() || value > best_value {
                 best_value = value;
-                best_action = Some((child.action as usize).into());
+                best_action = G::Action::try_from(child.action as usize).ok();
             }
         }
         best_action.unwrap()
@@ -67,7 +67,7 @@ impl PartialOrd for Outcome {
 
 pub trait Game<const N: usize>: Eq + Hash + Clone + std::fmt::Debug + Send {
     type PlayerId: HasTurnOrder;
-    type Action: Eq + Clone + Copy + std::fmt::Debug + Into<usize> + From<usize>;
+    type Action: Eq + Clone + Copy + std::fmt::Debug + Into<usize> + TryFrom<usize>;
     type ActionIterator: Iterator<Item = Self::Action>;
     type Features: PartialEq + Clone + std::fmt::Debug + Send;
 
@@ -84,6 +84,10 @@ pub trait Game<const N: usize>: Eq + Hash + Clone + std::fmt::Debug + Send {
     fn iter_actions(&self) -> Self::ActionIterator;
     /// 执行动作, 并判断游戏是否结束.
     fn step(&mut self, action: &Self::Action) -> bool;
+    /// 没结束但不用搜索就知道结果的局面(如残局库中的局面), 从当前走棋方来看; 默认没有
+    fn known_outcome(&self) -> Option<Outcome> {
+        None
+    }
     fn features(&self) -> Self::Features;
     fn print(&self);
 }
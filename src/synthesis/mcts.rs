@@ -87,7 +87,9 @@ impl<G: Game<N>, const N: usize> Node<G, N> {
 
     /// 获取当前节点的动作。
     fn action(&self) -> G::Action {
-        (self.action as usize).into()
+        G::Action::try_from(self.action as usize)
+            .ok()
+            .expect("节点中的动作序号来自合法动作")
     }
 
     /// 检查当前节点是否未被访问。
@@ -507,11 +509,12 @@ impl<'a, G: Game<N>, P: Policy<G, N>, const N: usize> MCTS<'a, G, P, N> {
             let mut child_game = game.clone();
             let is_over = child_game.step(&action);
             let solution = if is_over {
-                any_solved = true;
                 Some(child_game.reward(child_game.player()).into())
             } else {
-                None
+                // 残局库等能直接判定结果的局面也当作已解决
+                child_game.known_outcome()
             };
+            any_solved |= solution.is_some();
             let action: usize = action.into();
             let child = Node::unvisited(
                 node_id,
@@ -632,11 +635,12 @@ impl<'a, G: Game<N>, P: Policy<G, N>, const N: usize> MCTS<'a, G, P, N> {
             let mut child_game = game.clone();
             let is_over = child_game.step(&action);
             let solution = if is_over {
-                any_solved = true;
                 Some(child_game.reward(child_game.player()).into())
             } else {
-                None
+                // 残局库等能直接判定结果的局面也当作已解决
+                child_game.known_outcome()
             };
+            any_solved |= solution.is_some();
             let action: usize = action.into();
             let child = Node::unvisited(
                 node_id,
@@ -7,12 +7,13 @@ mod mcts;
 pub mod policies;
 mod utils;
 pub mod pgn;
+pub mod book;
 
 
 pub use alpha_zero::{
     alpha_zero, AlphaZeroIterationMetrics, AlphaZeroReport, AlphaZeroTrainer, TrainingMetrics,
 };
-pub use burn_support::{BurnAutodiffBackend, BurnBackend, BurnPolicy, BurnTrainer};
+pub use burn_support::{checkpoint_encoder, BurnAutodiffBackend, BurnBackend, BurnPolicy, BurnTrainer};
 pub use config::{
     ActionSelection, EvaluationConfig, Exploration, Fpu, LearningConfig, MCTSConfig, PolicyNoise,
     RolloutConfig, ValueTarget,
@@ -4,6 +4,10 @@ use std::path::Path;
 
 use anyhow::{Context, Result};
 
+use crate::pos::fen::Fen;
+use crate::pos::moves::{Move, Notation};
+use crate::pos::position::Position;
+
 /// PGN (Portable Game Notation) 记录结构
 #[derive(Debug, Clone)]
 pub struct PgnGame {
@@ -214,18 +218,67 @@ fn remove_comments(line: &str) -> String {
     result
 }
 
-/// 将中国象棋走法转换为 PGN 格式
-pub fn move_to_pgn(move_str: &str, _turn: usize) -> String {
-    // 简化实现：直接使用走法字符串
-    // 实际应用中可能需要更复杂的转换逻辑
-    move_str.to_string()
+/// 将着法转换为 PGN 中的记录格式, ICCS 格式写成 "H2-E2" 的形式
+pub fn move_to_pgn(position: &Position, mv: &Move, notation: Notation) -> String {
+    match notation {
+        Notation::Iccs => {
+            let iccs = mv.to_iccs().to_ascii_uppercase();
+            format!("{}-{}", &iccs[..2], &iccs[2..])
+        }
+        _ => notation.format(position, mv),
+    }
 }
 
-/// 从 PGN 格式解析为中国象棋走法
-pub fn pgn_to_move(pgn_move: &str) -> Result<String> {
-    // 简化实现：直接返回走法字符串
-    // 实际应用中可能需要解析和验证
-    Ok(pgn_move.to_string())
+/// 在给定局面下解析 PGN 中的着法
+pub fn pgn_to_move(position: &Position, pgn_move: &str, notation: Notation) -> Result<Move> {
+    Ok(notation.parse(position, pgn_move)?)
+}
+
+impl PgnGame {
+    /// 棋谱的着法格式, 由 Format 头部给出, 没有时按中文纵线格式处理
+    pub fn notation(&self) -> Result<Notation> {
+        match self.get_header("Format") {
+            Some(format) => format.parse().map_err(anyhow::Error::msg),
+            None => Ok(Notation::Chinese),
+        }
+    }
+
+    /// 起始局面, 由 FEN 头部给出, 没有时为初始局面
+    pub fn start_position(&self) -> Result<Position> {
+        let fen: Fen = match self.get_header("FEN") {
+            Some(fen) => fen.parse()?,
+            None => Fen::init(),
+        };
+        Ok(Position::from_fen(&fen))
+    }
+
+    /// 从起始局面开始, 按棋谱的着法格式依次走出所有着法
+    pub fn replay(&self) -> Result<Vec<Move>> {
+        let notation = self.notation()?;
+        let mut position = self.start_position()?;
+        let mut moves = Vec::with_capacity(self.moves.len());
+        for (i, pgn_move) in self.moves.iter().enumerate() {
+            let mv = pgn_to_move(&position, pgn_move, notation)
+                .with_context(|| format!("第 {} 步着法无效", i + 1))?;
+            position.make_move(mv);
+            moves.push(mv);
+        }
+        Ok(moves)
+    }
+
+    /// 复盘后把所有着法写成另一种格式
+    pub fn convert_moves(&self, notation: Notation) -> Result<Vec<String>> {
+        let mut position = self.start_position()?;
+        Ok(self
+            .replay()?
+            .into_iter()
+            .map(|mv| {
+                let pgn_move = move_to_pgn(&position, &mv, notation);
+                position.make_move(mv);
+                pgn_move
+            })
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -253,4 +306,40 @@ mod tests {
         assert!(output.contains("1. 炮二平五 马8进7"));
         assert!(output.contains("1-0"));
     }
+
+    #[test]
+    fn test_iccs_replay() {
+        let games = parse_pgn("[Format \"ICCS\"]\n\n1. H2-E2 H9-G7 2. H0-G2 *").unwrap();
+        assert_eq!(games[0].notation().unwrap(), Notation::Iccs);
+        assert_eq!(games[0].replay().unwrap().len(), 3);
+        assert_eq!(games[0].convert_moves(Notation::Iccs).unwrap(), games[0].moves);
+    }
+
+    #[test]
+    fn test_wxf_replay() {
+        let games = parse_pgn("[Format \"WXF\"]\n\n1. C2=5 H8+7 2. H2+3 R9=8 *").unwrap();
+        assert_eq!(games[0].notation().unwrap(), Notation::Wxf);
+        assert_eq!(
+            games[0].convert_moves(Notation::Iccs).unwrap(),
+            ["H2-E2", "H9-G7", "H0-G2", "I9-H9"]
+        );
+        assert_eq!(games[0].convert_moves(Notation::Wxf).unwrap(), games[0].moves);
+    }
+
+    #[test]
+    fn test_chinese_replay() {
+        let games = parse_pgn("1. 炮二平五 马8进7 2. 马二进三 车9平8 3. 车一平二 马2进3 *").unwrap();
+        assert_eq!(games[0].notation().unwrap(), Notation::Chinese);
+        assert_eq!(
+            games[0].convert_moves(Notation::Wxf).unwrap(),
+            ["C2=5", "H8+7", "H2+3", "R9=8", "R1=2", "H2+3"]
+        );
+        assert_eq!(games[0].convert_moves(Notation::Chinese).unwrap(), games[0].moves);
+    }
+
+    #[test]
+    fn test_iccs_replay_illegal_move() {
+        let games = parse_pgn("[Format \"ICCS\"]\n\n1. H2-E2 H2-E2 *").unwrap();
+        assert!(games[0].replay().is_err());
+    }
 }
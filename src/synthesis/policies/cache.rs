@@ -1,3 +1,5 @@
+use crate::cchess::{CChess, MAX_NUM_ACTIONS};
+use crate::pos::tt::TranspositionTable;
 use crate::synthesis::game::Game;
 use crate::synthesis::policies::Policy;
 use std::collections::HashMap;
@@ -103,3 +105,94 @@ impl<G: Game<N>, P: Policy<G, N>, const N: usize> Policy<G, N> for OwnedPolicyWi
         out
     }
 }
+
+/// 中国象棋专用的缓存: 评估结果存在按`CChess::feature_key`寻址的定长置换表里, 内存不会随着搜索无限增长.
+/// 特征编码用到历史局面时, 同一个局面不同的历史分开缓存.
+/// 表可以在多步棋之间、多个线程之间共享, 满了按深度和新旧替换(评估结果的深度都是0)
+pub struct PolicyWithTable<'a, P: Policy<CChess, MAX_NUM_ACTIONS>> {
+    pub policy: &'a mut P,
+    pub table: &'a TranspositionTable<([f32; MAX_NUM_ACTIONS], [f32; 3])>,
+}
+
+impl<'a, P: Policy<CChess, MAX_NUM_ACTIONS>> PolicyWithTable<'a, P> {
+    pub fn new(policy: &'a mut P, table: &'a TranspositionTable<([f32; MAX_NUM_ACTIONS], [f32; 3])>) -> Self {
+        Self { policy, table }
+    }
+}
+
+impl<P: Policy<CChess, MAX_NUM_ACTIONS>> Policy<CChess, MAX_NUM_ACTIONS> for PolicyWithTable<'_, P> {
+    fn eval(&mut self, game: &CChess) -> ([f32; MAX_NUM_ACTIONS], [f32; 3]) {
+        let (key, lock) = game.feature_key();
+        if let Some(entry) = self.table.probe_key(key, lock) {
+            entry.data
+        } else {
+            let pi_v = self.policy.eval(game);
+            self.table.store_key(key, lock, 0, pi_v);
+            pi_v
+        }
+    }
+
+    fn eval_batch(&mut self, games: &[CChess]) -> Vec<([f32; MAX_NUM_ACTIONS], [f32; 3])> {
+        let mut out = vec![([0.0; MAX_NUM_ACTIONS], [0.0; 3]); games.len()];
+        let mut miss_indices = Vec::new();
+        let mut miss_games = Vec::new();
+
+        for (i, game) in games.iter().enumerate() {
+            let (key, lock) = game.feature_key();
+            if let Some(entry) = self.table.probe_key(key, lock) {
+                out[i] = entry.data;
+            } else {
+                miss_indices.push(i);
+                miss_games.push(game.clone());
+            }
+        }
+
+        if !miss_games.is_empty() {
+            let miss_results = self.policy.eval_batch(&miss_games);
+            for ((i, game), pi_v) in miss_indices.into_iter().zip(miss_games).zip(miss_results) {
+                let (key, lock) = game.feature_key();
+                self.table.store_key(key, lock, 0, pi_v);
+                out[i] = pi_v;
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PolicyWithCache, PolicyWithTable};
+    use crate::cchess::{repeated_start, CChess, MAX_NUM_ACTIONS};
+    use crate::pos::tt::TranspositionTable;
+    use crate::synthesis::policies::Policy;
+    use rstest::rstest;
+
+    /// 每次评估返回不同的价值, 便于看出是否命中缓存
+    struct Counter(usize);
+
+    impl Policy<CChess, MAX_NUM_ACTIONS> for Counter {
+        fn eval(&mut self, _game: &CChess) -> ([f32; MAX_NUM_ACTIONS], [f32; 3]) {
+            self.0 += 1;
+            ([0.0; MAX_NUM_ACTIONS], [self.0 as f32, 0.0, 0.0])
+        }
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn test_cache_keys_follow_encoder(#[case] repetitions: bool) {
+        let (start, repeated) = repeated_start(repetitions);
+
+        let mut counter = Counter(0);
+        let mut cache = PolicyWithCache::with_capacity(4, &mut counter);
+        let first = cache.eval(&start).1;
+        assert_eq!(cache.eval(&repeated).1 != first, repetitions);
+
+        let table = TranspositionTable::new(1);
+        let mut counter = Counter(0);
+        let mut cached = PolicyWithTable::new(&mut counter, &table);
+        let first = cached.eval(&start).1;
+        assert_eq!(cached.eval_batch(&[repeated])[0].1 != first, repetitions);
+    }
+}
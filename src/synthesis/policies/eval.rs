@@ -0,0 +1,94 @@
+use crate::cchess::{CChess, MAX_NUM_ACTIONS};
+use crate::synthesis::game::Game;
+use crate::synthesis::policies::Policy;
+
+/// 不用神经网络的策略: 用`Position::evaluate`估计胜和负的概率, 吃子着法按SEE给更高的先验.
+/// 没有训练好的模型时可以用它搜索、自对弈起步, 或者检查MCTS
+#[derive(Debug, Clone, Copy)]
+pub struct EvalPolicy {
+    /// 评分换算成期望得分的尺度(以兵为100), 领先这么多分时期望得分约为0.73
+    pub scale: f32,
+    /// 均势时和棋的概率, 优势越大和棋越少
+    pub draw_rate: f32,
+    /// 不丢子的吃子着法的先验(logit), SEE每多得一个兵再加这么多
+    pub capture_bias: f32,
+}
+
+impl Default for EvalPolicy {
+    fn default() -> Self {
+        Self { scale: 400.0, draw_rate: 0.4, capture_bias: 1.0 }
+    }
+}
+
+impl EvalPolicy {
+    /// 把走棋方的评分换算成[负, 和, 胜]的概率
+    pub fn wdl(&self, score: i32) -> [f32; 3] {
+        let expected = 1.0 / (1.0 + (-score as f32 / self.scale).exp());
+        let draw = self.draw_rate * (1.0 - (2.0 * expected - 1.0).abs());
+        [1.0 - expected - draw / 2.0, draw, expected - draw / 2.0]
+    }
+}
+
+impl Policy<CChess, MAX_NUM_ACTIONS> for EvalPolicy {
+    fn eval(&mut self, game: &CChess) -> ([f32; MAX_NUM_ACTIONS], [f32; 3]) {
+        let position = game.position();
+        let mut logits = [0.0; MAX_NUM_ACTIONS];
+        for mv in position.legal_moves() {
+            if mv.captured().is_some() {
+                let see = position.see(&mv);
+                if see >= 0 {
+                    // SEE以兵为10
+                    logits[usize::from(mv)] = self.capture_bias * (1.0 + see as f32 / 10.0);
+                }
+            }
+        }
+
+        let outcome_probs = if game.is_over() {
+            let r = game.reward(game.player());
+            if r == 0.0 {
+                [0.0, 1.0, 0.0]
+            } else if r < 0.0 {
+                [1.0, 0.0, 0.0]
+            } else {
+                [0.0, 0.0, 1.0]
+            }
+        } else {
+            self.wdl(position.evaluate())
+        };
+        (logits, outcome_probs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EvalPolicy;
+    use crate::cchess::CChess;
+    use crate::fen::Fen;
+    use crate::moves::Move;
+    use crate::synthesis::policies::Policy;
+
+    #[test]
+    fn test_wdl() {
+        let policy = EvalPolicy::default();
+        for score in [-2000, -400, 0, 150, 900] {
+            let wdl = policy.wdl(score);
+            assert!(wdl.iter().all(|&p| p >= 0.0));
+            assert!((wdl.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        }
+        let even = policy.wdl(0);
+        assert!((even[0] - even[2]).abs() < 1e-6 && (even[1] - policy.draw_rate).abs() < 1e-6);
+        assert!(policy.wdl(400)[2] > policy.wdl(100)[2]);
+    }
+
+    #[test]
+    fn test_capture_priors() {
+        // 车吃无根卒得一个兵, 吃隔着马有炮保护的卒要丢车
+        let game = CChess::from_fen(Fen::new("3k5/4c4/4n4/9/4p4/9/p8/9/R3R4/4K4 w")).unwrap();
+        let (logits, wdl) = EvalPolicy::default().eval(&game);
+        let logit = |iccs: &str| logits[usize::from(Move::from_iccs(game.position(), iccs).unwrap())];
+        assert_eq!(logit("a1a3"), 2.0);
+        assert_eq!(logit("e1e5"), 0.0);
+        assert_eq!(logit("a1a2"), 0.0);
+        assert!(wdl[2] > wdl[0]);
+    }
+}
@@ -1,7 +1,9 @@
 mod cache;
+mod eval;
 mod rollout;
 mod traits;
 
-pub use cache::{OwnedPolicyWithCache, PolicyWithCache};
+pub use cache::{OwnedPolicyWithCache, PolicyWithCache, PolicyWithTable};
+pub use eval::EvalPolicy;
 pub use rollout::RolloutPolicy;
 pub use traits::{NNPolicy, Policy};